@@ -0,0 +1,1051 @@
+use arboard::ImageData;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use image::ImageFormat;
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use zip::result::ZipError;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::backend::blobs;
+use crate::backend::clipboard::{db_conn, ContentTypes, FileEntry, Format, TIMESTAMP_FORMAT};
+use crate::backend::migrations;
+use crate::backend::storage;
+use crate::backend::utils::{hash_image, hash_text, is_valid_hash};
+
+/// The version of the archive layout, bumped whenever an older app couldn't read a newer archive.
+const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const ITEMS_ENTRY: &str = "items.ndjson";
+const IMAGES_DIR: &str = "images";
+const REPRESENTATIONS_DIR: &str = "representations";
+const ICONS_DIR: &str = "icons";
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// The archive was written by a newer version of the app.
+    TooNew {
+        found: u32,
+        supported: u32,
+    },
+    Io(io::Error),
+    Zip(ZipError),
+    Json(serde_json::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::TooNew { found, supported } => write!(
+                f,
+                "archive version {} is newer than the latest supported version {}",
+                found, supported
+            ),
+            ArchiveError::Io(err) => write!(f, "{}", err),
+            ArchiveError::Zip(err) => write!(f, "{}", err),
+            ArchiveError::Json(err) => write!(f, "invalid archive: {}", err),
+            ArchiveError::Sqlite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(err: io::Error) -> Self {
+        ArchiveError::Io(err)
+    }
+}
+
+impl From<ZipError> for ArchiveError {
+    fn from(err: ZipError) -> Self {
+        ArchiveError::Zip(err)
+    }
+}
+
+impl From<serde_json::Error> for ArchiveError {
+    fn from(err: serde_json::Error) -> Self {
+        ArchiveError::Json(err)
+    }
+}
+
+impl From<rusqlite::Error> for ArchiveError {
+    fn from(err: rusqlite::Error) -> Self {
+        ArchiveError::Sqlite(err)
+    }
+}
+
+/// The `manifest.json` of an archive.
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    schema_version: i64, // The `PRAGMA user_version` of the exported database, for troubleshooting
+    exported_at: DateTime<Utc>,
+    pinboards: Vec<String>, // Every pinboard, in the order of their tabs
+}
+
+/// A line of `items.ndjson`: a history item with everything attached to it.
+///
+/// The files it refers to are stored next to it in the archive:
+/// - `images/<hash>.png`: the image of an `IMAGE` item, or of a `png` representation.
+/// - `representations/<hash>.<format>`: the data of each of `formats`.
+/// - `icons/<icon>`: the icon of the source app.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ArchivedItem {
+    content_type: ContentTypes,
+    content_hash: String,
+    content: String, // The text, or one file path per line. Empty for images
+    source_app: String,
+    icon: Option<String>, // The file name of the icon, if it is in the archive
+    timestamp: DateTime<Utc>,
+    size: i64,
+    redacted: bool,
+    masked: bool,
+    expires_at: Option<DateTime<Utc>>,
+    note: String,
+    tags: Vec<String>,
+    pins: Vec<ArchivedPin>,
+    formats: Vec<Format>,
+    files: Vec<FileEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ArchivedPin {
+    pinboard: String,
+    position: i64,
+}
+
+/// What `import` did with the items of an archive.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ImportSummary {
+    pub added: usize,   // Items that weren't in the history yet
+    pub merged: usize, // Items that were, whose tags, pins, etc. were merged into the existing ones
+    pub skipped: usize, // Images missing from the archive, and items that don't match their hash
+}
+
+/// Write the whole clipboard history to a zip archive, to back it up or move it to another machine.
+///
+/// Returns the number of exported items.
+///
+/// # Arguments
+///
+/// * `path` - The archive to write, replaced if it exists.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::archive;
+///
+/// let count = archive::export(std::path::Path::new("history.zip")).unwrap();
+/// println!("{}", count); // Output: 1234
+/// ```
+pub fn export(path: &Path) -> Result<usize, ArchiveError> {
+    let conn = db_conn()?;
+
+    // Write to a temporary file first, so a failed export never leaves a truncated archive behind
+    let tmp_path = path.with_extension("tmp");
+    let count = export_to(&conn, BufWriter::new(File::create(&tmp_path)?));
+
+    match count {
+        Ok(count) => {
+            fs::rename(&tmp_path, path)?;
+            Ok(count)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(err)
+        }
+    }
+}
+
+/// Merge an archive written by `export` into the clipboard history.
+///
+/// Items are matched by content hash: an item that is already in the history isn't duplicated,
+/// instead it gains the tags and pins of the archived one, and keeps the later of the two timestamps.
+/// New items keep the timestamp they had when they were exported. Pinboards are matched by name.
+///
+/// # Arguments
+///
+/// * `path` - The archive to read.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::archive;
+///
+/// let summary = archive::import(std::path::Path::new("history.zip")).unwrap();
+/// println!("{:?}", summary); // Output: ImportSummary { added: 1200, merged: 34, skipped: 0 }
+/// ```
+pub fn import(path: &Path) -> Result<ImportSummary, ArchiveError> {
+    let conn = db_conn()?;
+
+    import_from(&conn, BufReader::new(File::open(path)?))
+}
+
+/// Write every item of `conn` to a zip archive, see `export`.
+fn export_to<W: Write + Seek>(conn: &Connection, writer: W) -> Result<usize, ArchiveError> {
+    let mut zip = ZipWriter::new(writer);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // PNGs are compressed already
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let manifest = Manifest {
+        version: ARCHIVE_VERSION,
+        schema_version: migrations::schema_version(conn)?,
+        exported_at: Utc::now(),
+        pinboards: conn
+            .prepare("SELECT name FROM pinboards ORDER BY position, id")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?,
+    };
+    zip.start_file(MANIFEST_ENTRY, deflated)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+
+    let items = archived_items(conn)?;
+    let mut written = HashSet::new();
+
+    zip.start_file(ITEMS_ENTRY, deflated)?;
+    for (_, item) in &items {
+        serde_json::to_writer(&mut zip, item)?;
+        zip.write_all(b"\n")?;
+    }
+
+    for (id, item) in &items {
+        let mut images = Vec::new();
+        if item.content_type == ContentTypes::Image {
+            images.push(item.content_hash.clone());
+        }
+
+        for (format, data) in representations(conn, *id)? {
+            if format == Format::Png {
+                images.push(String::from_utf8_lossy(&data).to_string());
+            }
+
+            zip.start_file(representation_entry(item, &format), deflated)?;
+            zip.write_all(&data)?;
+        }
+
+        for content_hash in images {
+            let name = format!("{}/{}.png", IMAGES_DIR, content_hash);
+
+            // The same image may be shared by several items
+            if written.insert(name.clone()) {
+                match blobs::read_png(&content_hash) {
+                    Ok(png) => {
+                        zip.start_file(name, stored)?;
+                        zip.write_all(&png)?;
+                    }
+                    Err(err) => log::warn!("Not exporting missing image {}: {}", content_hash, err),
+                }
+            }
+        }
+
+        if let Some(icon) = &item.icon {
+            let name = format!("{}/{}", ICONS_DIR, icon);
+
+            if written.insert(name.clone()) {
+                if let Ok(data) = blobs::read_file(&storage::icon_cache_dir().join(icon)) {
+                    zip.start_file(name, stored)?;
+                    zip.write_all(&data)?;
+                }
+            }
+        }
+    }
+
+    zip.finish()?;
+
+    Ok(items.len())
+}
+
+/// Merge the items of a zip archive into `conn`, see `import`.
+///
+/// Nothing is imported if it fails: the items are inserted in a single transaction, and the images
+/// and icons extracted so far are deleted again.
+fn import_from<R: Read + Seek>(
+    conn: &Connection,
+    reader: R,
+) -> Result<ImportSummary, ArchiveError> {
+    let mut extracted = Vec::new();
+    let summary = import_items(conn, reader, &mut extracted);
+
+    if summary.is_err() {
+        for path in extracted {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    summary
+}
+
+/// Does the work of `import_from`, adding the files it writes to `extracted`.
+fn import_items<R: Read + Seek>(
+    conn: &Connection,
+    reader: R,
+    extracted: &mut Vec<PathBuf>,
+) -> Result<ImportSummary, ArchiveError> {
+    let mut zip = ZipArchive::new(reader)?;
+
+    let manifest: Manifest = serde_json::from_reader(zip.by_name(MANIFEST_ENTRY)?)?;
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(ArchiveError::TooNew {
+            found: manifest.version,
+            supported: ARCHIVE_VERSION,
+        });
+    }
+
+    let items = BufReader::new(zip.by_name(ITEMS_ENTRY)?)
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str::<ArchivedItem>(&line?)?))
+        .collect::<Result<Vec<_>, ArchiveError>>()?;
+
+    // Images are extracted under the write lock, see `blobs::collect_garbage`
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+    let mut summary = ImportSummary::default();
+
+    // Imported pins go after the items already on a pinboard, in their archived order
+    let mut pinboards = HashMap::new();
+    for name in &manifest.pinboards {
+        let existing_id: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM pinboards WHERE name = ?1 ORDER BY position, id LIMIT 1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let id = match existing_id {
+            Some(id) => id,
+            None => tx.query_row(
+                "INSERT INTO pinboards (name, position)
+                 VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM pinboards))
+                 RETURNING id",
+                params![name],
+                |row| row.get(0),
+            )?,
+        };
+        let first_position: i64 = tx.query_row(
+            "SELECT COALESCE(MAX(position) + 1, 0) FROM pinboard_items WHERE pinboard_id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+
+        pinboards.insert(name.clone(), (id, first_position));
+    }
+
+    for item in &items {
+        // The hash names files and archive entries, so it has to be the one of the content
+        let is_valid = match item.content_type {
+            ContentTypes::Text | ContentTypes::Files => {
+                is_valid_hash(&item.content_hash) && hash_text(&item.content) == item.content_hash
+            }
+            // Images first, an image item without its image would be useless
+            ContentTypes::Image => extract_image(&mut zip, &item.content_hash, extracted)?,
+        };
+
+        if !is_valid {
+            log::warn!(
+                "Not importing item {:?}, it doesn't match its hash or its image is missing from the archive",
+                item.content_hash
+            );
+            summary.skipped += 1;
+            continue;
+        }
+
+        let icon_path = match &item.icon {
+            Some(icon) => extract_icon(&mut zip, icon, extracted)?,
+            None => String::new(),
+        };
+
+        let existing_id: Option<i64> = tx
+            .query_row(
+                "SELECT id FROM history WHERE content_type = ?1 AND content_hash = ?2",
+                params![item.content_type.as_str(), item.content_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        // Like copying it again, importing an item in the trash brings it back
+        let id = match existing_id {
+            Some(id) => {
+                tx.execute(
+                    "UPDATE history
+                     SET timestamp = MAX(timestamp, ?2), note = CASE WHEN note = '' THEN ?3 ELSE note END, deleted_at = NULL
+                     WHERE id = ?1",
+                    params![id, format_timestamp(&item.timestamp), item.note],
+                )?;
+                summary.merged += 1;
+                id
+            }
+            None => {
+                let id = tx.query_row(
+                    "INSERT INTO history (source_app, icon_path, content_type, content, content_hash, timestamp, size, redacted, masked, expires_at, note)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                     RETURNING id",
+                    params![
+                        item.source_app,
+                        icon_path,
+                        item.content_type.as_str(),
+                        item.content,
+                        item.content_hash,
+                        format_timestamp(&item.timestamp),
+                        item.size,
+                        item.redacted,
+                        item.masked,
+                        item.expires_at.as_ref().map(format_timestamp),
+                        item.note,
+                    ],
+                    |row| row.get::<_, i64>(0),
+                )?;
+                summary.added += 1;
+                id
+            }
+        };
+
+        for format in &item.formats {
+            let mut data = Vec::new();
+            zip.by_name(&representation_entry(item, format))?
+                .read_to_end(&mut data)?;
+
+            if *format == Format::Png
+                && !extract_image(&mut zip, &String::from_utf8_lossy(&data), extracted)?
+            {
+                continue;
+            }
+
+            tx.execute(
+                "INSERT OR IGNORE INTO representations (item_id, format, data) VALUES (?1, ?2, ?3)",
+                params![id, format.mime(), data],
+            )?;
+        }
+
+        for (position, file) in item.files.iter().enumerate() {
+            tx.execute(
+                "INSERT OR IGNORE INTO item_files (item_id, position, path, is_dir, size, modified)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id,
+                    position as i64,
+                    file.path.to_string_lossy(),
+                    file.is_dir,
+                    file.size as i64,
+                    file.modified.as_ref().map(format_timestamp),
+                ],
+            )?;
+        }
+
+        for tag in &item.tags {
+            tx.execute(
+                "INSERT INTO tags (name) VALUES (?1) ON CONFLICT (name) DO NOTHING",
+                params![tag],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO item_tags (item_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+                params![id, tag],
+            )?;
+        }
+
+        for pin in &item.pins {
+            let Some((pinboard_id, first_position)) = pinboards.get(&pin.pinboard) else {
+                continue;
+            };
+
+            tx.execute(
+                "INSERT OR IGNORE INTO pinboard_items (pinboard_id, item_id, position) VALUES (?1, ?2, ?3)",
+                params![pinboard_id, id, first_position + pin.position],
+            )?;
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(summary)
+}
+
+/// Read every item of the history but the trashed ones, oldest first, along with its id.
+fn archived_items(conn: &Connection) -> rusqlite::Result<Vec<(i64, ArchivedItem)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, content_type, content_hash, CAST(content AS TEXT), source_app, icon_path, timestamp,
+                size, redacted, masked, expires_at, note
+         FROM history
+         WHERE deleted_at IS NULL
+         ORDER BY id",
+    )?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            let content_type: String = row.get(1)?;
+            let content_type = match content_type.as_str() {
+                "IMAGE" => ContentTypes::Image,
+                "TEXT" => ContentTypes::Text,
+                "FILES" => ContentTypes::Files,
+                _ => unreachable!(),
+            };
+            let icon_path: String = row.get(5)?;
+            let timestamp: String = row.get(6)?;
+            let expires_at: Option<String> = row.get(10)?;
+
+            let item = ArchivedItem {
+                content: match content_type {
+                    ContentTypes::Image => String::new(),
+                    ContentTypes::Text | ContentTypes::Files => {
+                        row.get::<_, Option<String>>(3)?.unwrap_or_default()
+                    }
+                },
+                content_type,
+                content_hash: row.get(2)?,
+                source_app: row.get(4)?,
+                icon: Path::new(&icon_path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string()),
+                timestamp: parse_timestamp(&timestamp).unwrap_or_else(Utc::now),
+                size: row.get(7)?,
+                redacted: row.get(8)?,
+                masked: row.get(9)?,
+                expires_at: expires_at.as_deref().and_then(parse_timestamp),
+                note: row.get(11)?,
+                tags: Vec::new(),
+                pins: Vec::new(),
+                formats: Vec::new(),
+                files: Vec::new(),
+            };
+
+            Ok((row.get::<_, i64>(0)?, item))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut tags_stmt = conn.prepare(
+        "SELECT tags.name FROM item_tags JOIN tags ON tags.id = item_tags.tag_id
+         WHERE item_tags.item_id = ?1
+         ORDER BY tags.name",
+    )?;
+    let mut pins_stmt = conn.prepare(
+        "SELECT pinboards.name, pinboard_items.position
+         FROM pinboard_items JOIN pinboards ON pinboards.id = pinboard_items.pinboard_id
+         WHERE pinboard_items.item_id = ?1
+         ORDER BY pinboards.position, pinboards.id",
+    )?;
+    let mut files_stmt = conn.prepare(
+        "SELECT path, is_dir, size, modified FROM item_files WHERE item_id = ?1 ORDER BY position",
+    )?;
+
+    rows.into_iter()
+        .map(|(id, mut item)| {
+            item.tags = tags_stmt
+                .query_map(params![id], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            item.pins = pins_stmt
+                .query_map(params![id], |row| {
+                    Ok(ArchivedPin {
+                        pinboard: row.get(0)?,
+                        position: row.get(1)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            item.formats = representations(conn, id)?
+                .into_iter()
+                .map(|(format, _)| format)
+                .collect();
+            item.files = files_stmt
+                .query_map(params![id], |row| {
+                    let path: String = row.get(0)?;
+                    let modified: Option<String> = row.get(3)?;
+
+                    Ok(FileEntry {
+                        path: PathBuf::from(path),
+                        is_dir: row.get(1)?,
+                        size: row.get::<_, i64>(2)? as u64,
+                        modified: modified.as_deref().and_then(parse_timestamp),
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok((id, item))
+        })
+        .collect()
+}
+
+/// Read the extra representations of an item, ordered by format.
+fn representations(conn: &Connection, id: i64) -> rusqlite::Result<Vec<(Format, Vec<u8>)>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT format, CAST(data AS BLOB) FROM representations WHERE item_id = ?1 ORDER BY format",
+    )?;
+
+    let rows = stmt.query_map(params![id], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+    })?;
+
+    rows.filter_map(|row| {
+        row.map(|(mime, data)| Format::from_mime(&mime).map(|format| (format, data)))
+            .transpose()
+    })
+    .collect()
+}
+
+/// The name of the archive entry holding a representation of an item.
+fn representation_entry(item: &ArchivedItem, format: &Format) -> String {
+    format!(
+        "{}/{}.{}",
+        REPRESENTATIONS_DIR,
+        item.content_hash,
+        format.label().to_lowercase()
+    )
+}
+
+/// Copy an image from the archive to the blob store, returning `false` if the archive doesn't have it,
+/// or if it isn't the image `content_hash` is the hash of.
+///
+/// The files written to the blob store are added to `extracted`.
+fn extract_image<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    content_hash: &str,
+    extracted: &mut Vec<PathBuf>,
+) -> Result<bool, ArchiveError> {
+    if !is_valid_hash(content_hash) {
+        return Ok(false);
+    }
+
+    if blobs::image_path(content_hash).exists() {
+        return Ok(true);
+    }
+
+    let mut png = Vec::new();
+    match zip.by_name(&format!("{}/{}.png", IMAGES_DIR, content_hash)) {
+        Ok(mut file) => file.read_to_end(&mut png)?,
+        Err(ZipError::FileNotFound) => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+
+    let Ok(image) = image::load_from_memory_with_format(&png, ImageFormat::Png) else {
+        return Ok(false);
+    };
+    let image = image.to_rgba8();
+    let image_hash = hash_image(&ImageData {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        bytes: Cow::Borrowed(image.as_raw()),
+    });
+    if image_hash != content_hash {
+        return Ok(false);
+    }
+
+    extracted.push(blobs::image_path(content_hash));
+    extracted.push(blobs::thumbnail_path(content_hash));
+    blobs::store_png(content_hash, &png)
+        .map_err(|err| ArchiveError::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+
+    Ok(true)
+}
+
+/// Copy an app icon from the archive to the icon cache, returning its path (or an empty one if it's missing).
+fn extract_icon<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    icon: &str,
+    extracted: &mut Vec<PathBuf>,
+) -> Result<String, ArchiveError> {
+    // Only ever write inside the icon cache, whatever the archive says
+    let Some(name) = Path::new(icon).file_name() else {
+        return Ok(String::new());
+    };
+    let path = storage::icon_cache_dir().join(name);
+
+    if !path.exists() {
+        let mut data = Vec::new();
+        match zip.by_name(&format!("{}/{}", ICONS_DIR, icon)) {
+            Ok(mut file) => file.read_to_end(&mut data)?,
+            Err(ZipError::FileNotFound) => return Ok(String::new()),
+            Err(err) => return Err(err.into()),
+        };
+        extracted.push(path.clone());
+        blobs::write_file(&path, &data)?;
+    }
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format(TIMESTAMP_FORMAT).to_string()
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|naive| Utc.from_utc_datetime(&naive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A new database. Its blob store and icon cache are in the data directory of the tests, see `storage::data_dir`.
+    fn open_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    /// A history with one item of each type, and everything that can be attached to them.
+    fn populated_db() -> Connection {
+        let conn = open_db();
+
+        let icon_path = storage::icon_cache_dir().join("Code.png");
+        fs::write(&icon_path, b"icon").unwrap();
+
+        let image = ImageData {
+            width: 2,
+            height: 1,
+            bytes: Cow::Owned(vec![255, 0, 0, 255, 0, 0, 255, 255]),
+        };
+        let image_hash = hash_image(&image);
+        blobs::store_image(&image_hash, &image).unwrap();
+
+        conn.execute_batch(&format!(
+            "INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash, timestamp, size, masked, note)
+             VALUES (1, 'Code', '{icon}', 'TEXT', 'fn main() {{}}', '{text_hash}', '2025-12-27 17:28:01', 12, 1, 'entry point');
+             INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash, timestamp, size, redacted, expires_at)
+             VALUES (2, 'Finder', '', 'FILES', '/tmp/a.txt\n/tmp/b', '{files_hash}', '2025-12-28 09:00:00', 13, 1, '2030-01-01 00:00:00');
+             INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash, timestamp, size)
+             VALUES (3, 'Preview', '', 'IMAGE', x'', '{image_hash}', '2025-12-29 10:30:00', 70);
+
+             INSERT INTO representations (item_id, format, data) VALUES (1, 'text/html', x'3c623e66e280a63c2f623e');
+             INSERT INTO representations (item_id, format, data) VALUES (1, 'image/png', '{image_hash}');
+             INSERT INTO item_files (item_id, position, path, is_dir, size, modified)
+             VALUES (2, 0, '/tmp/a.txt', 0, 42, '2025-12-01 08:00:00'), (2, 1, '/tmp/b', 1, 0, NULL);
+
+             INSERT INTO tags (id, name) VALUES (1, 'rust'), (2, 'work');
+             INSERT INTO item_tags (item_id, tag_id) VALUES (1, 1), (1, 2), (3, 2);
+
+             INSERT INTO pinboards (id, name, position) VALUES (1, 'Pinned', 0), (2, 'Empty', 1), (3, 'Snippets', 2);
+             INSERT INTO pinboard_items (pinboard_id, item_id, position) VALUES (1, 3, 0), (1, 1, 1), (3, 1, 0);",
+            icon = icon_path.display(),
+            text_hash = hash_text("fn main() {}"),
+            files_hash = hash_text("/tmp/a.txt\n/tmp/b"),
+        ))
+        .unwrap();
+
+        conn
+    }
+
+    fn export_to_memory(conn: &Connection) -> Vec<u8> {
+        let mut archive = Cursor::new(Vec::new());
+        export_to(conn, &mut archive).unwrap();
+        archive.into_inner()
+    }
+
+    /// The pinboards, and the items with the data of their representations.
+    type Snapshot = (Vec<String>, Vec<(ArchivedItem, Vec<(Format, Vec<u8>)>)>);
+
+    /// Everything about the history that an archive should preserve, independent of the item ids.
+    fn snapshot(conn: &Connection) -> Snapshot {
+        let pinboards = conn
+            .prepare("SELECT name FROM pinboards ORDER BY position, id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+
+        let mut items = archived_items(conn)
+            .unwrap()
+            .into_iter()
+            .map(|(id, item)| (item, representations(conn, id).unwrap()))
+            .collect::<Vec<_>>();
+        items.sort_by(|(a, _), (b, _)| a.content_hash.cmp(&b.content_hash));
+
+        (pinboards, items)
+    }
+
+    #[test]
+    fn export_then_import_is_lossless() {
+        let source = populated_db();
+        let archive = export_to_memory(&source);
+
+        let target = open_db();
+        let summary = import_from(&target, Cursor::new(archive)).unwrap();
+
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 3,
+                merged: 0,
+                skipped: 0
+            }
+        );
+        assert_eq!(snapshot(&target), snapshot(&source));
+
+        // The derived columns and the search index are rebuilt by the triggers
+        let pinned: Vec<bool> = target
+            .prepare("SELECT pinned FROM history ORDER BY timestamp")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(pinned, [true, false, true]);
+
+        let matches: i64 = target
+            .query_row(
+                "SELECT COUNT(*) FROM history_fts WHERE history_fts MATCH 'tags:work AND note:entry'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(matches, 1);
+    }
+
+    #[test]
+    fn importing_an_archive_twice_changes_nothing() {
+        let source = populated_db();
+        let archive = export_to_memory(&source);
+
+        let target = open_db();
+        import_from(&target, Cursor::new(archive.clone())).unwrap();
+        let first_import = snapshot(&target);
+        let summary = import_from(&target, Cursor::new(archive)).unwrap();
+
+        assert_eq!(summary.added, 0);
+        assert_eq!(summary.merged, 3);
+        assert_eq!(snapshot(&target), first_import);
+    }
+
+    #[test]
+    fn import_merges_into_existing_items() {
+        let archive = export_to_memory(&populated_db());
+
+        let target = open_db();
+        target
+            .execute_batch(&format!(
+                "INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash, timestamp)
+                 VALUES (7, 'Terminal', '', 'TEXT', 'fn main() {{}}', '{}', '2025-01-01 00:00:00');
+                 INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash, timestamp)
+                 VALUES (8, 'Terminal', '', 'TEXT', 'ls -la', '{}', '2026-01-01 00:00:00');
+                 INSERT INTO tags (id, name) VALUES (1, 'shell');
+                 INSERT INTO item_tags (item_id, tag_id) VALUES (7, 1);
+                 INSERT INTO pinboards (id, name, position) VALUES (1, 'Pinned', 0);
+                 INSERT INTO pinboard_items (pinboard_id, item_id, position) VALUES (1, 8, 0);",
+                hash_text("fn main() {}"),
+                hash_text("ls -la"),
+            ))
+            .unwrap();
+
+        let summary = import_from(&target, Cursor::new(archive)).unwrap();
+        assert_eq!(summary.added, 2);
+        assert_eq!(summary.merged, 1);
+
+        let (timestamp, tags, note): (String, String, String) = target
+            .query_row(
+                "SELECT timestamp, (SELECT GROUP_CONCAT(name, ' ') FROM (SELECT name FROM item_tags JOIN tags ON tags.id = tag_id WHERE item_id = 7 ORDER BY name)), note
+                 FROM history WHERE id = 7",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(timestamp, "2025-12-27 17:28:01");
+        assert_eq!(tags, "rust shell work");
+        assert_eq!(note, "entry point");
+
+        // Imported pins go after the ones already on the pinboard, in their archived order
+        let pinned_ids: Vec<i64> = target
+            .prepare("SELECT item_id FROM pinboard_items WHERE pinboard_id = 1 ORDER BY position")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(pinned_ids.len(), 3);
+        assert_eq!(pinned_ids[0], 8);
+        assert_eq!(pinned_ids[2], 7);
+    }
+
+    #[test]
+    fn import_brings_back_items_in_the_trash() {
+        let archive = export_to_memory(&populated_db());
+
+        let target = open_db();
+        target
+            .execute(
+                "INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash, timestamp, deleted_at)
+                 VALUES (7, 'Terminal', '', 'TEXT', 'fn main() {}', ?1, '2025-01-01 00:00:00', '2025-06-01 00:00:00')",
+                params![hash_text("fn main() {}")],
+            )
+            .unwrap();
+
+        let summary = import_from(&target, Cursor::new(archive)).unwrap();
+        assert_eq!(summary.merged, 1);
+
+        let deleted_at: Option<String> = target
+            .query_row("SELECT deleted_at FROM history WHERE id = 7", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(deleted_at, None);
+    }
+
+    /// An archive of `items`, with the given files next to them.
+    fn archive_of(items: &[ArchivedItem], files: &[(String, Vec<u8>)]) -> Vec<u8> {
+        let mut archive = Cursor::new(Vec::new());
+        let mut zip = ZipWriter::new(&mut archive);
+
+        zip.start_file(MANIFEST_ENTRY, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(
+            br#"{"version": 1, "schema_version": 15, "exported_at": "2030-01-01T00:00:00Z", "pinboards": []}"#,
+        )
+        .unwrap();
+
+        zip.start_file(ITEMS_ENTRY, SimpleFileOptions::default())
+            .unwrap();
+        for item in items {
+            writeln!(zip, "{}", serde_json::to_string(item).unwrap()).unwrap();
+        }
+
+        for (name, data) in files {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+
+        zip.finish().unwrap();
+        archive.into_inner()
+    }
+
+    fn archived_item(
+        content_type: ContentTypes,
+        content_hash: &str,
+        content: &str,
+    ) -> ArchivedItem {
+        ArchivedItem {
+            content_type,
+            content_hash: content_hash.to_string(),
+            content: content.to_string(),
+            source_app: "Code".to_string(),
+            icon: None,
+            timestamp: Utc::now(),
+            size: 0,
+            redacted: false,
+            masked: false,
+            expires_at: None,
+            note: String::new(),
+            tags: Vec::new(),
+            pins: Vec::new(),
+            formats: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn items_that_dont_match_their_hash_are_skipped() {
+        let mut png = Vec::new();
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([0, 255, 0, 255]))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let wrong_hash = hash_text("not the image");
+
+        let archive = archive_of(
+            &[
+                archived_item(ContentTypes::Image, "../../escaped", ""),
+                archived_item(ContentTypes::Image, &wrong_hash, ""),
+                archived_item(ContentTypes::Text, &"0".repeat(64), "hello"),
+                archived_item(
+                    ContentTypes::Text,
+                    &hash_text("hello").to_uppercase(),
+                    "hello",
+                ),
+                archived_item(ContentTypes::Text, &hash_text("hello"), "hello"),
+            ],
+            &[
+                (format!("{}/../../escaped.png", IMAGES_DIR), png.clone()),
+                (format!("{}/{}.png", IMAGES_DIR, wrong_hash), png),
+            ],
+        );
+
+        let target = open_db();
+        let summary = import_from(&target, Cursor::new(archive)).unwrap();
+
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 1,
+                merged: 0,
+                skipped: 4
+            }
+        );
+        assert!(!storage::blob_dir().join("../../escaped.png").exists());
+        assert!(!blobs::image_path(&wrong_hash).exists());
+    }
+
+    #[test]
+    fn failed_imports_leave_nothing_behind() {
+        let pixels = vec![0, 0, 255, 255];
+        let image_hash = hash_image(&ImageData {
+            width: 1,
+            height: 1,
+            bytes: Cow::Borrowed(&pixels),
+        });
+        let mut png = Vec::new();
+        image::RgbaImage::from_raw(1, 1, pixels)
+            .unwrap()
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        // The HTML representation of the second item is missing from the archive
+        let archive = archive_of(
+            &[
+                archived_item(ContentTypes::Image, &image_hash, ""),
+                ArchivedItem {
+                    formats: vec![Format::Html],
+                    ..archived_item(ContentTypes::Text, &hash_text("<b>"), "<b>")
+                },
+            ],
+            &[(format!("{}/{}.png", IMAGES_DIR, image_hash), png)],
+        );
+
+        let target = open_db();
+        let result = import_from(&target, Cursor::new(archive));
+
+        assert!(matches!(
+            result,
+            Err(ArchiveError::Zip(ZipError::FileNotFound))
+        ));
+        assert!(!blobs::image_path(&image_hash).exists());
+        assert!(!blobs::thumbnail_path(&image_hash).exists());
+        assert_eq!(
+            target
+                .query_row("SELECT COUNT(*) FROM history", [], |row| row
+                    .get::<_, i64>(0))
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn newer_archives_are_rejected() {
+        let mut archive = Cursor::new(Vec::new());
+        let mut zip = ZipWriter::new(&mut archive);
+        zip.start_file(MANIFEST_ENTRY, SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(
+            br#"{"version": 99, "schema_version": 42, "exported_at": "2030-01-01T00:00:00Z", "pinboards": []}"#,
+        )
+        .unwrap();
+        zip.finish().unwrap();
+
+        let result = import_from(&open_db(), Cursor::new(archive.into_inner()));
+
+        assert!(matches!(
+            result,
+            Err(ArchiveError::TooNew {
+                found: 99,
+                supported: ARCHIVE_VERSION
+            })
+        ));
+    }
+}
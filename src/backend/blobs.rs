@@ -0,0 +1,470 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use arboard::ImageData;
+use base64::prelude::*;
+use image::{ImageBuffer, ImageFormat, Rgba};
+use once_cell::sync::Lazy;
+use rusqlite::{Connection, Transaction, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::backend::storage;
+
+/// Maximum size of a thumbnail. Twice the size of a `ClipboardCard`, so they stay sharp on HiDPI screens.
+const THUMBNAIL_WIDTH: u32 = 480;
+const THUMBNAIL_HEIGHT: u32 = 360;
+
+/// How long a cached icon is kept before any item refers to it.
+/// Icons are cached when a copy is captured, just before the item is saved.
+const NEW_ICON_GRACE_PERIOD: Duration = Duration::from_secs(60);
+
+/// The first bytes of every encrypted file, followed by the nonce and the AES-256-GCM ciphertext.
+const ENCRYPTED_HEADER: &[u8; 8] = b"PFBLOB01";
+const NONCE_LEN: usize = 12;
+
+/// The custom protocol the UI loads thumbnails and app icons from, see `thumbnail_url`.
+pub const PROTOCOL: &str = "blobs";
+
+/// The cipher files are encrypted with, while an encrypted database is unlocked, see `set_key`.
+static CIPHER: Lazy<Mutex<Option<Aes256Gcm>>> = Lazy::new(|| Mutex::new(None));
+
+/// Sets the key files are encrypted with from now on, `None` to stop encrypting them.
+///
+/// With an encrypted database (see `encryption::open`), images, thumbnails and app icons are
+/// encrypted too, with a random key stored inside the database. Without the key (i.e. while the
+/// history is locked) they can't be read.
+pub(crate) fn set_key(key: Option<&[u8; 32]>) {
+    *CIPHER.lock().unwrap() = key.map(|key| Aes256Gcm::new(key.into()));
+}
+
+/// Writes a file of the blob store, encrypted if a key is set (see `set_key`).
+///
+/// The file is written to a temporary file first, so a crash never leaves a truncated one behind.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::{blobs, storage};
+///
+/// blobs::write_file(&storage::icon_cache_dir().join("Code.png"), &png).unwrap();
+/// ```
+pub fn write_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let data = match CIPHER.lock().unwrap().as_ref() {
+        Some(cipher) => Cow::Owned(seal(cipher, data)?),
+        None => Cow::Borrowed(data),
+    };
+
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, data)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Reads a file of the blob store, decrypting it if needed.
+///
+/// Fails with `io::ErrorKind::PermissionDenied` if the file is encrypted and no key is set.
+pub fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+
+    if !data.starts_with(ENCRYPTED_HEADER) {
+        return Ok(data);
+    }
+
+    match CIPHER.lock().unwrap().as_ref() {
+        Some(cipher) => unseal(cipher, &data),
+        None => Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the history is locked",
+        )),
+    }
+}
+
+/// A file of the blob store, as served to the UI (see `PROTOCOL`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DisplayFile {
+    pub mime: String,
+    pub data: String, // Base64, so it can be sent as JSON
+}
+
+impl DisplayFile {
+    /// Reads a thumbnail or an app icon, decrypting it if needed, see `read_file`.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mime = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("svg") => "image/svg+xml",
+            _ => "image/png",
+        };
+
+        Ok(DisplayFile {
+            mime: mime.to_string(),
+            data: BASE64_STANDARD.encode(read_file(path)?),
+        })
+    }
+
+    /// The content of the file, empty if `data` isn't valid base64.
+    pub fn bytes(&self) -> Vec<u8> {
+        BASE64_STANDARD.decode(&self.data).unwrap_or_default()
+    }
+}
+
+/// Returns the URL the UI loads the thumbnail of an image item from.
+///
+/// The web view can't read encrypted files, so it never loads them from their path: the UI serves
+/// `PROTOCOL` URLs with the files the daemon decrypts on demand (see `daemon::dispatch`).
+/// The URL only names the hash, the items listed to the UI don't carry any image.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::blobs;
+///
+/// println!("{}", blobs::thumbnail_url(&item.content_hash)); // Output: blobs://localhost/thumbnail/9f86d08...
+/// ```
+pub fn thumbnail_url(content_hash: &str) -> String {
+    format!("{}://localhost/thumbnail/{}", PROTOCOL, content_hash)
+}
+
+/// Returns the URL the UI loads the icon of the app an item was copied from, see `thumbnail_url`.
+pub fn icon_url(item_id: i64) -> String {
+    format!("{}://localhost/icon/{}", PROTOCOL, item_id)
+}
+
+/// Encrypts the images, thumbnails and app icons that are still in plain text, e.g. because they
+/// were stored before encryption was turned on. Does nothing if no key is set.
+///
+/// Returns the number of encrypted files.
+pub(crate) fn encrypt_plaintext_files() -> usize {
+    if CIPHER.lock().unwrap().is_none() {
+        return 0;
+    }
+
+    let mut encrypted = 0;
+
+    for dir in [
+        storage::blob_dir(),
+        storage::thumbnail_dir(),
+        storage::icon_cache_dir(),
+    ] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().is_some_and(|ext| ext == "tmp") || !path.is_file() {
+                continue;
+            }
+
+            // `read_file` returns plain text files as they are
+            let result = fs::read(&path).and_then(|data| {
+                if data.starts_with(ENCRYPTED_HEADER) {
+                    return Ok(false);
+                }
+                write_file(&path, &data).map(|_| true)
+            });
+
+            match result {
+                Ok(true) => encrypted += 1,
+                Ok(false) => {}
+                Err(err) => log::error!("Failed to encrypt {}: {}", path.display(), err),
+            }
+        }
+    }
+
+    encrypted
+}
+
+/// Encrypts `data` with a random nonce, see `ENCRYPTED_HEADER`.
+fn seal(cipher: &Aes256Gcm, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::fill(&mut nonce).map_err(|err| io::Error::other(err.to_string()))?;
+
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), data)
+        .map_err(|_| io::Error::other("failed to encrypt"))?;
+
+    Ok([ENCRYPTED_HEADER.as_slice(), &nonce, &ciphertext].concat())
+}
+
+/// Decrypts what `seal` returned.
+fn unseal(cipher: &Aes256Gcm, data: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "corrupt or foreign encrypted file",
+        )
+    };
+
+    let (nonce, ciphertext) = data
+        .strip_prefix(ENCRYPTED_HEADER.as_slice())
+        .filter(|rest| rest.len() >= NONCE_LEN)
+        .ok_or_else(invalid)?
+        .split_at(NONCE_LEN);
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid())
+}
+
+/// Returns the path of the full-resolution PNG of an image item.
+///
+/// Images are content-addressed: the file is named after the item's `content_hash`.
+pub fn image_path(content_hash: &str) -> PathBuf {
+    storage::blob_dir().join(format!("{}.png", content_hash))
+}
+
+/// Returns the path of the card-sized PNG preview of an image item.
+pub fn thumbnail_path(content_hash: &str) -> PathBuf {
+    storage::thumbnail_dir().join(format!("{}.png", content_hash))
+}
+
+/// Writes an image and its thumbnail to the blob store, unless they are already there.
+///
+/// Returns the size of the full-resolution PNG, in bytes.
+///
+/// # Arguments
+///
+/// * `content_hash` - The hash of the image, see `utils::hash_image`.
+/// * `content` - The raw image data captured from the system clipboard.
+pub fn store_image(content_hash: &str, content: &ImageData) -> image::ImageResult<u64> {
+    let path = image_path(content_hash);
+
+    if !path.exists() {
+        let img_buffer = ImageBuffer::<Rgba<u8>, _>::from_raw(
+            content.width as u32,
+            content.height as u32,
+            content.bytes.as_ref(),
+        )
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Image size mismatch"))?;
+
+        let mut png = Vec::new();
+        img_buffer.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        write_file(&path, &png)?;
+    }
+
+    store_thumbnail(content_hash)?;
+
+    Ok(read_png(content_hash)?.len() as u64)
+}
+
+/// Writes an already encoded PNG and its thumbnail to the blob store, unless they are already there.
+///
+/// Returns the size of the PNG, in bytes.
+///
+/// # Arguments
+///
+/// * `content_hash` - The hash of the image, see `utils::hash_image`.
+/// * `png` - The PNG file, e.g. read from an archive (see `archive::import`).
+pub fn store_png(content_hash: &str, png: &[u8]) -> image::ImageResult<u64> {
+    let path = image_path(content_hash);
+
+    if !path.exists() {
+        // Check that it is an image at all, so the blob store only ever contains valid ones
+        image::load_from_memory_with_format(png, ImageFormat::Png)?;
+
+        write_file(&path, png)?;
+    }
+
+    store_thumbnail(content_hash)?;
+
+    Ok(read_png(content_hash)?.len() as u64)
+}
+
+/// Writes the thumbnail of a stored image, unless it is already there.
+fn store_thumbnail(content_hash: &str) -> image::ImageResult<()> {
+    let thumbnail_path = thumbnail_path(content_hash);
+
+    if !thumbnail_path.exists() {
+        let mut thumbnail = Vec::new();
+        image::load_from_memory_with_format(&read_png(content_hash)?, ImageFormat::Png)?
+            .thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)
+            .write_to(&mut Cursor::new(&mut thumbnail), ImageFormat::Png)?;
+        write_file(&thumbnail_path, &thumbnail)?;
+    }
+
+    Ok(())
+}
+
+/// Reads the full-resolution PNG of an image item, decrypted.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::blobs;
+///
+/// std::fs::write("image.png", blobs::read_png(&item.content_hash).unwrap()).unwrap();
+/// ```
+pub fn read_png(content_hash: &str) -> io::Result<Vec<u8>> {
+    read_file(&image_path(content_hash))
+}
+
+/// Loads the full-resolution image of an image item, ready to be put on the system clipboard.
+///
+/// Returns `None` if the blob is missing or isn't a valid image.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::blobs;
+///
+/// let mut clipboard = arboard::Clipboard::new().unwrap();
+/// if let Some(image) = blobs::load_image(&item.content_hash) {
+///     clipboard.set_image(image).unwrap();
+/// }
+/// ```
+pub fn load_image(content_hash: &str) -> Option<ImageData<'static>> {
+    let rgba_image =
+        image::load_from_memory_with_format(&read_png(content_hash).ok()?, ImageFormat::Png)
+            .ok()?
+            .to_rgba8();
+    let (width, height) = rgba_image.dimensions();
+
+    Some(ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: Cow::Owned(rgba_image.into_raw()),
+    })
+}
+
+/// Deletes every blob and thumbnail that no image item or image representation refers to anymore,
+/// and the cached icons of apps no item was copied from anymore.
+///
+/// Items in the trash still count, so they can be restored with their images.
+/// Images are stored and referred to in a single `BEGIN IMMEDIATE` transaction (see `clipboard::save_image`),
+/// and the garbage is collected in one too: the write lock of SQLite is shared by every process
+/// using the database, so an image stored by the daemon is never deleted by the CLI before it is referred to.
+/// Must not be called inside a transaction.
+///
+/// Returns the number of deleted images.
+pub fn collect_garbage(conn: &Connection) -> rusqlite::Result<usize> {
+    let tx = Transaction::new_unchecked(conn, TransactionBehavior::Immediate)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT content_hash FROM history WHERE content_type = 'IMAGE'
+         UNION
+         SELECT CAST(data AS TEXT) FROM representations WHERE format = 'image/png'",
+    )?;
+    let referenced = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<HashSet<_>>>()?;
+
+    let mut deleted = 0;
+
+    for (dir, is_blob_dir) in [
+        (storage::blob_dir(), true),
+        (storage::thumbnail_dir(), false),
+    ] {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let is_referenced = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .is_some_and(|hash| referenced.contains(hash));
+
+            if !is_referenced && fs::remove_file(&path).is_ok() && is_blob_dir {
+                deleted += 1;
+            }
+        }
+    }
+
+    let icons = conn
+        .prepare("SELECT DISTINCT icon_path FROM history")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .map(|icon_path| icon_path.map(PathBuf::from))
+        .collect::<rusqlite::Result<HashSet<_>>>()?;
+
+    if let Ok(entries) = fs::read_dir(storage::icon_cache_dir()) {
+        for entry in entries.flatten() {
+            let is_new = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age < NEW_ICON_GRACE_PERIOD);
+
+            if !is_new && !icons.contains(&entry.path()) {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::migrations;
+    use crate::backend::utils::hash_image;
+
+    fn cipher(byte: u8) -> Aes256Gcm {
+        Aes256Gcm::new((&[byte; 32]).into())
+    }
+
+    #[test]
+    fn sealed_data_round_trips() {
+        let sealed = seal(&cipher(1), b"\x89PNG").unwrap();
+
+        assert!(sealed.starts_with(ENCRYPTED_HEADER));
+        assert!(!sealed.windows(4).any(|window| window == b"\x89PNG"));
+        assert_eq!(unseal(&cipher(1), &sealed).unwrap(), b"\x89PNG");
+
+        // Every file gets a nonce of its own
+        assert_ne!(seal(&cipher(1), b"\x89PNG").unwrap(), sealed);
+    }
+
+    #[test]
+    fn unsealing_rejects_other_keys_and_tampering() {
+        let mut sealed = seal(&cipher(1), b"\x89PNG").unwrap();
+
+        assert!(unseal(&cipher(2), &sealed).is_err());
+        assert!(unseal(&cipher(1), &sealed[..ENCRYPTED_HEADER.len() + 4]).is_err());
+
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(unseal(&cipher(1), &sealed).is_err());
+    }
+
+    #[test]
+    fn images_being_saved_are_not_collected() {
+        let path = storage::data_dir().join("garbage.db");
+        let _ = fs::remove_file(&path);
+
+        let mut saving = Connection::open(&path).unwrap();
+        migrations::migrate(&mut saving).unwrap();
+        // Another process, like the CLI emptying the trash while the daemon saves a copied image
+        let collecting = Connection::open(&path).unwrap();
+        collecting.busy_timeout(Duration::ZERO).unwrap();
+
+        let image = ImageData {
+            width: 1,
+            height: 1,
+            bytes: Cow::Borrowed(&[12, 34, 56, 255]),
+        };
+        let content_hash = hash_image(&image);
+
+        let tx = Transaction::new_unchecked(&saving, TransactionBehavior::Immediate).unwrap();
+        store_image(&content_hash, &image).unwrap();
+
+        assert!(collect_garbage(&collecting).is_err());
+        assert!(image_path(&content_hash).exists());
+
+        tx.execute(
+            "INSERT INTO history (source_app, icon_path, content_type, content, content_hash)
+             VALUES ('Code', '', 'IMAGE', x'', ?1)",
+            [&content_hash],
+        )
+        .unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(collect_garbage(&collecting).unwrap(), 0);
+        assert!(image_path(&content_hash).exists());
+    }
+}
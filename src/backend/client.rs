@@ -0,0 +1,334 @@
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::env::current_exe;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::backend::blobs::DisplayFile;
+use crate::backend::clipboard::{FileEntry, Item, Page, PageCursor, Pinboard};
+use crate::backend::daemon::{Request, Response, RpcError, DAEMON_FLAG};
+use crate::backend::merge::{CollageLayout, Separator};
+use crate::backend::snippets::{Rendered, Snippet};
+use crate::backend::storage;
+
+/// How long to wait for a daemon started by `ensure_daemon` to listen.
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long `call` waits for the daemon to answer before giving up on it.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait before reconnecting a subscription to a daemon that went away.
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Start a daemon in the background, unless one already serves the database.
+///
+/// The daemon is a copy of the current executable started with `--daemon` (and the same database),
+/// in its own process group so it keeps running when the `Paste` window exits or crashes.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::client;
+///
+/// client::ensure_daemon();
+/// println!("{:?}", client::is_locked()); // Output: Ok(false)
+/// ```
+pub fn ensure_daemon() {
+    let path = storage::socket_path();
+
+    if UnixStream::connect(&path).is_ok() {
+        return;
+    }
+
+    log::info!("Starting daemon");
+    let spawned = Command::new(current_exe().unwrap())
+        .arg(storage::DB_PATH_FLAG)
+        .arg(storage::db_path())
+        .arg(DAEMON_FLAG)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .process_group(0)
+        .spawn();
+
+    if let Err(err) = spawned {
+        log::error!("Failed to start daemon: {}", err);
+        return;
+    }
+
+    let started = Instant::now();
+    while UnixStream::connect(&path).is_err() && started.elapsed() < DAEMON_START_TIMEOUT {
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Call a JSON-RPC method of the daemon, see `daemon::dispatch`.
+///
+/// # Arguments
+///
+/// * `method` - The name of the method, e.g. `list`.
+/// * `params` - The named params of the method, as a JSON object.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::client;
+/// use crate::backend::clipboard::Item;
+///
+/// let items: Vec<Item> = client::call("list", serde_json::json!({ "limit": 10 })).unwrap();
+/// ```
+pub fn call<T: DeserializeOwned>(method: &str, params: Value) -> Result<T, RpcError> {
+    let unreachable = |err: std::io::Error| {
+        RpcError::new(
+            RpcError::UNREACHABLE,
+            format!("daemon unreachable: {}", err),
+        )
+    };
+
+    let mut stream = UnixStream::connect(storage::socket_path()).map_err(unreachable)?;
+    stream
+        .set_read_timeout(Some(CALL_TIMEOUT))
+        .map_err(unreachable)?;
+    let request = Request {
+        jsonrpc: "2.0".to_string(),
+        id: json!(1),
+        method: method.to_string(),
+        params,
+    };
+    writeln!(stream, "{}", serde_json::to_string(&request).unwrap()).map_err(unreachable)?;
+
+    let mut line = String::new();
+    BufReader::new(stream)
+        .read_line(&mut line)
+        .map_err(unreachable)?;
+
+    let response: Response = serde_json::from_str(&line)
+        .map_err(|err| RpcError::new(RpcError::PARSE_ERROR, err))?;
+
+    if let Some(err) = response.error {
+        return Err(err);
+    }
+
+    serde_json::from_value(response.result.unwrap_or(Value::Null))
+        .map_err(|err| RpcError::new(RpcError::PARSE_ERROR, err))
+}
+
+/// Send the id of every item the daemon saves to `tx`, forever.
+///
+/// Reconnects (starting a new daemon if needed) whenever the daemon goes away.
+/// This function never returns, so it should be run on its own thread.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::client;
+/// use tokio::sync::mpsc;
+///
+/// let (tx, mut rx) = mpsc::unbounded_channel::<i64>();
+/// std::thread::spawn(move || client::subscribe(tx));
+/// ```
+pub fn subscribe(tx: mpsc::UnboundedSender<i64>) {
+    loop {
+        ensure_daemon();
+
+        if let Ok(mut stream) = UnixStream::connect(storage::socket_path()) {
+            let request = Request {
+                jsonrpc: "2.0".to_string(),
+                id: json!(1),
+                method: "subscribe".to_string(),
+                params: Value::Null,
+            };
+
+            if writeln!(stream, "{}", serde_json::to_string(&request).unwrap()).is_ok() {
+                for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                    let Ok(notification) = serde_json::from_str::<Response>(&line) else {
+                        continue;
+                    };
+
+                    if notification.method.as_deref() == Some("item_saved") {
+                        if let Some(id) = notification.params["id"].as_i64() {
+                            if tx.send(id).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        log::warn!("Lost connection to daemon, reconnecting");
+        thread::sleep(RESUBSCRIBE_INTERVAL);
+    }
+}
+
+/// See `clipboard::get_page`.
+pub fn get_page(after: Option<&PageCursor>, limit: i64, filter: &str) -> Result<Page, RpcError> {
+    call(
+        "list",
+        json!({ "after": after, "limit": limit, "query": filter }),
+    )
+}
+
+/// See `clipboard::search_text`.
+pub fn search_text(term: &str) -> Result<Vec<Item>, RpcError> {
+    call("search", json!({ "query": term }))
+}
+
+/// See `clipboard::get_record`.
+pub fn get_record(id: i64) -> Result<Option<Item>, RpcError> {
+    call("get", json!({ "id": id }))
+}
+
+/// See `clipboard::get_files`.
+pub fn get_files(id: i64) -> Result<Vec<FileEntry>, RpcError> {
+    call("get_files", json!({ "id": id }))
+}
+
+/// Get the thumbnail or icon a `blobs::thumbnail_url` or `blobs::icon_url` points at, from the path of the URL.
+pub fn get_display_file(path: &str) -> Result<DisplayFile, RpcError> {
+    call("display_file", json!({ "path": path }))
+}
+
+/// Put an item back on the system clipboard and bump it to the top of the history.
+///
+/// See `clipboard::write_to_clipboard` and `clipboard::update_timestamp`.
+pub fn set_clipboard(id: i64, as_plain_text: bool) -> Result<(), RpcError> {
+    call(
+        "set_clipboard",
+        json!({ "id": id, "plain_text": as_plain_text }),
+    )
+}
+
+/// Like `set_clipboard`, but put the text of the item changed by the transform `transform` instead.
+///
+/// See `transforms::apply`.
+pub fn set_clipboard_transformed(id: i64, transform: &str) -> Result<(), RpcError> {
+    call(
+        "set_clipboard",
+        json!({ "id": id, "transform": transform }),
+    )
+}
+
+/// See `clipboard::delete_many`.
+pub fn delete_many(ids: &[i64]) -> Result<usize, RpcError> {
+    call("delete", json!({ "ids": ids }))
+}
+
+/// See `clipboard::restore_items`.
+pub fn restore_items(ids: &[i64]) -> Result<usize, RpcError> {
+    call("restore", json!({ "ids": ids }))
+}
+
+/// See `clipboard::get_trash`.
+pub fn get_trash() -> Result<Vec<Item>, RpcError> {
+    call("trash", Value::Null)
+}
+
+/// See `clipboard::empty_trash`.
+pub fn empty_trash() -> Result<usize, RpcError> {
+    call("empty_trash", Value::Null)
+}
+
+/// See `merge::merge`.
+pub fn merge(ids: &[i64], separator: &Separator, layout: CollageLayout) -> Result<i64, RpcError> {
+    call(
+        "merge",
+        json!({ "ids": ids, "separator": separator, "layout": layout }),
+    )
+}
+
+/// See `snippets::search`.
+pub fn get_snippets(filter: &str) -> Result<Vec<Snippet>, RpcError> {
+    call("snippets", json!({ "query": filter }))
+}
+
+/// See `snippets::save_snippet`.
+pub fn save_snippet(name: &str, content: &str) -> Result<i64, RpcError> {
+    call("save_snippet", json!({ "name": name, "content": content }))
+}
+
+/// See `snippets::delete_snippet`.
+pub fn delete_snippet(id: i64) -> Result<(), RpcError> {
+    call("delete_snippet", json!({ "id": id }))
+}
+
+/// See `snippets::write_to_clipboard`.
+pub fn copy_snippet(id: i64, inputs: &HashMap<String, String>) -> Result<Rendered, RpcError> {
+    call("copy_snippet", json!({ "id": id, "inputs": inputs }))
+}
+
+/// See `clipboard::pin`.
+pub fn pin(item_id: i64, pinboard_id: i64) -> Result<(), RpcError> {
+    call("pin", json!({ "id": item_id, "pinboard_id": pinboard_id }))
+}
+
+/// See `clipboard::unpin`.
+pub fn unpin(item_id: i64, pinboard_id: i64) -> Result<(), RpcError> {
+    call("unpin", json!({ "id": item_id, "pinboard_id": pinboard_id }))
+}
+
+/// See `clipboard::get_pinboards`.
+pub fn get_pinboards() -> Result<Vec<Pinboard>, RpcError> {
+    call("pinboards", Value::Null)
+}
+
+/// See `clipboard::create_pinboard`.
+pub fn create_pinboard(name: &str) -> Result<i64, RpcError> {
+    call("create_pinboard", json!({ "name": name }))
+}
+
+/// See `clipboard::delete_pinboard`.
+pub fn delete_pinboard(id: i64) -> Result<(), RpcError> {
+    call("delete_pinboard", json!({ "id": id }))
+}
+
+/// See `clipboard::get_pinboard_items`.
+pub fn get_pinboard_items(pinboard_id: i64, filter: &str) -> Result<Vec<Item>, RpcError> {
+    call(
+        "pinboard_items",
+        json!({ "pinboard_id": pinboard_id, "query": filter }),
+    )
+}
+
+/// See `clipboard::move_pinned_item`.
+pub fn move_pinned_item(item_id: i64, pinboard_id: i64, offset: i64) -> Result<(), RpcError> {
+    call(
+        "move_pinned_item",
+        json!({ "id": item_id, "pinboard_id": pinboard_id, "offset": offset }),
+    )
+}
+
+/// See `clipboard::set_tags`.
+pub fn set_tags(id: i64, tags: &[String]) -> Result<(), RpcError> {
+    call("set_tags", json!({ "id": id, "tags": tags }))
+}
+
+/// See `clipboard::set_note`.
+pub fn set_note(id: i64, note: &str) -> Result<(), RpcError> {
+    call("set_note", json!({ "id": id, "note": note }))
+}
+
+/// See `clipboard::rename_tag`.
+pub fn rename_tag(name: &str, new_name: &str) -> Result<(), RpcError> {
+    call("rename_tag", json!({ "name": name, "new_name": new_name }))
+}
+
+/// See `clipboard::lock`.
+pub fn lock() -> Result<(), RpcError> {
+    call("lock", Value::Null)
+}
+
+/// See `clipboard::unlock`.
+pub fn unlock(passphrase: Option<&str>) -> Result<(), RpcError> {
+    call("unlock", json!({ "passphrase": passphrase }))
+}
+
+/// See `clipboard::is_locked`.
+pub fn is_locked() -> Result<bool, RpcError> {
+    call("is_locked", Value::Null)
+}
@@ -1,70 +1,318 @@
 use arboard::{Clipboard, ImageData};
-use base64::engine::general_purpose;
-use base64::prelude::*;
-use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono::{Duration, NaiveDateTime, TimeZone, Utc};
 use clipboard_master::{CallbackResult, ClipboardHandler, Master};
-use image::{ImageBuffer, Rgba};
+#[cfg(not(test))]
 use once_cell::sync::Lazy;
-use rusqlite::types::{Type, ValueRef};
-use rusqlite::{params, Connection, Row};
-use std::env::current_exe;
-use std::io::Cursor;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{
+    params, params_from_iter, Connection, OptionalExtension, Row, Transaction, TransactionBehavior,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Mutex, MutexGuard};
 use tokio::sync::mpsc;
 
-use crate::backend::macos::{current_focus_app_icon_path, current_focus_app_name};
+use crate::backend::blobs;
+use crate::backend::config::{self, SecretAction};
+#[cfg(not(test))]
+use crate::backend::config::EncryptionMode;
+use crate::backend::encryption::{self, EncryptionError};
+use crate::backend::platform::{
+    add_clipboard_data, can_add_clipboard_data, clipboard_data, clipboard_types,
+    current_focus_app_icon_path,
+};
+use crate::backend::privacy::{self, CaptureAction, FocusedApp};
+use crate::backend::search::{self, SearchQuery};
+use crate::backend::secrets;
+use crate::backend::storage;
+use crate::backend::utils::{hash_image, hash_text};
 
 // A simple boolean lock that designed for loop prevention
 // Caller should use this lock proactively notify the `ClipboardHandler` that subsequent clipboard changes will originate internally.
 pub static IS_INTERNAL_PASTE: AtomicBool = AtomicBool::new(false);
 
-const DB_PATH: &str = "clipboard.db";
-static DB_CONN: Lazy<Mutex<Connection>> = Lazy::new(|| {
-    let exe_path = current_exe().unwrap();
-    let exe_parent = exe_path.parent().unwrap();
-    let db_path = exe_parent.join(DB_PATH);
-    let conn = Connection::open(db_path).unwrap();
+/// The format of the `timestamp` column, always in UTC.
+pub(crate) const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS history (
-                id INTEGER PRIMARY KEY,
-                source_app TEXT NOT NULL,
-                icon_path TEXT NOT NULL,
-                content_type TEXT NOT NULL,
-                content BLOB NOT NULL,
-                timestamp TEXT NOT NULL DEFAULT (DATETIME('NOW', 'UTC'))
-    )",
-        [],
-    )
-    .expect("Failed to create table");
+// `None` while the clipboard history is locked, see `lock` and `unlock`
+#[cfg(not(test))]
+static DB_CONN: Lazy<Mutex<Option<Connection>>> = Lazy::new(|| {
+    let path = storage::db_path();
+
+    let conn = match config::get().encryption.mode {
+        // A database that can't be opened (e.g. written by a newer version) stays locked,
+        // `unlock` reports why
+        EncryptionMode::Off | EncryptionMode::Keyring => encryption::open(&path, None)
+            .map_err(|err| log::error!("Failed to open database: {}", err))
+            .ok(),
+        // Stay locked until the passphrase is typed in
+        EncryptionMode::Passphrase => None,
+    };
 
     Mutex::new(conn)
 });
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Item {
     pub id: i64,
     pub source_app: String,
-    pub icon_path: String,
+    pub icon_path: String, // The cached icon, the UI loads it from `blobs::icon_url`
     pub content_type: ContentTypes,
-    pub content: String,
+    pub content: String, // The text itself, or one file path per line. Empty for images, see `blobs::thumbnail_url`
+    pub content_hash: String,
     pub timestamp: chrono::DateTime<Utc>,
+    pub formats: Vec<Format>, // The representations stored next to `content`, e.g. HTML for formatted text
+    pub redacted: bool, // Whether `content` should be hidden in the UI, see `privacy::CaptureAction::Redact`
+    pub masked: bool,   // Whether `content` contains secrets to hide in the UI, see `secrets::mask`
+    pub pinned: bool, // Whether the item is on any pinboard, which exempts it from retention pruning
+    pub tags: Vec<String>, // Sorted by name, see `set_tags`
+    pub note: String, // A note of the user, empty if there is none
+    pub deleted_at: Option<chrono::DateTime<Utc>>, // When the item was moved to the trash, see `delete_item`
+}
+
+/// A named, ordered collection of items, shown as a tab in the `Paste` window.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pinboard {
+    pub id: i64,
+    pub name: String,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
 pub enum ContentTypes {
     Text,
     Image,
+    Files,
+}
+
+impl ContentTypes {
+    /// The value stored in the `content_type` column.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentTypes::Text => "TEXT",
+            ContentTypes::Image => "IMAGE",
+            ContentTypes::Files => "FILES",
+        }
+    }
+}
+
+/// A file or folder of a `ContentTypes::Files` item, as it was when it was copied.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64, // In bytes, 0 for folders
+    pub modified: Option<chrono::DateTime<Utc>>,
+}
+
+impl FileEntry {
+    fn from_path(path: &Path) -> Self {
+        let metadata = fs::metadata(path).ok();
+
+        FileEntry {
+            path: path.to_path_buf(),
+            is_dir: metadata.as_ref().is_some_and(|metadata| metadata.is_dir()),
+            size: metadata
+                .as_ref()
+                .filter(|metadata| metadata.is_file())
+                .map_or(0, |metadata| metadata.len()),
+            modified: metadata
+                .and_then(|metadata| metadata.modified().ok())
+                .map(chrono::DateTime::<Utc>::from),
+        }
+    }
+
+    /// The last component of the path, for display.
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.to_string_lossy().to_string())
+    }
+
+    /// Whether the file is still there. It may have been moved or deleted since it was copied.
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+}
+
+/// An extra representation of an item, offered by the source app next to the plain text.
+///
+/// Copying from a browser or a word processor typically offers HTML and RTF, which keep
+/// the formatting of the text when pasted back into an app that understands them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Html,
+    Rtf,
+    Png, // Stored as the `content_hash` of an image in the blob store
+}
+
+impl Format {
+    /// The MIME type of the format, stored in the `format` column of `representations`.
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Format::Html => "text/html",
+            Format::Rtf => "text/rtf",
+            Format::Png => "image/png",
+        }
+    }
+
+    /// A short name of the format, for display.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Format::Html => "HTML",
+            Format::Rtf => "RTF",
+            Format::Png => "PNG",
+        }
+    }
+
+    pub(crate) fn from_mime(mime: &str) -> Option<Self> {
+        match mime {
+            "text/html" => Some(Format::Html),
+            "text/rtf" => Some(Format::Rtf),
+            "image/png" => Some(Format::Png),
+            _ => None,
+        }
+    }
+}
+
+/// Where a page of `get_page` ended, to get the next one from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageCursor {
+    /// The last item of a page ordered by recency.
+    ///
+    /// It holds the values the page is ordered by rather than just an id, so the next page is
+    /// still found if the item was deleted, trashed or copied again in the meantime.
+    Recent {
+        timestamp: String, // As stored in the database
+        id: i64,
+    },
+    /// The search results after a page ordered by relevance.
+    ///
+    /// They are ranked once, by the first page: BM25 ranks depend on the whole index, so they
+    /// change with every capture, and a cursor holding a rank would skip or repeat items.
+    Ranked { ids: Vec<i64> },
+}
+
+/// A page of records, see `get_page`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Page {
+    pub items: Vec<Item>,
+    pub next: Option<PageCursor>, // None on the last page
+}
+
+/// The columns `row_to_item` expects, qualified so they stay unambiguous in joins.
+const ITEM_COLUMNS: &str = "history.id, history.source_app, history.icon_path, history.content_type, history.content, history.timestamp, history.content_hash,
+     (SELECT GROUP_CONCAT(format) FROM representations WHERE item_id = history.id) AS formats, history.redacted, history.masked, history.pinned, history.note,
+     (SELECT GROUP_CONCAT(name, ' ') FROM (SELECT tags.name FROM item_tags JOIN tags ON tags.id = item_tags.tag_id WHERE item_tags.item_id = history.id ORDER BY tags.name)) AS tags,
+     history.deleted_at";
+
+/// The `JOIN` and `WHERE` parts of a query on `history`, built from a `SearchQuery`.
+struct SqlFilter {
+    joins: String,
+    conditions: Vec<String>,
+    values: Vec<Value>,
+}
+
+impl SqlFilter {
+    fn from_query(query: &SearchQuery) -> Self {
+        let mut filter = SqlFilter {
+            joins: String::new(),
+            // Trashed items are only listed by `get_trash`
+            conditions: vec!["history.deleted_at IS NULL".to_string()],
+            values: Vec::new(),
+        };
+
+        if query.snippets_only {
+            filter.conditions.push("FALSE".to_string());
+        }
+
+        if let Some(expression) = query.fts_expression() {
+            filter.joins = "JOIN history_fts ON history_fts.rowid = history.id".to_string();
+            filter.push("history_fts MATCH ?", expression);
+        }
+
+        if let Some(app) = &query.app {
+            filter.push("history.source_app LIKE ?", format!("%{}%", app));
+        }
+
+        if let Some(content_type) = &query.content_type {
+            filter.push(
+                "history.content_type = ?",
+                content_type.as_str().to_string(),
+            );
+        }
+
+        if let Some(before) = query.before {
+            filter.push(
+                "history.timestamp < ?",
+                before.format(TIMESTAMP_FORMAT).to_string(),
+            );
+        }
+
+        if let Some(after) = query.after {
+            filter.push(
+                "history.timestamp >= ?",
+                after.format(TIMESTAMP_FORMAT).to_string(),
+            );
+        }
+
+        for tag in &query.tags {
+            filter.push(
+                "EXISTS (SELECT 1 FROM item_tags JOIN tags ON tags.id = item_tags.tag_id WHERE item_tags.item_id = history.id AND tags.name = ?)",
+                tag.clone(),
+            );
+        }
+
+        filter
+    }
+
+    fn push(&mut self, condition: &str, value: impl Into<Value>) {
+        self.conditions.push(condition.to_string());
+        self.values.push(value.into());
+    }
+
+    fn to_sql(&self) -> String {
+        format!("{} WHERE {}", self.joins, self.conditions.join(" AND "))
+    }
+}
+
+/// Where a clipboard change comes from and how it is stored, resolved once per change.
+struct Capture {
+    source_app: String,
+    icon_path: String,
+    redacted: bool,
+    expires_at: Option<String>,
+}
+
+impl Capture {
+    /// Applies every action that matched the change: any `Redact` hides it, the earliest `Expire` wins.
+    fn new(focused_app: &FocusedApp, actions: &[CaptureAction]) -> Self {
+        Capture {
+            source_app: focused_app.name.clone(),
+            icon_path: current_focus_app_icon_path().to_string_lossy().to_string(),
+            redacted: actions.contains(&CaptureAction::Redact),
+            expires_at: actions
+                .iter()
+                .filter_map(CaptureAction::expires_at)
+                .min()
+                .map(|expires_at| expires_at.format(TIMESTAMP_FORMAT).to_string()),
+        }
+    }
 }
 
 struct Handler {
     clipboard_ctx: Option<Clipboard>,
-    ui_notify_tx: mpsc::UnboundedSender<()>,
+    ui_notify_tx: mpsc::UnboundedSender<i64>,
 }
 
 impl Handler {
-    fn new(ui_notify_tx: mpsc::UnboundedSender<()>) -> Self {
+    fn new(ui_notify_tx: mpsc::UnboundedSender<i64>) -> Self {
         Handler {
             clipboard_ctx: None,
             ui_notify_tx,
@@ -76,7 +324,7 @@ impl Handler {
             match Clipboard::new() {
                 Ok(ctx) => self.clipboard_ctx = Some(ctx),
                 Err(err) => {
-                    println!("Failed to get clipboard: {}", err);
+                    log::error!("Failed to get clipboard: {}", err);
                     return None;
                 }
             }
@@ -92,10 +340,11 @@ impl ClipboardHandler for Handler {
     /// # Processing Logic
     /// 1. Loop Prevention
     ///    Checks if the change is an internal paste action (`IS_INTERNAL_PASTE`).
-    ///    If so, do not save anything to the database.
+    ///    If so, do not save anything to the database. Neither while the history is locked.
     /// 2. Sensitive Data Filtering
-    ///    Checks if the currently focused application is a password manager.
-    ///    If so, do not save anything to the database.
+    ///    Checks the currently focused application against the privacy rules (see `privacy::action_for`),
+    ///    and the clipboard types against the concealed / transient markers (see `privacy::markers`).
+    ///    Depending on the matching rule, the change is ignored, redacted or saved with an expiry.
     /// 3. Persistence
     ///    Save the clipboard contents to the SQLite database.
     fn on_clipboard_change(&mut self) -> CallbackResult {
@@ -106,49 +355,107 @@ impl ClipboardHandler for Handler {
             return CallbackResult::Next;
         }
 
-        // If the clipboard changed event is triggered from a password manager app
+        // Nothing can be written to an encrypted database while it is locked
+        if is_locked() {
+            log::debug!("Ignoring clipboard change, the history is locked");
+            return CallbackResult::Next;
+        }
+
+        // If the clipboard changed event is triggered from an app ignored by the privacy rules (e.g. a password manager),
+        // or the copy is flagged as concealed / transient by the app that made it
         // DO NOT save anything to the database
-        const IGNORED_APPS: &[&str] = &["Passwords", "Keychain Access", "Bitwarden"];
-        let current_focus_app = current_focus_app_name();
+        let focused_app = FocusedApp::current();
+        let mut actions = vec![privacy::action_for(&focused_app)];
+        actions.extend(privacy::action_for_markers(&privacy::markers(
+            &clipboard_types(),
+        )));
 
-        if IGNORED_APPS
-            .iter()
-            .any(|ignored_app| current_focus_app.contains(ignored_app))
-        {
+        if actions.contains(&CaptureAction::Ignore) {
+            log::debug!("Ignoring clipboard change from {}", focused_app.name);
             return CallbackResult::Next;
         }
 
+        let capture = Capture::new(&focused_app, &actions);
+
         // Save the clipboard contents to the SQLite database
+        // File managers also offer the file names as text and the file icons as an image, so files go first
+        let mut saved_id = None;
+
         if let Some(clipboard) = self.get_clipboard() {
-            if let Ok(text) = clipboard.get_text() {
-                save_text(&text).unwrap();
+            let saved = if let Some(paths) = clipboard
+                .get()
+                .file_list()
+                .ok()
+                .filter(|paths| !paths.is_empty())
+            {
+                save_files(&capture, &paths).map(Some)
+            } else if let Ok(text) = clipboard.get_text() {
+                let (representations, image) = rich_representations(clipboard);
+                save_text(&capture, &text, &representations, image.as_ref())
             } else if let Ok(image) = clipboard.get_image() {
-                save_image(&image).unwrap();
+                save_image(&capture, &image).map(Some)
+            } else {
+                Ok(None)
+            };
+
+            // E.g. the database is busy or the disk is full, the next change is tried again
+            match saved {
+                Ok(id) => saved_id = id,
+                Err(err) => log::error!("Failed to save clipboard change: {}", err),
             }
         }
 
-        // Notify the item has been saved to the database
-        self.ui_notify_tx.send(()).unwrap();
+        // Notify which item has been saved to the database. Without anyone to notify, the history
+        // is still saved, so the capture goes on
+        if let Some(id) = saved_id {
+            if self.ui_notify_tx.send(id).is_err() {
+                log::warn!("Nobody is notified of saved items anymore, item {} was saved", id);
+            }
+        }
 
         CallbackResult::Next
     }
 }
 
+/// Collect the formats the system clipboard offers next to plain text.
+///
+/// An image is returned on its own, to be written to the blob store by `save_text`.
+/// Only HTML is collected on platforms that can't restore the other formats, see `can_add_clipboard_data`.
+fn rich_representations(
+    clipboard: &mut Clipboard,
+) -> (Vec<(Format, Vec<u8>)>, Option<ImageData<'static>>) {
+    let mut representations = Vec::new();
+
+    if let Ok(html) = clipboard.get().html() {
+        representations.push((Format::Html, html.into_bytes()));
+    }
+
+    if !can_add_clipboard_data() {
+        return (representations, None);
+    }
+
+    if let Some(rtf) = clipboard_data(&Format::Rtf) {
+        representations.push((Format::Rtf, rtf));
+    }
+
+    (representations, clipboard.get_image().ok())
+}
+
 /// Listen to system clipboard changes.
 /// When clipboard changes, save the latest item to the SQLite database
 ///
 /// # Arguments
 ///
-/// * `tx` - The channel to notify the item has been saved to the database
-/// 
+/// * `tx` - The channel to notify the item has been saved to the database, carrying the row id of the saved item
+///
 /// Example:
 /// ```
 /// use crate::backend::clipboard;
 ///
-/// let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+/// let (tx, mut rx) = mpsc::unbounded_channel::<i64>();
 /// clipboard::listen(tx); // Start listening
 /// ```
-pub fn listen(tx: mpsc::UnboundedSender<()>) {
+pub fn listen(tx: mpsc::UnboundedSender<i64>) {
     let handler = Handler::new(tx);
     Master::new(handler).unwrap().run().unwrap();
 }
@@ -162,13 +469,14 @@ pub fn listen(tx: mpsc::UnboundedSender<()>) {
 /// let records = clipboard::get_all_records();
 /// println!("{:?}", records); // Output: Ok([Item { id: 1, source_app: "Code", icon_path: "/foo/bar/Code.png", content_type: TEXT, content: "Hello", timestamp: 2025-12-27T17:11:28Z }])
 pub fn get_all_records() -> rusqlite::Result<Vec<Item>> {
-    let conn = db_conn();
+    let conn = db_conn()?;
 
-    let mut stmt = conn.prepare(
-        "SELECT id, source_app, icon_path, content_type, content, timestamp
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITEM_COLUMNS}
          FROM history
-         ORDER BY timestamp DESC",
-    )?;
+         WHERE deleted_at IS NULL
+         ORDER BY timestamp DESC"
+    ))?;
 
     let history_iter = stmt.query_map(params![], row_to_item)?;
 
@@ -189,46 +497,205 @@ pub fn get_all_records() -> rusqlite::Result<Vec<Item>> {
 /// println!("{:?}", records); // Output: Ok([Item { id: 1, source_app: "Code", icon_path: "/foo/bar/Code.png", content_type: TEXT, content: "Hello", timestamp: 2025-12-27T17:11:28Z }])
 /// ```
 pub fn get_recent_records(limit: i64) -> rusqlite::Result<Vec<Item>> {
-    let conn = db_conn();
+    let conn = db_conn()?;
 
-    let mut stmt = conn.prepare(
-        "SELECT id, source_app, icon_path, content_type, content, timestamp
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITEM_COLUMNS}
          FROM history
+         WHERE deleted_at IS NULL
          ORDER BY timestamp DESC
-         LIMIT ?1",
-    )?;
+         LIMIT ?1"
+    ))?;
 
     let history_iter = stmt.query_map(params![limit], row_to_item)?;
 
     history_iter.collect()
 }
 
-/// Search for specific text in the SQLite database
+/// Get a page of records from the SQLite database
+///
+/// Pages are keyset-paginated: pass the `next` cursor of the previous page as `after` to get
+/// the next one. Unlike an `OFFSET`, this stays fast deep into the history and doesn't skip or
+/// repeat items when new ones are inserted in between two calls.
+///
+/// Records are ordered by BM25 relevance if `filter` contains free-text terms, by recency otherwise.
+/// Search results are ranked when the first page is loaded, and the next pages keep that order,
+/// see `PageCursor::Ranked`.
+///
+/// # Arguments
+///
+/// * `after` - The `next` cursor of the previous page, `None` for the first page
+/// * `limit` - The maximum number of records to return
+/// * `filter` - A search bar query (see `SearchQuery`), empty for no filtering
+///
+/// # Example:
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let first_page = clipboard::get_page(None, 50, "").unwrap();
+/// let second_page = clipboard::get_page(first_page.next.as_ref(), 50, "").unwrap();
+/// ```
+pub fn get_page(after: Option<&PageCursor>, limit: i64, filter: &str) -> rusqlite::Result<Page> {
+    let conn = db_conn()?;
+    let query = SearchQuery::parse(filter);
+    let mut filter = SqlFilter::from_query(&query);
+
+    match after {
+        Some(PageCursor::Ranked { ids }) => ranked_page(&conn, filter, ids, limit),
+        Some(PageCursor::Recent { timestamp, id }) => {
+            filter
+                .conditions
+                .push("(history.timestamp, history.id) < (?, ?)".to_string());
+            filter.values.push(timestamp.clone().into());
+            filter.values.push((*id).into());
+            recent_page(&conn, filter, limit)
+        }
+        None if query.terms.is_empty() => recent_page(&conn, filter, limit),
+        None => {
+            let ids = conn
+                .prepare(&format!(
+                    "SELECT history.id
+                     FROM history {}
+                     ORDER BY bm25(history_fts, 1.0, 0.5), history.timestamp DESC, history.id DESC",
+                    filter.to_sql()
+                ))?
+                .query_map(params_from_iter(&filter.values), |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+            ranked_page(&conn, filter, &ids, limit)
+        }
+    }
+}
+
+/// Get the `limit` most recent records matching `filter`, see `get_page`.
+fn recent_page(conn: &Connection, mut filter: SqlFilter, limit: i64) -> rusqlite::Result<Page> {
+    let sql = filter.to_sql();
+    filter.values.push(limit.into());
+
+    let items = conn
+        .prepare(&format!(
+            "SELECT {ITEM_COLUMNS}
+             FROM history {sql}
+             ORDER BY history.timestamp DESC, history.id DESC
+             LIMIT ?"
+        ))?
+        .query_map(params_from_iter(filter.values), row_to_item)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // A full page may be followed by more
+    let next = match items.last() {
+        Some(last) if items.len() as i64 == limit => Some(PageCursor::Recent {
+            timestamp: last.timestamp.format(TIMESTAMP_FORMAT).to_string(),
+            id: last.id,
+        }),
+        _ => None,
+    };
+
+    Ok(Page { items, next })
+}
+
+/// Get the records of `ids` still matching `filter`, in that order, up to `limit` of them, see `get_page`.
+fn ranked_page(
+    conn: &Connection,
+    mut filter: SqlFilter,
+    ids: &[i64],
+    limit: i64,
+) -> rusqlite::Result<Page> {
+    let limit = limit.max(0) as usize;
+    let mut items = Vec::with_capacity(limit);
+    let mut rest = ids;
+
+    // Results deleted or changed since they were ranked are left out, the page is filled from the next ones
+    while items.len() < limit && !rest.is_empty() {
+        let (chunk, after_chunk) = rest.split_at((limit - items.len()).min(rest.len()));
+        rest = after_chunk;
+
+        filter.conditions.push(format!(
+            "history.id IN ({})",
+            vec!["?"; chunk.len()].join(", ")
+        ));
+        filter.values.extend(chunk.iter().map(|&id| Value::from(id)));
+
+        let mut found = conn
+            .prepare(&format!("SELECT {ITEM_COLUMNS} FROM history {}", filter.to_sql()))?
+            .query_map(params_from_iter(&filter.values), row_to_item)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        found.sort_by_key(|item| chunk.iter().position(|&id| id == item.id));
+        items.extend(found);
+
+        filter.conditions.pop();
+        filter.values.truncate(filter.values.len() - chunk.len());
+    }
+
+    Ok(Page {
+        items,
+        next: (!rest.is_empty()).then(|| PageCursor::Ranked { ids: rest.to_vec() }),
+    })
+}
+
+/// Get a single record from the SQLite database, even if it is in the trash
+///
+/// # Arguments
+///
+/// * `id` - The unique identifier (Primary Key) of the history record.
+///
+/// # Example:
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let record = clipboard::get_record(1);
+/// println!("{:?}", record); // Output: Ok(Some(Item { id: 1, source_app: "Code", icon_path: "/foo/bar/Code.png", content_type: TEXT, content: "Hello", timestamp: 2025-12-27T17:11:28Z }))
+/// ```
+pub fn get_record(id: i64) -> rusqlite::Result<Option<Item>> {
+    let conn = db_conn()?;
+
+    conn.query_row(
+        &format!(
+            "SELECT {ITEM_COLUMNS}
+             FROM history
+             WHERE id = ?1"
+        ),
+        params![id],
+        row_to_item,
+    )
+    .optional()
+}
+
+/// Search the SQLite database with a search bar query
+///
+/// The free-text part of the query is matched against the FTS5 index, and the results
+/// are ranked by BM25 relevance. Queries made of qualifiers only (e.g. `type:image`)
+/// are ordered by recency instead. See `SearchQuery` for the query syntax.
 ///
 /// # Arguments
 ///
-/// * `term` - The text to search for
+/// * `term` - The raw search bar query
 ///
 /// # Example:
 /// ```
 /// use crate::backend::clipboard;
 ///
-/// let records = clipboard::search_text("Hello World");
+/// let records = clipboard::search_text("hello app:Code");
 /// println!("{:?}", records); // Output: Ok([Item { id: 1, source_app: "Code", icon_path: "/foo/bar/Code.png", content_type: TEXT, content: "Hello World", timestamp: 2025-12-27T17:28:01Z }])
 /// ```
 pub fn search_text(term: &str) -> rusqlite::Result<Vec<Item>> {
-    let conn = db_conn();
-    let pattern = format!("%{}%", term);
+    let conn = db_conn()?;
+    let query = SearchQuery::parse(term);
+    let filter = SqlFilter::from_query(&query);
+    let order = if query.terms.is_empty() {
+        "history.timestamp DESC"
+    } else {
+        "bm25(history_fts, 1.0, 0.5), history.timestamp DESC"
+    };
 
-    let mut stmt = conn.prepare(
-        "SELECT id, source_app, icon_path, content_type, content, timestamp
-         FROM history
-         WHERE content_type = 'TEXT' AND content LIKE ?1
-         ORDER BY timestamp DESC
-        ",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITEM_COLUMNS}
+         FROM history {}
+         ORDER BY {order}",
+        filter.to_sql()
+    ))?;
 
-    let history_iter = stmt.query_map(params![pattern], row_to_item)?;
+    let history_iter = stmt.query_map(params_from_iter(filter.values), row_to_item)?;
 
     history_iter.collect()
 }
@@ -242,6 +709,7 @@ pub fn search_text(term: &str) -> rusqlite::Result<Vec<Item>> {
 /// This is typically used when a user re-pastes an old clipboard item.
 /// By updating the timestamp, the item is effectively "bumped" to the
 /// **top of the list** (marked as most recently used).
+/// Pinboards are ordered by position instead, so items inside a pinboard keep their place.
 ///
 /// # Arguments
 ///
@@ -254,7 +722,7 @@ pub fn search_text(term: &str) -> rusqlite::Result<Vec<Item>> {
 /// clipboard::update_timestamp(1);
 /// ```
 pub fn update_timestamp(id: i64) -> rusqlite::Result<()> {
-    let conn = db_conn();
+    let conn = db_conn()?;
 
     conn.execute(
         "UPDATE history SET timestamp = DATETIME('NOW', 'UTC') WHERE id = ?1",
@@ -264,132 +732,1426 @@ pub fn update_timestamp(id: i64) -> rusqlite::Result<()> {
     Ok(())
 }
 
-/// Saves text content to the clipboard history database.
+/// Deletes a specific history record for good, and the images and icons no other record refers to.
 ///
-/// It automatically captures context metadata:
-/// - `source_app`: Name of the focused application.
-/// - `icon_path`: Path to the application's icon.
+/// Unlike `delete_item`, this can't be undone.
 ///
 /// # Arguments
 ///
-/// * `content` - The text string to be saved.
-fn save_text(content: &str) -> rusqlite::Result<()> {
-    let conn = db_conn();
-    let source_app = current_focus_app_name();
-    let icon_path = current_focus_app_icon_path().to_string_lossy().to_string();
-
-    let row_effected = conn.execute(
-        "
-        UPDATE history
-        SET timestamp = DATETIME('NOW', 'UTC'), source_app = ?1, icon_path = ?2
-        WHERE content_type = 'TEXT' AND content = ?3
-    ",
-        params![source_app, icon_path, content],
-    )?;
+/// * `id` - The unique identifier (Primary Key) of the history record.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::delete_record(1);
+/// ```
+pub fn delete_record(id: i64) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
 
-    if row_effected == 0 {
-        conn.execute(
-            "INSERT INTO history (source_app, icon_path, content_type, content) VALUES (?1, ?2, 'TEXT', ?3)",
-            params![source_app, icon_path, content],
-        )?;
+    if conn.execute("DELETE FROM history WHERE id = ?1", params![id])? > 0 {
+        blobs::collect_garbage(&conn)?;
     }
 
     Ok(())
 }
 
-/// Saves image content to the clipboard history database.
+/// Moves a specific history record to the trash, see `delete_many`.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::delete_item(1).unwrap();
+/// clipboard::restore_items(&[1]).unwrap(); // Undo
+/// ```
+pub fn delete_item(id: i64) -> rusqlite::Result<()> {
+    delete_many(&[id])?;
+
+    Ok(())
+}
+
+/// Moves history records to the trash.
 ///
-/// Similar to `save_text` function.
+/// Trashed records are hidden from the history, search and pinboards, and leave the full-text
+/// index right away. They keep their tags and pins until the trash is emptied (see `empty_trash`
+/// and `retention::prune`), so they can be restored with `restore_items`. Copying a trashed record
+/// again restores it.
+///
+/// Their images, thumbnails and icons stay on disk until then too: `blobs::collect_garbage` only
+/// deletes them once the records are purged.
+///
+/// Returns the number of records moved to the trash.
 ///
 /// # Arguments
 ///
-/// * `content` - The raw image data captured from the system clipboard.
-fn save_image(content: &ImageData) -> rusqlite::Result<()> {
-    let conn = db_conn();
-    let source_app = current_focus_app_name();
-    let icon_path = current_focus_app_icon_path().to_string_lossy().to_string();
-    let content_bytes = content.bytes.as_ref();
-    let width = content.width as u32;
-    let height = content.height as u32;
-    let png_bytes = if let Some(img_buffer) =
-        ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, content_bytes)
-    {
-        let mut bytes: Vec<u8> = Vec::new();
-
-        if img_buffer
-            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
-            .is_ok()
-        {
-            bytes
-        } else {
-            Vec::new()
-        }
-    } else {
-        Vec::new()
-    };
-
-    let rows_affected = conn.execute(
-        "UPDATE history 
-         SET timestamp = DATETIME('NOW', 'UTC'), source_app = ?1, icon_path = ?2
-         WHERE content_type = 'IMAGE' AND content = ?3",
-        params![source_app, icon_path, png_bytes],
-    )?;
+/// * `ids` - The unique identifiers (Primary Keys) of the history records.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let deleted = clipboard::delete_many(&[1, 2, 3]);
+/// println!("{:?}", deleted); // Output: Ok(3)
+/// ```
+pub fn delete_many(ids: &[i64]) -> rusqlite::Result<usize> {
+    let conn = db_conn()?;
+    let tx = conn.unchecked_transaction()?;
+    let mut deleted = 0;
 
-    if rows_affected == 0 {
-        conn.execute(
-            "INSERT INTO history (source_app, icon_path, content_type, content) VALUES (?1, ?2, 'IMAGE', ?3)",
-            params![source_app, icon_path, png_bytes],
+    for id in ids {
+        deleted += tx.execute(
+            "UPDATE history SET deleted_at = DATETIME('NOW', 'UTC') WHERE id = ?1 AND deleted_at IS NULL",
+            params![id],
         )?;
     }
 
-    Ok(())
+    tx.commit()?;
+
+    Ok(deleted)
 }
 
-/// Maps a raw database row to the `Item` struct.
+/// Moves history records out of the trash, back to where they were.
+///
+/// Returns the number of restored records.
 ///
 /// # Arguments
 ///
-/// * `row` - A reference to a single row result from a rusqlite query.
-fn row_to_item(row: &Row) -> rusqlite::Result<Item> {
-    let id: i64 = row.get(0)?;
-    let source_app: String = row.get(1)?;
-    let icon_path: String = row.get(2)?;
-    let content_type: String = row.get(3)?;
-    let content: ValueRef = row.get_ref(4)?;
-    let timestamp: String = row.get(5)?;
+/// * `ids` - The unique identifiers (Primary Keys) of the trashed history records.
+pub fn restore_items(ids: &[i64]) -> rusqlite::Result<usize> {
+    let conn = db_conn()?;
+    let tx = conn.unchecked_transaction()?;
+    let mut restored = 0;
 
-    let content_type = match content_type.as_str() {
-        "IMAGE" => ContentTypes::Image,
-        "TEXT" => ContentTypes::Text,
-        _ => unreachable!(),
-    };
+    for id in ids {
+        restored += tx.execute(
+            "UPDATE history SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL",
+            params![id],
+        )?;
+    }
 
-    let content_raw_bytes: Vec<u8> = match content.data_type() {
-        Type::Blob => content.as_blob()?.to_vec(),
-        Type::Text => content.as_str()?.as_bytes().to_vec(),
-        _ => Vec::new(),
-    };
+    tx.commit()?;
 
-    let content = match content_type {
-        ContentTypes::Image => general_purpose::STANDARD.encode(&content_raw_bytes),
-        ContentTypes::Text => String::from_utf8_lossy(&content_raw_bytes).to_string(),
-    };
+    Ok(restored)
+}
 
-    let timestamp = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S")
-        .map(|naive| Utc.from_utc_datetime(&naive))
-        .unwrap_or_else(|_| Utc::now());
+/// Get the records in the trash, most recently deleted first.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let trash = clipboard::get_trash();
+/// println!("{:?}", trash); // Output: Ok([Item { id: 1, source_app: "Code", ..., deleted_at: Some(2025-12-28T09:12:44Z) }])
+/// ```
+pub fn get_trash() -> rusqlite::Result<Vec<Item>> {
+    let conn = db_conn()?;
 
-    Ok(Item {
-        id,
-        source_app,
-        icon_path,
-        content_type,
-        content,
-        timestamp,
-    })
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITEM_COLUMNS}
+         FROM history
+         WHERE deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC, id DESC"
+    ))?;
+
+    let history_iter = stmt.query_map([], row_to_item)?;
+
+    history_iter.collect()
 }
 
-/// Get a connection to the SQLite database
-fn db_conn() -> MutexGuard<'static, Connection> {
-    DB_CONN.lock().unwrap()
+/// Deletes every record in the trash for good, and the images and icons no other record refers to.
+///
+/// Returns the number of deleted records.
+pub fn empty_trash() -> rusqlite::Result<usize> {
+    let conn = db_conn()?;
+
+    let deleted = conn.execute("DELETE FROM history WHERE deleted_at IS NOT NULL", [])?;
+
+    if deleted > 0 {
+        blobs::collect_garbage(&conn)?;
+    }
+
+    Ok(deleted)
+}
+
+/// Sets the note of a specific history record. An empty note removes it.
+///
+/// Notes are part of the full-text index, so the item can be searched by its note.
+///
+/// # Arguments
+///
+/// * `id` - The unique identifier (Primary Key) of the history record.
+/// * `note` - The note of the user.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::set_note(1, "The staging API key");
+/// ```
+pub fn set_note(id: i64, note: &str) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
+
+    conn.execute(
+        "UPDATE history SET note = ?2 WHERE id = ?1",
+        params![id, note.trim()],
+    )?;
+
+    Ok(())
+}
+
+/// Replaces the tags of a specific history record.
+///
+/// Tags are cleaned up first (see `search::normalize_tag`) and matched case-insensitively against
+/// the existing ones, so `Work` and `#work` are the same tag. Tags no item uses anymore are deleted.
+///
+/// # Arguments
+///
+/// * `id` - The unique identifier (Primary Key) of the history record.
+/// * `tags` - All of the tags the record should have.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::set_tags(1, &["#work".to_string(), "todo".to_string()]);
+/// ```
+pub fn set_tags(id: i64, tags: &[String]) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
+    let tx = conn.unchecked_transaction()?;
+
+    tx.execute("DELETE FROM item_tags WHERE item_id = ?1", params![id])?;
+
+    for tag in tags.iter().filter_map(|tag| search::normalize_tag(tag)) {
+        tx.execute(
+            "INSERT INTO tags (name) VALUES (?1) ON CONFLICT (name) DO NOTHING",
+            params![tag],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO item_tags (item_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+            params![id, tag],
+        )?;
+    }
+
+    tx.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM item_tags)",
+        [],
+    )?;
+
+    tx.commit()
+}
+
+/// Renames a tag on every item that has it.
+///
+/// If another tag already has the new name, the two are merged.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::rename_tag("todo", "later");
+/// ```
+pub fn rename_tag(name: &str, new_name: &str) -> rusqlite::Result<()> {
+    let (Some(name), Some(new_name)) =
+        (search::normalize_tag(name), search::normalize_tag(new_name))
+    else {
+        return Ok(());
+    };
+    let conn = db_conn()?;
+    let tx = conn.unchecked_transaction()?;
+
+    let existing_id: Option<i64> = tx
+        .query_row(
+            "SELECT id FROM tags WHERE name = ?1",
+            params![new_name],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    match existing_id {
+        // Merge into the existing tag (unless it only differs in case), deleting the old one
+        Some(existing_id) => {
+            tx.execute(
+                "INSERT OR IGNORE INTO item_tags (item_id, tag_id)
+                 SELECT item_id, ?2 FROM item_tags WHERE tag_id = (SELECT id FROM tags WHERE name = ?1)",
+                params![name, existing_id],
+            )?;
+            tx.execute(
+                "DELETE FROM tags WHERE name = ?1 AND id != ?2",
+                params![name, existing_id],
+            )?;
+            tx.execute(
+                "UPDATE tags SET name = ?2 WHERE id = ?1",
+                params![existing_id, new_name],
+            )?;
+        }
+        None => {
+            tx.execute(
+                "UPDATE tags SET name = ?2 WHERE name = ?1",
+                params![name, new_name],
+            )?;
+        }
+    }
+
+    tx.commit()
+}
+
+/// Get all pinboards, in the order of their tabs.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let pinboards = clipboard::get_pinboards();
+/// println!("{:?}", pinboards); // Output: Ok([Pinboard { id: 1, name: "Pinned" }])
+/// ```
+pub fn get_pinboards() -> rusqlite::Result<Vec<Pinboard>> {
+    let conn = db_conn()?;
+    let mut stmt = conn.prepare("SELECT id, name FROM pinboards ORDER BY position, id")?;
+
+    let pinboard_iter = stmt.query_map([], |row| {
+        Ok(Pinboard {
+            id: row.get(0)?,
+            name: row.get(1)?,
+        })
+    })?;
+
+    pinboard_iter.collect()
+}
+
+/// Creates a pinboard after the existing ones.
+///
+/// Returns the id of the new pinboard.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let id = clipboard::create_pinboard("Snippets").unwrap();
+/// ```
+pub fn create_pinboard(name: &str) -> rusqlite::Result<i64> {
+    let conn = db_conn()?;
+
+    conn.query_row(
+        "INSERT INTO pinboards (name, position)
+         VALUES (?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM pinboards))
+         RETURNING id",
+        params![name],
+        |row| row.get(0),
+    )
+}
+
+/// Deletes a pinboard. Its items stay in the history, and are unpinned unless they are on another pinboard.
+pub fn delete_pinboard(id: i64) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
+
+    conn.execute("DELETE FROM pinboards WHERE id = ?1", params![id])?;
+
+    Ok(())
+}
+
+/// Get the items of a pinboard, in their order on the pinboard.
+///
+/// # Arguments
+///
+/// * `pinboard_id` - The pinboard to get the items of.
+/// * `filter` - A search bar query (see `SearchQuery`), empty for no filtering
+///
+/// # Example:
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let items = clipboard::get_pinboard_items(1, "type:image").unwrap();
+/// ```
+pub fn get_pinboard_items(pinboard_id: i64, filter: &str) -> rusqlite::Result<Vec<Item>> {
+    let conn = db_conn()?;
+    let mut filter = SqlFilter::from_query(&SearchQuery::parse(filter));
+
+    filter
+        .joins
+        .push_str(" JOIN pinboard_items ON pinboard_items.item_id = history.id");
+    filter.push("pinboard_items.pinboard_id = ?", pinboard_id);
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ITEM_COLUMNS}
+         FROM history {}
+         ORDER BY pinboard_items.position",
+        filter.to_sql()
+    ))?;
+
+    let history_iter = stmt.query_map(params_from_iter(filter.values), row_to_item)?;
+
+    history_iter.collect()
+}
+
+/// Adds an item to the end of a pinboard, which pins it. Does nothing if it is already there.
+///
+/// Pinned items are exempt from retention pruning.
+///
+/// # Arguments
+///
+/// * `item_id` - The unique identifier (Primary Key) of the history record.
+/// * `pinboard_id` - The pinboard to add the item to.
+///
+/// # Example
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::pin(42, 1);
+/// ```
+pub fn pin(item_id: i64, pinboard_id: i64) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
+
+    conn.execute(
+        "INSERT OR IGNORE INTO pinboard_items (pinboard_id, item_id, position)
+         VALUES (?1, ?2, (SELECT COALESCE(MAX(position) + 1, 0) FROM pinboard_items WHERE pinboard_id = ?1))",
+        params![pinboard_id, item_id],
+    )?;
+
+    Ok(())
+}
+
+/// Removes an item from a pinboard. The item is unpinned unless it is on another pinboard.
+pub fn unpin(item_id: i64, pinboard_id: i64) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
+
+    conn.execute(
+        "DELETE FROM pinboard_items WHERE pinboard_id = ?1 AND item_id = ?2",
+        params![pinboard_id, item_id],
+    )?;
+
+    Ok(())
+}
+
+/// Moves an item of a pinboard by `offset` places, e.g. `-1` to swap it with the item before it.
+///
+/// The item stops at either end of the pinboard.
+pub fn move_pinned_item(item_id: i64, pinboard_id: i64, offset: i64) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
+    let tx = conn.unchecked_transaction()?;
+
+    let mut item_ids = tx
+        .prepare("SELECT item_id FROM pinboard_items WHERE pinboard_id = ?1 ORDER BY position")?
+        .query_map(params![pinboard_id], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let Some(from) = item_ids.iter().position(|id| *id == item_id) else {
+        return Ok(());
+    };
+    let to = (from as i64 + offset).clamp(0, item_ids.len() as i64 - 1) as usize;
+    let item_id = item_ids.remove(from);
+    item_ids.insert(to, item_id);
+
+    // Rewrite every position, so they stay dense whatever was removed in between
+    for (position, item_id) in item_ids.iter().enumerate() {
+        tx.execute(
+            "UPDATE pinboard_items SET position = ?3 WHERE pinboard_id = ?1 AND item_id = ?2",
+            params![pinboard_id, item_id, position as i64],
+        )?;
+    }
+
+    tx.commit()
+}
+
+/// Saves text content to the clipboard history database.
+///
+/// It automatically captures context metadata:
+/// - `source_app`: Name of the focused application.
+/// - `icon_path`: Path to the application's icon.
+///
+/// # Arguments
+///
+/// * `capture` - The source app of the text, and how it should be stored.
+/// * `content` - The text string to be saved.
+/// * `representations` - The other formats the text was offered in, see `rich_representations`.
+/// * `image` - An image the text was also offered as, stored as a `Format::Png` representation.
+///
+/// The text is scanned for secrets first (see `secrets::detect`), which may keep it from being saved,
+/// hide the secrets in its preview or make it expire, depending on the `[secrets]` settings.
+///
+/// Returns the id of the saved item, or `None` if it contains a secret that must not be saved.
+/// Copying a text that is already in the history (i.e. has the same `content_hash`) bumps
+/// the existing item instead of inserting a duplicate, and its representations are replaced
+/// by the ones of the latest copy.
+fn save_text(
+    capture: &Capture,
+    content: &str,
+    representations: &[(Format, Vec<u8>)],
+    image: Option<&ImageData>,
+) -> rusqlite::Result<Option<i64>> {
+    let mut masked = false;
+    let mut expires_at = capture.expires_at.clone();
+
+    for detection in secrets::detect(content) {
+        match detection.kind.action() {
+            SecretAction::Off => {}
+            SecretAction::Skip => {
+                log::info!(
+                    "Not saving copied text containing a {}",
+                    detection.kind.label()
+                );
+                return Ok(None);
+            }
+            SecretAction::Mask => masked = true,
+            SecretAction::Expire => {
+                let secret_expires_at = (Utc::now()
+                    + Duration::minutes(config::get().secrets.expire_after_minutes as i64))
+                .format(TIMESTAMP_FORMAT)
+                .to_string();
+
+                // Keep the earliest expiry, if a privacy rule already set one
+                expires_at = Some(match expires_at {
+                    Some(expires_at) => expires_at.min(secret_expires_at),
+                    None => secret_expires_at,
+                });
+            }
+        }
+    }
+
+    let conn = db_conn()?;
+    // The write lock is taken before the image is stored, so no garbage collection
+    // can run between storing it and referring to it, see `blobs::collect_garbage`
+    let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)?;
+
+    let mut representations = representations.to_vec();
+    let mut size = content.len() as u64;
+
+    if let Some(image) = image {
+        let image_hash = hash_image(image);
+
+        match blobs::store_image(&image_hash, image) {
+            Ok(image_size) => {
+                size += image_size;
+                representations.push((Format::Png, image_hash.into_bytes()));
+            }
+            Err(err) => log::error!("Failed to store image representation: {}", err),
+        }
+    }
+
+    let content_hash = hash_text(content);
+    size += representations
+        .iter()
+        .filter(|(format, _)| *format != Format::Png)
+        .map(|(_, data)| data.len() as u64)
+        .sum::<u64>();
+
+    let id: i64 = tx.query_row(
+        "INSERT INTO history (source_app, icon_path, content_type, content, content_hash, size, redacted, expires_at, masked)
+         VALUES (?1, ?2, 'TEXT', ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT (content_type, content_hash) DO UPDATE
+         SET timestamp = DATETIME('NOW', 'UTC'), source_app = excluded.source_app, icon_path = excluded.icon_path, size = excluded.size,
+             redacted = excluded.redacted, expires_at = excluded.expires_at, masked = excluded.masked, deleted_at = NULL
+         RETURNING id",
+        params![
+            capture.source_app,
+            capture.icon_path,
+            content,
+            content_hash,
+            size as i64,
+            capture.redacted,
+            expires_at,
+            masked,
+        ],
+        |row| row.get(0),
+    )?;
+
+    tx.execute(
+        "DELETE FROM representations WHERE item_id = ?1",
+        params![id],
+    )?;
+
+    for (format, data) in &representations {
+        tx.execute(
+            "INSERT INTO representations (item_id, format, data) VALUES (?1, ?2, ?3)",
+            params![id, format.mime(), data],
+        )?;
+    }
+
+    tx.commit()?;
+
+    Ok(Some(id))
+}
+
+/// Saves image content to the clipboard history database.
+///
+/// Similar to `save_text` function, except that the image itself is written to the blob store
+/// (see `blobs::store_image`) and only referenced from the database by its hash.
+///
+/// # Arguments
+///
+/// * `capture` - The source app of the image, and how it should be stored.
+/// * `content` - The raw image data captured from the system clipboard.
+///
+/// Returns the id of the saved item.
+fn save_image(capture: &Capture, content: &ImageData) -> rusqlite::Result<i64> {
+    let conn = db_conn()?;
+    let content_hash = hash_image(content);
+
+    // The pixels live in the blob store, the row only refers to them by hash.
+    // Like in `save_text`, the image is stored under the write lock of the row referring to it.
+    let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)?;
+    let size = blobs::store_image(&content_hash, content)
+        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+
+    let id = tx.query_row(
+        "INSERT INTO history (source_app, icon_path, content_type, content, content_hash, size, redacted, expires_at)
+         VALUES (?1, ?2, 'IMAGE', x'', ?3, ?4, ?5, ?6)
+         ON CONFLICT (content_type, content_hash) DO UPDATE
+         SET timestamp = DATETIME('NOW', 'UTC'), source_app = excluded.source_app, icon_path = excluded.icon_path,
+             redacted = excluded.redacted, expires_at = excluded.expires_at, deleted_at = NULL
+         RETURNING id",
+        params![
+            capture.source_app,
+            capture.icon_path,
+            content_hash,
+            size as i64,
+            capture.redacted,
+            capture.expires_at,
+        ],
+        |row| row.get(0),
+    )?;
+
+    tx.commit()?;
+
+    Ok(id)
+}
+
+/// Saves the result of merging items (see `merge::merge`) as a new text item.
+///
+/// The merged item is saved as if it had been copied from the app of the first of `items`,
+/// and keeps the strictest privacy of all of them: it is redacted if any of them is,
+/// and expires with the first of them that expires. It is scanned for secrets like any copied text.
+///
+/// Returns the id of the saved item, or `None` if it contains a secret that must not be saved.
+pub(crate) fn save_merged_text(items: &[Item], content: &str) -> rusqlite::Result<Option<i64>> {
+    save_text(&merged_capture(items)?, content, &[], None)
+}
+
+/// Saves the collage of merged images (see `merge::merge`) as a new image item.
+///
+/// Like `save_merged_text`, the collage takes the app and the privacy of the merged items.
+pub(crate) fn save_merged_image(items: &[Item], content: &ImageData) -> rusqlite::Result<i64> {
+    save_image(&merged_capture(items)?, content)
+}
+
+/// The `Capture` of the result of merging `items`, see `save_merged_text`.
+fn merged_capture(items: &[Item]) -> rusqlite::Result<Capture> {
+    let conn = db_conn()?;
+    let mut expires_at = None::<String>;
+
+    for item in items {
+        let item_expires_at: Option<String> = conn.query_row(
+            "SELECT expires_at FROM history WHERE id = ?1",
+            params![item.id],
+            |row| row.get(0),
+        )?;
+
+        expires_at = match (expires_at, item_expires_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    Ok(Capture {
+        source_app: items
+            .first()
+            .map(|item| item.source_app.clone())
+            .unwrap_or_default(),
+        icon_path: items
+            .first()
+            .map(|item| item.icon_path.clone())
+            .unwrap_or_default(),
+        redacted: items.iter().any(|item| item.redacted),
+        expires_at,
+    })
+}
+
+/// Get the data of one of the extra representations of a record.
+///
+/// # Arguments
+///
+/// * `id` - The unique identifier (Primary Key) of the history record.
+/// * `format` - The representation to get, one of `Item::formats`.
+///
+/// # Example:
+/// ```
+/// use crate::backend::clipboard::{self, Format};
+///
+/// let html = clipboard::get_representation(1, &Format::Html);
+/// println!("{:?}", html); // Output: Ok(Some([60, 98, 62, 72, 101, 108, 108, 111, 60, 47, 98, 62]))
+/// ```
+pub fn get_representation(id: i64, format: &Format) -> rusqlite::Result<Option<Vec<u8>>> {
+    let conn = db_conn()?;
+
+    conn.query_row(
+        "SELECT CAST(data AS BLOB) FROM representations WHERE item_id = ?1 AND format = ?2",
+        params![id, format.mime()],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Puts a record back on the system clipboard, in all of the formats it was copied in.
+///
+/// The change is flagged with `IS_INTERNAL_PASTE`, so it isn't saved to the history again.
+/// If the clipboard can't be written, the flag is cleared, so the next copy of the user is still saved.
+///
+/// # Arguments
+///
+/// * `item` - The record to put on the clipboard.
+/// * `as_plain_text` - Only restore the plain text, dropping the formatting of the source app.
+///   Files are restored as their paths.
+///
+/// # Example:
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let item = clipboard::get_record(1).unwrap().unwrap();
+/// clipboard::write_to_clipboard(&item, false).unwrap();
+/// ```
+pub fn write_to_clipboard(item: &Item, as_plain_text: bool) -> Result<(), arboard::Error> {
+    write_internal(|| set_clipboard_contents(item, as_plain_text))
+}
+
+/// Puts a text on the system clipboard, without saving it to the history, e.g. a rendered snippet.
+///
+/// # Example:
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::write_text_to_clipboard("Best regards,\nJane").unwrap();
+/// ```
+pub fn write_text_to_clipboard(text: &str) -> Result<(), arboard::Error> {
+    write_internal(|| Clipboard::new()?.set_text(text))
+}
+
+/// Runs `write`, flagging the clipboard change it makes with `IS_INTERNAL_PASTE`, see `write_to_clipboard`.
+fn write_internal(
+    write: impl FnOnce() -> Result<(), arboard::Error>,
+) -> Result<(), arboard::Error> {
+    IS_INTERNAL_PASTE.store(true, Ordering::SeqCst);
+
+    let result = write();
+    if result.is_err() {
+        IS_INTERNAL_PASTE.store(false, Ordering::SeqCst);
+    }
+
+    result
+}
+
+/// Puts a record on the system clipboard, see `write_to_clipboard`.
+fn set_clipboard_contents(item: &Item, as_plain_text: bool) -> Result<(), arboard::Error> {
+    let mut clipboard = Clipboard::new()?;
+
+    if item.content_type == ContentTypes::Image {
+        let image =
+            blobs::load_image(&item.content_hash).ok_or(arboard::Error::ContentNotAvailable)?;
+        return clipboard.set_image(image);
+    }
+
+    // Files that have been deleted since can't be pasted anymore, the others still can
+    if item.content_type == ContentTypes::Files && !as_plain_text {
+        let paths = item
+            .content
+            .lines()
+            .map(PathBuf::from)
+            .filter(|path| path.exists())
+            .collect::<Vec<_>>();
+
+        if paths.is_empty() {
+            return Err(arboard::Error::ContentNotAvailable);
+        }
+
+        return clipboard.set().file_list(&paths);
+    }
+
+    let formats = if as_plain_text {
+        &[][..]
+    } else {
+        &item.formats[..]
+    };
+    let representation = |format: &Format| -> Option<Vec<u8>> {
+        get_representation(item.id, format)
+            .unwrap_or_else(|err| {
+                log::error!(
+                    "Failed to load {} of item {}: {}",
+                    format.mime(),
+                    item.id,
+                    err
+                );
+                None
+            })
+            .and_then(|data| match format {
+                // The representation only refers to the image, the PNG itself is in the blob store
+                Format::Png => blobs::read_png(&String::from_utf8_lossy(&data)).ok(),
+                _ => Some(data),
+            })
+    };
+
+    let html = if formats.contains(&Format::Html) {
+        representation(&Format::Html)
+    } else {
+        None
+    };
+
+    match html {
+        Some(html) => clipboard.set().html(
+            String::from_utf8_lossy(&html),
+            Some(item.content.as_str().into()),
+        )?,
+        None => clipboard.set_text(&item.content)?,
+    }
+
+    for format in formats.iter().filter(|format| **format != Format::Html) {
+        if let Some(data) = representation(format) {
+            if !add_clipboard_data(format, &data) {
+                log::debug!(
+                    "Can't restore {} of item {} on this platform",
+                    format.mime(),
+                    item.id
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Saves a list of copied files and folders to the clipboard history database.
+///
+/// Similar to `save_text` function, except that the size and modification time of every file
+/// are saved too, so the history still describes them after they have been changed or deleted.
+///
+/// # Arguments
+///
+/// * `capture` - The source app of the files, and how they should be stored.
+/// * `paths` - The paths of the copied files, in the order they were offered.
+///
+/// Returns the id of the saved item.
+fn save_files(capture: &Capture, paths: &[PathBuf]) -> rusqlite::Result<i64> {
+    let conn = db_conn()?;
+    let tx = conn.unchecked_transaction()?;
+
+    let content = paths
+        .iter()
+        .map(|path| path.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let content_hash = hash_text(&content);
+
+    let id: i64 = tx.query_row(
+        "INSERT INTO history (source_app, icon_path, content_type, content, content_hash, size, redacted, expires_at)
+         VALUES (?1, ?2, 'FILES', ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (content_type, content_hash) DO UPDATE
+         SET timestamp = DATETIME('NOW', 'UTC'), source_app = excluded.source_app, icon_path = excluded.icon_path,
+             redacted = excluded.redacted, expires_at = excluded.expires_at, deleted_at = NULL
+         RETURNING id",
+        params![
+            capture.source_app,
+            capture.icon_path,
+            content,
+            content_hash,
+            content.len() as i64,
+            capture.redacted,
+            capture.expires_at,
+        ],
+        |row| row.get(0),
+    )?;
+
+    tx.execute("DELETE FROM item_files WHERE item_id = ?1", params![id])?;
+
+    for (position, path) in paths.iter().enumerate() {
+        let file = FileEntry::from_path(path);
+
+        tx.execute(
+            "INSERT INTO item_files (item_id, position, path, is_dir, size, modified)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                position as i64,
+                file.path.to_string_lossy(),
+                file.is_dir,
+                file.size as i64,
+                file.modified
+                    .map(|modified| modified.format(TIMESTAMP_FORMAT).to_string()),
+            ],
+        )?;
+    }
+
+    tx.commit()?;
+
+    Ok(id)
+}
+
+/// Get the files and folders of a `ContentTypes::Files` record, in the order they were copied.
+///
+/// # Arguments
+///
+/// * `id` - The unique identifier (Primary Key) of the history record.
+///
+/// # Example:
+/// ```
+/// use crate::backend::clipboard;
+///
+/// let files = clipboard::get_files(1);
+/// println!("{:?}", files); // Output: Ok([FileEntry { path: "/Users/foo/report.pdf", is_dir: false, size: 52133, modified: Some(2025-12-27T17:11:28Z) }])
+/// ```
+pub fn get_files(id: i64) -> rusqlite::Result<Vec<FileEntry>> {
+    let conn = db_conn()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT path, is_dir, size, modified
+         FROM item_files
+         WHERE item_id = ?1
+         ORDER BY position",
+    )?;
+
+    let files_iter = stmt.query_map(params![id], |row| {
+        let path: String = row.get(0)?;
+        let modified: Option<String> = row.get(3)?;
+
+        Ok(FileEntry {
+            path: PathBuf::from(path),
+            is_dir: row.get(1)?,
+            size: row.get::<_, i64>(2)? as u64,
+            modified: modified
+                .and_then(|modified| {
+                    NaiveDateTime::parse_from_str(&modified, TIMESTAMP_FORMAT).ok()
+                })
+                .map(|naive| Utc.from_utc_datetime(&naive)),
+        })
+    })?;
+
+    files_iter.collect()
+}
+
+/// Maps a raw database row to the `Item` struct.
+///
+/// # Arguments
+///
+/// * `row` - A reference to a single row result from a rusqlite query.
+fn row_to_item(row: &Row) -> rusqlite::Result<Item> {
+    let id: i64 = row.get(0)?;
+    let source_app: String = row.get(1)?;
+    let icon_path: String = row.get(2)?;
+    let content_type: String = row.get(3)?;
+    let content: ValueRef = row.get_ref(4)?;
+    let timestamp: String = row.get(5)?;
+    let content_hash: String = row.get(6)?;
+    let formats: Option<String> = row.get(7)?;
+    let redacted: bool = row.get(8)?;
+    let masked: bool = row.get(9)?;
+    let pinned: bool = row.get(10)?;
+    let note: String = row.get(11)?;
+    let tags: Option<String> = row.get(12)?;
+    let deleted_at: Option<String> = row.get(13)?;
+
+    let content_type = match content_type.as_str() {
+        "IMAGE" => ContentTypes::Image,
+        "TEXT" => ContentTypes::Text,
+        "FILES" => ContentTypes::Files,
+        _ => unreachable!(),
+    };
+
+    let content = match content_type {
+        ContentTypes::Image => String::new(),
+        ContentTypes::Text | ContentTypes::Files => match content {
+            ValueRef::Blob(bytes) | ValueRef::Text(bytes) => {
+                String::from_utf8_lossy(bytes).to_string()
+            }
+            _ => String::new(),
+        },
+    };
+
+    let timestamp = NaiveDateTime::parse_from_str(&timestamp, TIMESTAMP_FORMAT)
+        .map(|naive| Utc.from_utc_datetime(&naive))
+        .unwrap_or_else(|_| Utc::now());
+
+    Ok(Item {
+        id,
+        source_app,
+        icon_path,
+        content_type,
+        content,
+        content_hash,
+        timestamp,
+        formats: formats
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(Format::from_mime)
+            .collect(),
+        redacted,
+        masked,
+        pinned,
+        // Tags never contain whitespace, see `search::normalize_tag`
+        tags: tags
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+        note,
+        deleted_at: deleted_at
+            .and_then(|deleted_at| NaiveDateTime::parse_from_str(&deleted_at, TIMESTAMP_FORMAT).ok())
+            .map(|naive| Utc.from_utc_datetime(&naive)),
+    })
+}
+
+/// Unlock the clipboard history, opening (and if needed, encrypting) the database.
+///
+/// Does nothing if the history isn't locked.
+///
+/// # Arguments
+///
+/// * `passphrase` - The passphrase typed in by the user, only needed in `passphrase` mode.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::clipboard;
+///
+/// clipboard::unlock(Some("correct horse battery staple")).unwrap();
+/// assert!(!clipboard::is_locked());
+/// ```
+pub fn unlock(passphrase: Option<&str>) -> Result<(), EncryptionError> {
+    let mut conn = db_mutex().lock().unwrap();
+
+    if conn.is_none() {
+        *conn = Some(encryption::open(&storage::db_path(), passphrase)?);
+        log::info!("Unlocked clipboard history");
+    }
+
+    Ok(())
+}
+
+/// Lock the clipboard history, closing the database until `unlock` is called.
+///
+/// While locked, nothing is read from or recorded to the history.
+pub fn lock() {
+    *db_mutex().lock().unwrap() = None;
+    blobs::set_key(None);
+    log::info!("Locked clipboard history");
+}
+
+/// Whether the clipboard history is locked, see `lock`.
+pub fn is_locked() -> bool {
+    db_mutex().lock().unwrap().is_none()
+}
+
+#[cfg(not(test))]
+fn db_mutex() -> &'static Mutex<Option<Connection>> {
+    &DB_CONN
+}
+
+/// Every test gets a database of its own, in memory, as tests run on threads of their own.
+#[cfg(test)]
+fn db_mutex() -> &'static Mutex<Option<Connection>> {
+    thread_local! {
+        static TEST_DB_CONN: &'static Mutex<Option<Connection>> = {
+            let mut conn = Connection::open_in_memory().unwrap();
+            crate::backend::migrations::migrate(&mut conn).unwrap();
+            Box::leak(Box::new(Mutex::new(Some(conn))))
+        };
+    }
+
+    TEST_DB_CONN.with(|conn| *conn)
+}
+
+/// A connection to the SQLite database, held exclusively until dropped.
+pub(crate) struct DbConn(MutexGuard<'static, Option<Connection>>);
+
+impl Deref for DbConn {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        // `db_conn` never hands out a guard without a connection
+        self.0.as_ref().unwrap()
+    }
+}
+
+/// Get a connection to the SQLite database
+///
+/// Fails with `SQLITE_AUTH` while the clipboard history is locked.
+pub(crate) fn db_conn() -> rusqlite::Result<DbConn> {
+    let conn = db_mutex().lock().unwrap();
+
+    if conn.is_none() {
+        return Err(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_AUTH),
+            Some("the clipboard history is locked".to_string()),
+        ));
+    }
+
+    Ok(DbConn(conn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn capture() -> Capture {
+        Capture {
+            source_app: "Code".to_string(),
+            icon_path: String::new(),
+            redacted: false,
+            expires_at: None,
+        }
+    }
+
+    fn copy_text(content: &str) -> i64 {
+        save_text(&capture(), content, &[], None).unwrap().unwrap()
+    }
+
+    /// Walk every page of `filter`, calling `between_pages` before loading each next page,
+    /// and return the ids in the order they were listed.
+    fn walk_pages(filter: &str, mut between_pages: impl FnMut(usize)) -> Vec<i64> {
+        let mut ids = Vec::new();
+        let mut page = get_page(None, 4, filter).unwrap();
+
+        for page_number in 1.. {
+            ids.extend(page.items.iter().map(|item| item.id));
+
+            let Some(next) = page.next else {
+                break;
+            };
+            between_pages(page_number);
+            page = get_page(Some(&next), 4, filter).unwrap();
+        }
+
+        ids
+    }
+
+    fn ids(items: &[Item]) -> Vec<i64> {
+        items.iter().map(|item| item.id).collect()
+    }
+
+    fn search(filter: &str) -> Vec<i64> {
+        ids(&get_page(None, 10, filter).unwrap().items)
+    }
+
+    fn tags_of(id: i64) -> Vec<String> {
+        get_record(id).unwrap().unwrap().tags
+    }
+
+    fn tag_count() -> i64 {
+        db_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn is_pinned(id: i64) -> bool {
+        get_record(id).unwrap().unwrap().pinned
+    }
+
+    fn assert_listed_once(listed: &[i64], expected: &HashSet<i64>) {
+        let unique: HashSet<i64> = listed.iter().copied().collect();
+
+        assert_eq!(unique.len(), listed.len(), "repeated: {listed:?}");
+        assert_eq!(&unique, expected);
+    }
+
+    #[test]
+    fn copying_the_same_content_again_brings_the_item_back() {
+        let id = copy_text("hello world");
+        db_conn()
+            .unwrap()
+            .execute(
+                "UPDATE history SET timestamp = '2025-01-01 10:00:00', deleted_at = '2025-01-02 10:00:00'
+                 WHERE id = ?1",
+                params![id],
+            )
+            .unwrap();
+
+        assert_eq!(copy_text("hello world"), id);
+
+        let count: i64 = db_conn()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let item = get_record(id).unwrap().unwrap();
+        assert!(item.timestamp > Utc.with_ymd_and_hms(2025, 1, 1, 10, 0, 0).unwrap());
+        assert_eq!(item.deleted_at, None);
+    }
+
+    #[test]
+    fn the_locked_history_fails_with_sqlite_auth_until_unlocked() {
+        copy_text("hello world");
+
+        lock();
+
+        assert!(is_locked());
+        assert_eq!(
+            get_page(None, 10, "").unwrap_err().sqlite_error_code(),
+            Some(rusqlite::ErrorCode::AuthorizationForStatementDenied)
+        );
+        assert!(save_text(&capture(), "copied while locked", &[], None).is_err());
+
+        // Without encryption, the database of the test data directory opens without a passphrase
+        unlock(None).unwrap();
+
+        assert!(!is_locked());
+        assert!(get_page(None, 10, "").is_ok());
+    }
+
+    #[test]
+    fn items_stay_pinned_while_on_any_pinboard() {
+        let work = create_pinboard("Work").unwrap();
+        let home = create_pinboard("Home").unwrap();
+        let [first, second, third] = ["first", "second", "third"].map(copy_text);
+
+        for id in [first, second, third] {
+            pin(id, work).unwrap();
+        }
+        pin(third, home).unwrap();
+        // Pinning an item again leaves it where it is
+        pin(first, work).unwrap();
+
+        assert_eq!(
+            get_pinboards().unwrap(),
+            [
+                Pinboard {
+                    id: work,
+                    name: "Work".to_string()
+                },
+                Pinboard {
+                    id: home,
+                    name: "Home".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            ids(&get_pinboard_items(work, "").unwrap()),
+            [first, second, third]
+        );
+        assert_eq!(ids(&get_pinboard_items(home, "").unwrap()), [third]);
+        assert!(is_pinned(first) && is_pinned(third));
+
+        unpin(third, work).unwrap();
+        assert!(is_pinned(third));
+        unpin(third, home).unwrap();
+        assert!(!is_pinned(third));
+
+        delete_pinboard(work).unwrap();
+        assert!(!is_pinned(first));
+        assert!(get_record(first).unwrap().is_some());
+    }
+
+    #[test]
+    fn pinned_items_move_within_their_pinboard() {
+        let pinboard = create_pinboard("Pinned").unwrap();
+        let [first, second, third] = ["first", "second", "third"].map(copy_text);
+        for id in [first, second, third] {
+            pin(id, pinboard).unwrap();
+        }
+        let order = || ids(&get_pinboard_items(pinboard, "").unwrap());
+
+        move_pinned_item(third, pinboard, -1).unwrap();
+        assert_eq!(order(), [first, third, second]);
+
+        // Items stop at either end
+        move_pinned_item(first, pinboard, 10).unwrap();
+        assert_eq!(order(), [third, second, first]);
+        move_pinned_item(second, pinboard, -10).unwrap();
+        assert_eq!(order(), [second, third, first]);
+
+        // New pins go after the moved items
+        let fourth = copy_text("fourth");
+        pin(fourth, pinboard).unwrap();
+        assert_eq!(order(), [second, third, first, fourth]);
+
+        // Items that aren't on the pinboard can't be moved there
+        move_pinned_item(copy_text("unpinned"), pinboard, -1).unwrap();
+        assert_eq!(order(), [second, third, first, fourth]);
+    }
+
+    #[test]
+    fn pinned_items_survive_pruning() {
+        let pinboard = create_pinboard("Pinned").unwrap();
+        let pinned = copy_text("pinned");
+        pin(pinned, pinboard).unwrap();
+        let unpinned = copy_text("unpinned");
+
+        let policy = config::RetentionPolicy {
+            max_items: Some(0),
+            max_text_bytes: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(
+            crate::backend::retention::prune(&db_conn().unwrap(), &policy).unwrap(),
+            1
+        );
+
+        assert!(get_record(unpinned).unwrap().is_none());
+        assert_eq!(ids(&get_pinboard_items(pinboard, "").unwrap()), [pinned]);
+    }
+
+    #[test]
+    fn tags_are_cleaned_up_and_searchable() {
+        let first = copy_text("first");
+        let second = copy_text("second");
+
+        let tags = ["#Work", "  todo list ", "#"].map(String::from);
+        set_tags(first, &tags).unwrap();
+        // Tags are matched regardless of their case, and keep the name they were created with
+        set_tags(second, &["work".to_string()]).unwrap();
+
+        assert_eq!(tags_of(first), ["todo-list", "Work"]);
+        assert_eq!(tags_of(second), ["Work"]);
+        assert_eq!(search("#work"), [second, first]);
+        assert_eq!(search("#todo-list"), [first]);
+
+        set_tags(first, &[]).unwrap();
+
+        assert!(tags_of(first).is_empty());
+        assert_eq!(search("#work"), [second]);
+        assert!(search("#todo-list").is_empty());
+        assert_eq!(tag_count(), 1);
+    }
+
+    #[test]
+    fn renaming_a_tag_to_an_existing_one_merges_them() {
+        let todo = copy_text("todo");
+        let both = copy_text("both");
+        let later = copy_text("later");
+        set_tags(todo, &["todo".to_string()]).unwrap();
+        set_tags(both, &["todo".to_string(), "later".to_string()]).unwrap();
+        set_tags(later, &["later".to_string()]).unwrap();
+
+        rename_tag("#todo", "Later").unwrap();
+
+        for id in [todo, both, later] {
+            assert_eq!(tags_of(id), ["Later"]);
+        }
+        assert_eq!(search("#later"), [later, both, todo]);
+        assert!(search("#todo").is_empty());
+        assert_eq!(tag_count(), 1);
+
+        rename_tag("later", "some day").unwrap();
+
+        assert_eq!(tags_of(both), ["some-day"]);
+        assert_eq!(search("#some-day"), [later, both, todo]);
+        assert!(search("#later").is_empty());
+    }
+
+    #[test]
+    fn notes_are_searchable() {
+        let id = copy_text("4f9a2c71e8");
+        copy_text("unrelated");
+
+        set_note(id, "  staging API key ").unwrap();
+
+        assert_eq!(get_record(id).unwrap().unwrap().note, "staging API key");
+        assert_eq!(search("staging"), [id]);
+
+        set_note(id, "").unwrap();
+
+        assert!(search("staging").is_empty());
+    }
+
+    #[test]
+    fn restored_items_are_listed_again() {
+        let [kept, restored, trashed] = ["kept", "restored", "trashed"].map(copy_text);
+
+        assert_eq!(delete_many(&[restored, trashed, 999]).unwrap(), 2);
+        assert_eq!(search(""), [kept]);
+        assert_eq!(ids(&get_trash().unwrap()), [trashed, restored]);
+
+        // Only items in the trash are counted as restored
+        assert_eq!(restore_items(&[restored, kept]).unwrap(), 1);
+
+        assert_eq!(search(""), [restored, kept]);
+        assert_eq!(ids(&get_trash().unwrap()), [trashed]);
+    }
+
+    #[test]
+    fn emptying_the_trash_deletes_the_images_of_its_items() {
+        let image = |color: u8| ImageData {
+            width: 1,
+            height: 1,
+            bytes: vec![color, 0, 0, 255].into(),
+        };
+        let trashed_image = image(255);
+        let kept_image = image(0);
+        let trashed = save_image(&capture(), &trashed_image).unwrap();
+        save_image(&capture(), &kept_image).unwrap();
+        let trashed_hash = hash_image(&trashed_image);
+        let kept_hash = hash_image(&kept_image);
+
+        delete_many(&[trashed]).unwrap();
+        // Images stay in the blob store while they can be restored
+        assert!(blobs::image_path(&trashed_hash).exists());
+
+        assert_eq!(empty_trash().unwrap(), 1);
+
+        assert!(get_record(trashed).unwrap().is_none());
+        assert!(!blobs::image_path(&trashed_hash).exists());
+        assert!(!blobs::thumbnail_path(&trashed_hash).exists());
+        assert!(blobs::image_path(&kept_hash).exists());
+        assert!(blobs::thumbnail_path(&kept_hash).exists());
+    }
+
+    #[test]
+    fn pages_list_every_item_once() {
+        let ids: HashSet<i64> = (0..10)
+            .map(|i| copy_text(&format!("note {i}")))
+            .collect();
+
+        // Items copied while paging are on top of the history, before the first page
+        let listed = walk_pages("", |page_number| {
+            copy_text(&format!("copied after page {page_number}"));
+        });
+
+        assert_listed_once(&listed, &ids);
+    }
+
+    #[test]
+    fn search_pages_list_every_result_once() {
+        // Different term frequencies, so that the results have different ranks
+        let ids: HashSet<i64> = (0..10)
+            .map(|i| copy_text(&format!("{} note {i}", "apple ".repeat(i % 3 + 1))))
+            .collect();
+        copy_text("banana");
+
+        // New results change the BM25 rank of every other one
+        let listed = walk_pages("apple", |page_number| {
+            copy_text(&format!("apple apple apple apple {page_number}"));
+        });
+
+        assert_listed_once(&listed, &ids);
+    }
+
+    #[test]
+    fn search_pages_are_ordered_by_relevance() {
+        let once = copy_text("apple pie recipe with cinnamon and sugar");
+        let twice = copy_text("apple apple");
+
+        let page = get_page(None, 10, "apple").unwrap();
+
+        assert_eq!(
+            page.items.iter().map(|item| item.id).collect::<Vec<_>>(),
+            [twice, once]
+        );
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn deleted_results_are_skipped_by_the_next_pages() {
+        let ids: Vec<i64> = (0..6).map(|i| copy_text(&format!("apple {i}"))).collect();
+
+        let first = get_page(None, 3, "apple").unwrap();
+        let first_ids: Vec<i64> = first.items.iter().map(|item| item.id).collect();
+        let left: Vec<i64> = ids.iter().copied().filter(|id| !first_ids.contains(id)).collect();
+        delete_many(&left[..2]).unwrap();
+
+        let second = get_page(first.next.as_ref(), 3, "apple").unwrap();
+
+        assert_eq!(
+            second.items.iter().map(|item| item.id).collect::<Vec<_>>(),
+            left[2..]
+        );
+        assert_eq!(second.next, None);
+    }
 }
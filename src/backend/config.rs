@@ -0,0 +1,266 @@
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::fs;
+
+use crate::backend::storage;
+
+static CONFIG: Lazy<AppConfig> = Lazy::new(load);
+
+/// User settings, read from `config.toml` inside `storage::data_dir()`.
+///
+/// Every setting is optional. A missing or invalid file falls back to the defaults,
+/// which keep the behaviour of the app as if there was no config at all.
+///
+/// # Example
+///
+/// ```toml
+/// [retention]
+/// max_age_days = 30
+/// max_items = 5000
+///
+/// [[privacy.rules]]
+/// app = "1Password"
+/// action = "ignore"
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub retention: RetentionPolicy,
+    pub privacy: PrivacyPolicy,
+    pub secrets: SecretPolicy,
+    pub encryption: EncryptionPolicy,
+    pub paste: PastePolicy,
+}
+
+/// How long clipboard history is kept. Pinned items are never pruned.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Items last copied more than this many days ago are deleted.
+    pub max_age_days: Option<u64>,
+    /// Only this many of the most recent items are kept.
+    pub max_items: Option<u64>,
+    /// Total size of all text and file list items, in bytes.
+    pub max_text_bytes: Option<u64>,
+    /// Total size of all image items, in bytes.
+    pub max_image_bytes: Option<u64>,
+    /// How often the history is pruned, in minutes.
+    pub prune_interval_minutes: u64,
+    /// Items deleted from the `Paste` window are kept in the trash for this many days.
+    pub empty_trash_after_days: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            max_age_days: None,
+            max_items: None,
+            max_text_bytes: None,
+            max_image_bytes: None,
+            prune_interval_minutes: 60,
+            empty_trash_after_days: 30,
+        }
+    }
+}
+
+/// Which apps the clipboard is recorded from. See `privacy::action_for`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PrivacyPolicy {
+    /// What happens to copies from apps no rule matches.
+    pub mode: PrivacyMode,
+    /// Checked in order, the first rule matching the focused app decides what happens to its copies.
+    pub rules: Vec<AppRule>,
+    /// What happens to copies flagged by the app that made them, whichever app that is.
+    pub markers: MarkerPolicy,
+}
+
+/// What happens to copies flagged with the markers of nspasteboard.org (macOS) or KDE (Linux),
+/// which password managers and other apps put next to what they copy.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct MarkerPolicy {
+    /// Passwords and other sensitive data.
+    pub concealed: RuleAction,
+    /// Data only put on the clipboard for a moment, e.g. by a text expander.
+    pub transient: RuleAction,
+    /// Data the app copied on its own, without any action of the user.
+    pub auto_generated: RuleAction,
+    /// How long copies are kept with the `expire` action, in minutes.
+    pub expire_after_minutes: Option<u64>,
+}
+
+impl Default for MarkerPolicy {
+    fn default() -> Self {
+        MarkerPolicy {
+            concealed: RuleAction::Ignore,
+            transient: RuleAction::Ignore,
+            auto_generated: RuleAction::Ignore,
+            expire_after_minutes: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivacyMode {
+    /// Record every app, except the ones matched by a rule.
+    #[default]
+    Blocklist,
+    /// Only record the apps matched by a `record` rule.
+    Allowlist,
+}
+
+/// Matches the focused app by any combination of its name, identifier, path and window title.
+///
+/// Every field that is set has to match. A rule without any of them never matches.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AppRule {
+    /// A part of the app name, case-insensitive.
+    pub app: Option<String>,
+    /// The bundle identifier on macOS (e.g. `com.1password.1password`), the `WM_CLASS` on Linux.
+    pub app_id: Option<String>,
+    /// A part of the app path: the bundle path on macOS, the executable path on Linux.
+    pub path: Option<String>,
+    /// A regular expression matched against the title of the focused window.
+    pub window_title: Option<String>,
+    pub action: RuleAction,
+    /// How long copies are kept with the `expire` action, in minutes.
+    pub expire_after_minutes: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Save copies as usual.
+    Record,
+    /// Don't save copies at all.
+    #[default]
+    Ignore,
+    /// Save copies, but hide their content in the history.
+    Redact,
+    /// Save copies, but delete them after `expire_after_minutes`.
+    Expire,
+}
+
+/// What happens to copied text that contains a secret, per kind of secret. See `secrets::detect`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SecretPolicy {
+    pub aws_access_key: SecretAction,
+    pub github_token: SecretAction,
+    pub gitlab_token: SecretAction,
+    pub jwt: SecretAction,
+    pub private_key: SecretAction,
+    pub card_number: SecretAction,
+    /// Long random-looking tokens, e.g. API keys of services without a recognizable prefix.
+    pub high_entropy: SecretAction,
+    /// How long copies are kept with the `expire` action, in minutes.
+    pub expire_after_minutes: u64,
+}
+
+impl Default for SecretPolicy {
+    fn default() -> Self {
+        SecretPolicy {
+            aws_access_key: SecretAction::Skip,
+            github_token: SecretAction::Skip,
+            gitlab_token: SecretAction::Skip,
+            jwt: SecretAction::Skip,
+            private_key: SecretAction::Skip,
+            card_number: SecretAction::Skip,
+            high_entropy: SecretAction::Off,
+            expire_after_minutes: 10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretAction {
+    /// Don't look for this kind of secret.
+    Off,
+    /// Don't save the text at all.
+    Skip,
+    /// Save the text, but hide the secret in the preview.
+    Mask,
+    /// Save the text, but delete it after `expire_after_minutes`.
+    Expire,
+}
+
+/// Whether the clipboard history database is encrypted on disk. See `encryption::open`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct EncryptionPolicy {
+    pub mode: EncryptionMode,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EncryptionMode {
+    /// Store the database in plain text.
+    #[default]
+    Off,
+    /// Encrypt the database with a random key kept in the OS keyring (Keychain, Secret Service).
+    Keyring,
+    /// Encrypt the database with a key derived from a passphrase, typed in to unlock the history.
+    Passphrase,
+}
+
+/// What happens after an item is picked in the `Paste` window. See `paste::paste_into_previous_app`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PastePolicy {
+    pub mode: PasteMode,
+    /// How long the app focused before the `Paste` window gets to take the focus back, in milliseconds.
+    pub delay_ms: u64,
+}
+
+impl Default for PastePolicy {
+    fn default() -> Self {
+        PastePolicy {
+            mode: PasteMode::Auto,
+            delay_ms: 150,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PasteMode {
+    /// Put the item on the clipboard and paste it into the app focused before the `Paste` window opened.
+    #[default]
+    Auto,
+    /// Only put the item on the clipboard, and give the focus back to that app.
+    Copy,
+}
+
+/// Returns the user settings, loading them on first use.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::config;
+///
+/// println!("{:?}", config::get().retention.max_items); // Output: Some(5000)
+/// ```
+pub fn get() -> &'static AppConfig {
+    &CONFIG
+}
+
+fn load() -> AppConfig {
+    let path = storage::config_path();
+
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return AppConfig::default();
+    };
+
+    toml::from_str(&contents).unwrap_or_else(|err| {
+        log::error!(
+            "Failed to parse {}, using defaults: {}",
+            path.display(),
+            err
+        );
+        AppConfig::default()
+    })
+}
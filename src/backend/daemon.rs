@@ -0,0 +1,599 @@
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::backend::blobs::{self, DisplayFile};
+use crate::backend::clipboard;
+use crate::backend::encryption::{self, EncryptionError};
+use crate::backend::merge::{self, MergeError};
+use crate::backend::retention;
+use crate::backend::snippets::{self, SnippetError};
+use crate::backend::storage;
+use crate::backend::transforms::{self, TransformError};
+use crate::backend::utils::is_valid_hash;
+
+/// Command-line flag running the app as a headless daemon, e.g. `paste-fork --daemon`.
+pub const DAEMON_FLAG: &str = "--daemon";
+
+/// The default number of items returned by `list`.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// How many lines can wait to be written to a connection. A subscriber that falls further behind is dropped.
+const OUTGOING_QUEUE_SIZE: usize = 64;
+
+/// How long writing to a connection may block before it is closed.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connections that called `subscribe`, notified of every item saved to the history.
+static SUBSCRIBERS: Lazy<Mutex<Vec<Subscriber>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// A connection that called `subscribe`.
+struct Subscriber {
+    outgoing: SyncSender<String>, // The lines to write to the connection, see `handle_connection`
+    stream: UnixStream,           // To hang up on it once dropped
+}
+
+/// A JSON-RPC 2.0 request, sent as a single line of JSON.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// A JSON-RPC 2.0 response, sent back as a single line of JSON.
+///
+/// Notifications sent to subscribers have a `method` and `params` instead of an `id` and `result`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    /// The request isn't valid JSON.
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// The request was valid, but failed, e.g. because the history is locked.
+    pub const SERVER_ERROR: i64 = -32000;
+    /// The daemon couldn't be reached, only ever returned by the client.
+    pub const UNREACHABLE: i64 = -32001;
+
+    pub fn new(code: i64, message: impl fmt::Display) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<rusqlite::Error> for RpcError {
+    fn from(err: rusqlite::Error) -> Self {
+        RpcError::new(RpcError::SERVER_ERROR, err)
+    }
+}
+
+impl From<EncryptionError> for RpcError {
+    fn from(err: EncryptionError) -> Self {
+        RpcError::new(RpcError::SERVER_ERROR, err)
+    }
+}
+
+impl From<MergeError> for RpcError {
+    fn from(err: MergeError) -> Self {
+        RpcError::new(RpcError::SERVER_ERROR, err)
+    }
+}
+
+impl From<SnippetError> for RpcError {
+    fn from(err: SnippetError) -> Self {
+        RpcError::new(RpcError::SERVER_ERROR, err)
+    }
+}
+
+impl From<TransformError> for RpcError {
+    fn from(err: TransformError) -> Self {
+        RpcError::new(RpcError::INVALID_PARAMS, err)
+    }
+}
+
+impl From<arboard::Error> for RpcError {
+    fn from(err: arboard::Error) -> Self {
+        RpcError::new(RpcError::SERVER_ERROR, err)
+    }
+}
+
+/// Run the clipboard capture loop, the retention pruner and the JSON-RPC server, without any window.
+///
+/// Clients (the `Paste` window, scripts...) talk to the daemon over the Unix socket at
+/// `storage::socket_path()`, one JSON-RPC request per line. See `dispatch` for the methods.
+/// This function only returns if another daemon already serves the same database.
+///
+/// # Example
+///
+/// ```shell
+/// $ paste-fork --daemon &
+/// $ echo '{"jsonrpc": "2.0", "id": 1, "method": "list", "params": {"limit": 1}}' | nc -U ~/.local/share/paste-fork/clipboard.sock
+/// {"jsonrpc":"2.0","id":1,"result":[{"id":42,"source_app":"Code",...}]}
+/// ```
+pub fn run() {
+    let path = storage::socket_path();
+    encryption::allow_encrypting_in_place();
+
+    // A socket nobody answers on was left behind by a daemon that didn't exit cleanly
+    if UnixStream::connect(&path).is_ok() {
+        log::error!("A daemon is already listening on {}", path.display());
+        return;
+    }
+    let _ = fs::remove_file(&path);
+
+    // Open the database (encrypting it if needed) before clients can connect, so they never see it in plain text
+    if clipboard::is_locked() {
+        log::info!("Clipboard history is locked");
+    }
+
+    let listener = match bind_private(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::error!("Failed to bind {}: {}", path.display(), err);
+            return;
+        }
+    };
+    log::info!("Daemon listening on {}", path.display());
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<i64>();
+    thread::spawn(move || clipboard::listen(tx));
+    thread::spawn(retention::run_pruner);
+
+    // Forward every saved item to the subscribers, dropping the ones that disconnected or stopped reading
+    thread::spawn(move || {
+        while let Some(id) = rx.blocking_recv() {
+            let notification = Response {
+                jsonrpc: "2.0".to_string(),
+                method: Some("item_saved".to_string()),
+                params: json!({ "id": id }),
+                ..Default::default()
+            };
+            let line = serde_json::to_string(&notification).unwrap();
+
+            SUBSCRIBERS
+                .lock()
+                .unwrap()
+                .retain(|subscriber| match subscriber.outgoing.try_send(line.clone()) {
+                    Ok(_) => true,
+                    Err(TrySendError::Full(_)) => {
+                        log::warn!("Dropping a subscriber that stopped reading");
+                        let _ = subscriber.stream.shutdown(Shutdown::Both);
+                        false
+                    }
+                    Err(TrySendError::Disconnected(_)) => false,
+                });
+        }
+    });
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    if let Err(err) = handle_connection(stream) {
+                        log::debug!("Daemon connection closed: {}", err);
+                    }
+                });
+            }
+            Err(err) => log::error!("Failed to accept daemon connection: {}", err),
+        }
+    }
+}
+
+/// Bind the socket at `path`, only accessible to the current user.
+///
+/// A socket is created with the permissions of the umask, so it is bound in a private directory
+/// first and only moved to `path` once restricted.
+fn bind_private(path: &Path) -> io::Result<UnixListener> {
+    let private_dir = path.with_extension("sock.tmp");
+    let _ = fs::remove_dir_all(&private_dir);
+    fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+
+    let tmp_path = private_dir.join("socket");
+    let listener = UnixListener::bind(&tmp_path).and_then(|listener| {
+        fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&tmp_path, path)?;
+        Ok(listener)
+    });
+
+    let _ = fs::remove_dir_all(&private_dir);
+    listener
+}
+
+/// Answer every request of a connection, until the client disconnects.
+///
+/// Responses and notifications (see `SUBSCRIBERS`) are written by a thread of their own,
+/// so the lines of both never interleave.
+fn handle_connection(stream: UnixStream) -> io::Result<()> {
+    let (outgoing, lines) = sync_channel::<String>(OUTGOING_QUEUE_SIZE);
+
+    let mut writer = stream.try_clone()?;
+    writer.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    thread::spawn(move || {
+        for line in lines {
+            if writeln!(writer, "{}", line).is_err() {
+                let _ = writer.shutdown(Shutdown::Both);
+                return;
+            }
+        }
+    });
+
+    for line in BufReader::new(stream.try_clone()?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) if request.method == "subscribe" => {
+                SUBSCRIBERS.lock().unwrap().push(Subscriber {
+                    outgoing: outgoing.clone(),
+                    stream: stream.try_clone()?,
+                });
+                respond(request.id, Ok(Value::Bool(true)))
+            }
+            Ok(request) => {
+                log::trace!("Daemon request: {}", request.method);
+                respond(request.id, dispatch(&request.method, &request.params))
+            }
+            Err(err) => respond(
+                Value::Null,
+                Err(RpcError::new(RpcError::PARSE_ERROR, err)),
+            ),
+        };
+
+        // Only fails once the writer gave up on the connection
+        outgoing
+            .send(serde_json::to_string(&response).unwrap())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"))?;
+    }
+
+    Ok(())
+}
+
+fn respond(id: Value, result: Result<Value, RpcError>) -> Response {
+    let (result, error) = match result {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+
+    Response {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result,
+        error,
+        ..Default::default()
+    }
+}
+
+/// Run a JSON-RPC method, with its named `params`.
+///
+/// | Method | Params | Result |
+/// |---|---|---|
+/// | `list` | `after`?, `limit`?, `query`? | A page of items and the cursor of the next one, see `clipboard::get_page` |
+/// | `search` | `query` | Every matching item, see `clipboard::search_text` |
+/// | `get` | `id` | The item, or `null` |
+/// | `get_files` | `id` | The files of a `FILES` item |
+/// | `display_file` | `path` | The thumbnail or icon at the path of a `blobs::thumbnail_url` or `blobs::icon_url` |
+/// | `set_clipboard` | `id`, `plain_text`?, `transform`? | Puts the item (as plain text once transformed, see `transforms::apply`) on the system clipboard and bumps it |
+/// | `delete` | `ids` | Moves the items to the trash, returns how many were moved |
+/// | `restore` | `ids` | Moves the items out of the trash, returns how many were restored |
+/// | `trash` | | The items in the trash, most recently deleted first |
+/// | `empty_trash` | | Deletes the items in the trash for good, returns how many were deleted |
+/// | `merge` | `ids`, `separator`?, `layout`? | The id of the new item, see `merge::merge` |
+/// | `snippets` | `query`? | The snippets matching the query, see `snippets::search` |
+/// | `save_snippet` | `name`, `content` | The id of the snippet |
+/// | `delete_snippet` | `id` | |
+/// | `copy_snippet` | `id`, `inputs`? | Puts the rendered snippet on the system clipboard, see `snippets::write_to_clipboard` |
+/// | `pin` / `unpin` | `id`, `pinboard_id` | |
+/// | `pinboards` | | Every pinboard |
+/// | `create_pinboard` | `name` | The id of the new pinboard |
+/// | `delete_pinboard` | `id` | |
+/// | `pinboard_items` | `pinboard_id`, `query`? | The items of the pinboard |
+/// | `move_pinned_item` | `id`, `pinboard_id`, `offset` | |
+/// | `set_tags` | `id`, `tags` | |
+/// | `set_note` | `id`, `note` | |
+/// | `rename_tag` | `name`, `new_name` | |
+/// | `lock` / `unlock` / `is_locked` | `passphrase`? (`unlock`) | `is_locked` returns a boolean |
+/// | `subscribe` | | `true`, then an `item_saved` notification with the `id` of every saved item |
+fn dispatch(method: &str, params: &Value) -> Result<Value, RpcError> {
+    let result = match method {
+        "list" => json!(clipboard::get_page(
+            param::<Option<clipboard::PageCursor>>(params, "after")?.as_ref(),
+            param::<Option<i64>>(params, "limit")?.unwrap_or(DEFAULT_PAGE_SIZE),
+            &param::<Option<String>>(params, "query")?.unwrap_or_default(),
+        )?),
+        "search" => json!(clipboard::search_text(&param::<String>(params, "query")?)?),
+        "get" => json!(clipboard::get_record(param(params, "id")?)?),
+        "get_files" => json!(clipboard::get_files(param(params, "id")?)?),
+        "display_file" => json!(display_file(&param::<String>(params, "path")?)?),
+        "set_clipboard" => {
+            let id = param(params, "id")?;
+            let mut item = clipboard::get_record(id)?
+                .ok_or_else(|| RpcError::new(RpcError::INVALID_PARAMS, "no such item"))?;
+            let mut as_plain_text = param::<Option<bool>>(params, "plain_text")?.unwrap_or(false);
+
+            // Only the pasted text is transformed, the item stays as it was copied
+            if let Some(transform) = param::<Option<String>>(params, "transform")? {
+                if item.content_type == clipboard::ContentTypes::Image {
+                    return Err(RpcError::new(
+                        RpcError::INVALID_PARAMS,
+                        "images can't be transformed",
+                    ));
+                }
+
+                item.content = transforms::apply(&transform, &item.content)?;
+                item.content_type = clipboard::ContentTypes::Text;
+                as_plain_text = true;
+            }
+
+            clipboard::write_to_clipboard(&item, as_plain_text)?;
+            clipboard::update_timestamp(id)?;
+            Value::Null
+        }
+        "delete" => json!(clipboard::delete_many(&param::<Vec<i64>>(params, "ids")?)?),
+        "restore" => json!(clipboard::restore_items(&param::<Vec<i64>>(params, "ids")?)?),
+        "trash" => json!(clipboard::get_trash()?),
+        "empty_trash" => json!(clipboard::empty_trash()?),
+        "merge" => json!(merge::merge(
+            &param::<Vec<i64>>(params, "ids")?,
+            &param::<Option<merge::Separator>>(params, "separator")?.unwrap_or_default(),
+            param::<Option<merge::CollageLayout>>(params, "layout")?.unwrap_or_default(),
+        )?),
+        "snippets" => json!(snippets::search(
+            &param::<Option<String>>(params, "query")?.unwrap_or_default()
+        )?),
+        "save_snippet" => json!(snippets::save_snippet(
+            &param::<String>(params, "name")?,
+            &param::<String>(params, "content")?,
+        )?),
+        "delete_snippet" => json!(snippets::delete_snippet(param(params, "id")?)?),
+        "copy_snippet" => json!(snippets::write_to_clipboard(
+            param(params, "id")?,
+            &param::<Option<HashMap<String, String>>>(params, "inputs")?.unwrap_or_default(),
+        )?),
+        "pin" => json!(clipboard::pin(
+            param(params, "id")?,
+            param(params, "pinboard_id")?
+        )?),
+        "unpin" => json!(clipboard::unpin(
+            param(params, "id")?,
+            param(params, "pinboard_id")?
+        )?),
+        "pinboards" => json!(clipboard::get_pinboards()?),
+        "create_pinboard" => json!(clipboard::create_pinboard(&param::<String>(
+            params, "name"
+        )?)?),
+        "delete_pinboard" => json!(clipboard::delete_pinboard(param(params, "id")?)?),
+        "pinboard_items" => json!(clipboard::get_pinboard_items(
+            param(params, "pinboard_id")?,
+            &param::<Option<String>>(params, "query")?.unwrap_or_default(),
+        )?),
+        "move_pinned_item" => json!(clipboard::move_pinned_item(
+            param(params, "id")?,
+            param(params, "pinboard_id")?,
+            param(params, "offset")?,
+        )?),
+        "set_tags" => json!(clipboard::set_tags(
+            param(params, "id")?,
+            &param::<Vec<String>>(params, "tags")?,
+        )?),
+        "set_note" => json!(clipboard::set_note(
+            param(params, "id")?,
+            &param::<String>(params, "note")?,
+        )?),
+        "rename_tag" => json!(clipboard::rename_tag(
+            &param::<String>(params, "name")?,
+            &param::<String>(params, "new_name")?,
+        )?),
+        "lock" => json!(clipboard::lock()),
+        "unlock" => json!(clipboard::unlock(
+            param::<Option<String>>(params, "passphrase")?.as_deref()
+        )?),
+        "is_locked" => json!(clipboard::is_locked()),
+        _ => {
+            return Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("unknown method {}", method),
+            ))
+        }
+    };
+
+    Ok(result)
+}
+
+/// Read the file a `blobs::thumbnail_url` or a `blobs::icon_url` points at, from the path of the URL.
+///
+/// Only the thumbnails of images and the icons of items can be read this way, never any other file.
+fn display_file(path: &str) -> Result<DisplayFile, RpcError> {
+    let not_found = || RpcError::new(RpcError::INVALID_PARAMS, format!("no file at {}", path));
+
+    let file = match path.trim_start_matches('/').split_once('/') {
+        Some(("thumbnail", content_hash)) if is_valid_hash(content_hash) => {
+            blobs::thumbnail_path(content_hash)
+        }
+        Some(("icon", id)) => {
+            let id = id.parse().map_err(|_| not_found())?;
+            match clipboard::get_record(id)? {
+                Some(item) if !item.icon_path.is_empty() => PathBuf::from(item.icon_path),
+                _ => return Err(not_found()),
+            }
+        }
+        _ => return Err(not_found()),
+    };
+
+    DisplayFile::read(&file).map_err(|err| RpcError::new(RpcError::SERVER_ERROR, err))
+}
+
+/// Read the named parameter `name`. A missing parameter is read as `null`, which is only valid for `Option`s.
+fn param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<T, RpcError> {
+    let value = params.get(name).cloned().unwrap_or(Value::Null);
+
+    serde_json::from_value(value).map_err(|err| {
+        RpcError::new(
+            RpcError::INVALID_PARAMS,
+            format!("invalid param {}: {}", name, err),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::params;
+
+    /// Save a text item straight to the (in-memory) history of the test.
+    fn insert_text(content: &str) -> i64 {
+        clipboard::db_conn()
+            .unwrap()
+            .query_row(
+                "INSERT INTO history (source_app, icon_path, content_type, content, content_hash, size)
+                 VALUES ('Code', '', 'TEXT', ?1, HEX(RANDOMBLOB(16)), ?2)
+                 RETURNING id",
+                params![content, content.len() as i64],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    fn error_code(method: &str, params: Value) -> i64 {
+        dispatch(method, &params).unwrap_err().code
+    }
+
+    #[test]
+    fn methods_are_routed_to_the_history() {
+        let id = insert_text("hello");
+
+        let page = dispatch("list", &json!({ "limit": 10 })).unwrap();
+        assert_eq!(page["items"][0]["id"], id);
+        assert_eq!(page["items"][0]["content"], "hello");
+
+        assert_eq!(
+            dispatch("set_tags", &json!({ "id": id, "tags": ["work"] })).unwrap(),
+            Value::Null
+        );
+        assert_eq!(
+            dispatch("get", &json!({ "id": id })).unwrap()["tags"],
+            json!(["work"])
+        );
+
+        let pinboard_id = dispatch("create_pinboard", &json!({ "name": "Work" })).unwrap();
+        dispatch("pin", &json!({ "id": id, "pinboard_id": pinboard_id })).unwrap();
+        assert_eq!(
+            dispatch("pinboard_items", &json!({ "pinboard_id": pinboard_id })).unwrap()[0]["id"],
+            id
+        );
+
+        assert_eq!(dispatch("delete", &json!({ "ids": [id] })).unwrap(), 1);
+        assert_eq!(dispatch("trash", &Value::Null).unwrap()[0]["id"], id);
+        assert_eq!(dispatch("get", &json!({ "id": 999 })).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn unknown_methods_are_not_found() {
+        assert_eq!(
+            error_code("drop_table", json!({})),
+            RpcError::METHOD_NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn missing_or_mistyped_params_are_invalid() {
+        assert_eq!(error_code("get", json!({})), RpcError::INVALID_PARAMS);
+        assert_eq!(
+            error_code("get", json!({ "id": "1" })),
+            RpcError::INVALID_PARAMS
+        );
+        assert_eq!(
+            error_code("list", json!({ "limit": "ten" })),
+            RpcError::INVALID_PARAMS
+        );
+        assert_eq!(
+            error_code("set_clipboard", json!({ "id": 999 })),
+            RpcError::INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn only_thumbnails_and_icons_are_displayed() {
+        let id = insert_text("hello");
+
+        for path in [
+            "/../../etc/passwd".to_string(),
+            "/thumbnail/../../clipboard.db".to_string(),
+            "/icon/not-an-id".to_string(),
+            format!("/icon/{}", id), // The item has no icon
+        ] {
+            assert_eq!(
+                error_code("display_file", json!({ "path": path })),
+                RpcError::INVALID_PARAMS
+            );
+        }
+    }
+
+    #[test]
+    fn the_locked_history_fails_until_unlocked() {
+        let id = insert_text("hello");
+
+        dispatch("lock", &Value::Null).unwrap();
+
+        assert_eq!(dispatch("is_locked", &Value::Null).unwrap(), true);
+        assert_eq!(
+            error_code("get", json!({ "id": id })),
+            RpcError::SERVER_ERROR
+        );
+        // Params are still checked first
+        assert_eq!(error_code("get", json!({})), RpcError::INVALID_PARAMS);
+
+        dispatch("unlock", &json!({})).unwrap();
+
+        assert_eq!(dispatch("is_locked", &Value::Null).unwrap(), false);
+        assert!(dispatch("list", &json!({})).is_ok());
+    }
+}
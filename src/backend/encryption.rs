@@ -0,0 +1,370 @@
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::backend::blobs;
+use crate::backend::config::{self, EncryptionMode};
+use crate::backend::migrations::{self, MigrationError};
+
+/// The service name the database key is stored under in the OS keyring.
+const KEYRING_SERVICE: &str = "paste-fork";
+
+/// How long a write waits for another process (e.g. the command line) to finish its own.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The first bytes of every SQLite database that isn't encrypted.
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Whether `open` may encrypt a database still in plain text, see `encrypt_in_place`.
+///
+/// Only the daemon does: the file is replaced, so any other process using it at the same time
+/// would keep reading and writing the plain text database, and lose its writes.
+static CAN_ENCRYPT_IN_PLACE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    /// The key or passphrase doesn't decrypt the database.
+    WrongKey,
+    /// The database is encrypted with a passphrase, but none was given.
+    MissingPassphrase,
+    /// Encryption is enabled, but the database is still in plain text and only the daemon encrypts it.
+    NotEncryptedYet,
+    Keyring(keyring::Error),
+    Io(io::Error),
+    Migration(MigrationError),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::WrongKey => write!(f, "wrong key or passphrase"),
+            EncryptionError::MissingPassphrase => write!(f, "a passphrase is required"),
+            EncryptionError::NotEncryptedYet => write!(
+                f,
+                "the database isn't encrypted yet, unlock the history in the app first"
+            ),
+            EncryptionError::Keyring(err) => write!(f, "keyring: {}", err),
+            EncryptionError::Io(err) => write!(f, "{}", err),
+            EncryptionError::Migration(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+impl From<keyring::Error> for EncryptionError {
+    fn from(err: keyring::Error) -> Self {
+        EncryptionError::Keyring(err)
+    }
+}
+
+impl From<io::Error> for EncryptionError {
+    fn from(err: io::Error) -> Self {
+        EncryptionError::Io(err)
+    }
+}
+
+impl From<MigrationError> for EncryptionError {
+    fn from(err: MigrationError) -> Self {
+        EncryptionError::Migration(err)
+    }
+}
+
+impl From<rusqlite::Error> for EncryptionError {
+    fn from(err: rusqlite::Error) -> Self {
+        // With a wrong key, SQLCipher can't tell the database from random bytes
+        if err.sqlite_error_code() == Some(ErrorCode::NotADatabase) {
+            return EncryptionError::WrongKey;
+        }
+        EncryptionError::Migration(MigrationError::Sqlite(err))
+    }
+}
+
+/// Whether the database is encrypted at all, i.e. whether the history can be locked.
+pub fn is_enabled() -> bool {
+    config::get().encryption.mode != EncryptionMode::Off
+}
+
+/// Whether a passphrase has to be typed in to open the database.
+pub fn needs_passphrase() -> bool {
+    config::get().encryption.mode == EncryptionMode::Passphrase
+}
+
+/// Let `open` encrypt a database still in plain text, in this process. Called by the daemon only.
+pub fn allow_encrypting_in_place() {
+    CAN_ENCRYPT_IN_PLACE.store(true, Ordering::SeqCst);
+}
+
+/// Open the clipboard history database and upgrade its schema, decrypting it according
+/// to the `[encryption]` settings.
+///
+/// When encryption is enabled and the database is still in plain text (e.g. it was created
+/// before encryption was turned on), the daemon encrypts it in place first (see `allow_encrypting_in_place`).
+/// Other processes fail with `EncryptionError::NotEncryptedYet` instead.
+///
+/// Images, thumbnails and app icons are files next to the database (see `blobs`). When encryption is
+/// enabled, they are encrypted too, with a random key stored in the database (see `blob_key`),
+/// and the ones still in plain text are encrypted once the database is open.
+///
+/// # Arguments
+///
+/// * `path` - The location of the database, see `storage::db_path`.
+/// * `passphrase` - The passphrase typed in by the user, only used in `passphrase` mode.
+///
+/// # Errors
+///
+/// Returns `EncryptionError::WrongKey` if the key doesn't decrypt the database, e.g. because
+/// the passphrase has a typo or the encryption mode was changed after the database was encrypted.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::{encryption, storage};
+///
+/// let conn = encryption::open(&storage::db_path(), Some("correct horse battery staple")).unwrap();
+/// ```
+pub fn open(path: &Path, passphrase: Option<&str>) -> Result<Connection, EncryptionError> {
+    let key = match config::get().encryption.mode {
+        EncryptionMode::Off => None,
+        EncryptionMode::Keyring => Some(keyring_key(path)?),
+        EncryptionMode::Passphrase => Some(
+            passphrase
+                .filter(|passphrase| !passphrase.is_empty())
+                .ok_or(EncryptionError::MissingPassphrase)?
+                .to_string(),
+        ),
+    };
+
+    if let Some(key) = &key {
+        if is_plaintext(path) {
+            if !CAN_ENCRYPT_IN_PLACE.load(Ordering::SeqCst) {
+                return Err(EncryptionError::NotEncryptedYet);
+            }
+            encrypt_in_place(path, key)?;
+        }
+    }
+
+    let conn = open_with_key(path, key.as_deref())?;
+
+    match &key {
+        Some(_) => {
+            blobs::set_key(Some(&blob_key(&conn)?));
+
+            let encrypted = blobs::encrypt_plaintext_files();
+            if encrypted > 0 {
+                log::info!("Encrypted {} image and icon files", encrypted);
+            }
+        }
+        None => blobs::set_key(None),
+    }
+
+    Ok(conn)
+}
+
+/// Open the database at `path` with the SQLCipher `key` (`None` if it isn't encrypted),
+/// and upgrade its schema.
+fn open_with_key(path: &Path, key: Option<&str>) -> Result<Connection, EncryptionError> {
+    let mut conn = Connection::open(path)?;
+
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key)?;
+    }
+
+    // SQLCipher only checks the key on the first read
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))?;
+
+    // The daemon and the command line may use the database at the same time:
+    // readers don't block the writer in WAL mode, and writers wait for each other
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+    migrations::migrate(&mut conn)?;
+
+    Ok(conn)
+}
+
+/// Return the key the files of the blob store are encrypted with, creating a random one on first use.
+///
+/// The key is stored in the encrypted database rather than derived from the database key, so that
+/// the files can't be used to check guesses of the passphrase any faster than the database itself.
+fn blob_key(conn: &Connection) -> rusqlite::Result<[u8; 32]> {
+    let key: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE name = 'blob_key'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if let Some(key) = key.and_then(|key| key.try_into().ok()) {
+        return Ok(key);
+    }
+
+    let mut key = [0u8; 32];
+    getrandom::fill(&mut key).expect("Failed to generate a blob key");
+
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (name, value) VALUES ('blob_key', ?1)",
+        params![key.as_slice()],
+    )?;
+
+    Ok(key)
+}
+
+/// Return the SQLCipher key of the database at `path` from the OS keyring, creating a random one
+/// on first use.
+fn keyring_key(path: &Path) -> Result<String, EncryptionError> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, &path.to_string_lossy())?;
+
+    let hex = match entry.get_password() {
+        Ok(hex) => hex,
+        Err(keyring::Error::NoEntry) => {
+            let mut bytes = [0u8; 32];
+            getrandom::fill(&mut bytes).expect("Failed to generate a database key");
+
+            let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            entry.set_password(&hex)?;
+            log::info!("Stored a new database key in the OS keyring");
+            hex
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // A raw key rather than a passphrase, so SQLCipher skips the (deliberately slow) key derivation
+    Ok(format!("x'{}'", hex))
+}
+
+/// Whether the file at `path` is an SQLite database that isn't encrypted.
+fn is_plaintext(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| &header == PLAINTEXT_HEADER)
+}
+
+/// Encrypt the plain text database at `path` with `key`.
+///
+/// The encrypted copy is written next to the database and only replaces it once complete,
+/// so an interrupted migration leaves the plain text database untouched. The images and icons
+/// of the database are encrypted afterwards, by `open`.
+///
+/// The write-ahead log is folded into the database and removed first: left next to the encrypted
+/// database, SQLite would replay its plain text pages into it.
+fn encrypt_in_place(path: &Path, key: &str) -> Result<(), EncryptionError> {
+    log::info!("Encrypting database {}", path.display());
+
+    let encrypted_path = path.with_extension("encrypting");
+    let _ = fs::remove_file(&encrypted_path);
+
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    conn.pragma_update_and_check(None, "journal_mode", "DELETE", |_| Ok(()))?;
+
+    conn.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        params![encrypted_path.to_string_lossy(), key],
+    )?;
+    conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+
+    // `sqlcipher_export` copies the tables, but not the schema version
+    let version = migrations::schema_version(&conn)?;
+    conn.pragma_update(Some("encrypted"), "user_version", version)?;
+
+    conn.execute_batch("DETACH DATABASE encrypted")?;
+    drop(conn);
+
+    fs::rename(&encrypted_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::storage;
+    use std::path::PathBuf;
+
+    const KEY: &str = "correct horse battery staple";
+
+    /// The path of a database in the test data directory, and of its write-ahead log.
+    fn db_paths(name: &str) -> (PathBuf, PathBuf) {
+        let path = storage::data_dir().join(name);
+        let wal_path = storage::data_dir().join(format!("{}-wal", name));
+
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&wal_path);
+        (path, wal_path)
+    }
+
+    fn count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM history", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    /// A plain text database with an item that is still in its write-ahead log,
+    /// as an app that crashed leaves it behind.
+    fn crashed_plaintext_db(name: &str) -> PathBuf {
+        let (origin_path, origin_wal_path) = db_paths("origin.db");
+        let (path, wal_path) = db_paths(name);
+
+        let conn = open_with_key(&origin_path, None).unwrap();
+        conn.execute(
+            "INSERT INTO history (source_app, icon_path, content_type, content)
+             VALUES ('Code', '', 'TEXT', 'hello world')",
+            [],
+        )
+        .unwrap();
+
+        // Closing the connection would fold the log into the database
+        fs::copy(&origin_path, &path).unwrap();
+        fs::copy(&origin_wal_path, &wal_path).unwrap();
+        drop(conn);
+
+        path
+    }
+
+    #[test]
+    fn plaintext_databases_are_encrypted_with_their_write_ahead_log() {
+        let path = crashed_plaintext_db("clipboard.db");
+        assert!(is_plaintext(&path));
+
+        encrypt_in_place(&path, KEY).unwrap();
+
+        assert!(!is_plaintext(&path));
+        assert!(!storage::data_dir().join("clipboard.db-wal").exists());
+        assert_eq!(count(&open_with_key(&path, Some(KEY)).unwrap()), 1);
+    }
+
+    #[test]
+    fn databases_in_use_are_left_in_plain_text() {
+        let path = crashed_plaintext_db("clipboard.db");
+        let other_process = open_with_key(&path, None).unwrap();
+
+        assert!(encrypt_in_place(&path, KEY).is_err());
+
+        assert!(is_plaintext(&path));
+        assert_eq!(count(&other_process), 1);
+    }
+
+    #[test]
+    fn wrong_keys_dont_open_encrypted_databases() {
+        let path = crashed_plaintext_db("clipboard.db");
+        encrypt_in_place(&path, KEY).unwrap();
+
+        assert!(matches!(
+            open_with_key(&path, Some("wrong horse battery staple")),
+            Err(EncryptionError::WrongKey)
+        ));
+        assert!(matches!(
+            open_with_key(&path, None),
+            Err(EncryptionError::WrongKey)
+        ));
+        assert!(open_with_key(&path, Some(KEY)).is_ok());
+    }
+}
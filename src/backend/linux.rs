@@ -0,0 +1,713 @@
+use std::env::var_os;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt, CreateWindowAux, EventMask, InputFocus,
+    Keycode, Keysym, Window, WindowClass, KEY_PRESS_EVENT, KEY_RELEASE_EVENT,
+};
+use x11rb::protocol::xtest::{self, ConnectionExt as _};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::backend::blobs;
+use crate::backend::clipboard::Format;
+use crate::backend::platform::{ClipboardFormatProvider, FocusedAppProvider, PasteProvider};
+use crate::backend::storage::icon_path;
+
+/// The Linux implementation of `FocusedAppProvider`, backed by X11 / EWMH.
+///
+/// The focused window is resolved through `_NET_ACTIVE_WINDOW` on the root window,
+/// its owning process through `_NET_WM_PID`, and its icon through the matching
+/// `.desktop` entry and the icon theme directories.
+pub struct Linux;
+
+/// The bits of the focused X11 window that we use to identify its application.
+#[derive(Default)]
+struct FocusedWindow {
+    pid: Option<u32>,
+    class: Option<String>,
+    title: Option<String>,
+}
+
+/// The bits of a `.desktop` entry that we care about.
+struct DesktopEntry {
+    name: Option<String>,
+    icon: Option<String>,
+}
+
+impl FocusedAppProvider for Linux {
+    /// Return the name of the current focused application.
+    ///
+    /// The name is taken from the matching `.desktop` entry if there is one,
+    /// otherwise from `WM_CLASS`, otherwise from `/proc/<pid>/comm`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{}", Linux::current_focus_app_name()); // Output: "Visual Studio Code"
+    /// ```
+    fn current_focus_app_name() -> String {
+        let window = focused_window();
+
+        app_name(&window, desktop_entry(&window).as_ref())
+    }
+
+    /// Return the executable path of the current focused application.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{:?}", Linux::current_focus_app_path()); // Output: "/usr/share/code/code"
+    /// ```
+    fn current_focus_app_path() -> PathBuf {
+        focused_window()
+            .pid
+            .and_then(|pid| fs::read_link(format!("/proc/{}/exe", pid)).ok())
+            .unwrap_or_default()
+    }
+
+    /// Return the icon file path of the current focused application.
+    ///
+    /// - Icon is looked up from the `Icon=` key of the matching `.desktop` entry.
+    /// - Icon will be copied into the icon cache directory, see `storage::icon_path`.
+    /// - If no icon can be found, the returned path does not exist.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{:?}", Linux::current_focus_app_icon_path()); // Output: "~/.local/share/paste-fork/icons/6e1f…9a0b.png"
+    /// ```
+    fn current_focus_app_icon_path() -> PathBuf {
+        let window = focused_window();
+        let entry = desktop_entry(&window);
+        let current_focus_app_name = app_name(&window, entry.as_ref());
+
+        for extension in ["png", "svg"] {
+            let cached = icon_path(&current_focus_app_name, extension);
+            if cached.exists() {
+                return cached;
+            }
+        }
+
+        let icon_source = entry
+            .and_then(|entry| entry.icon)
+            .and_then(|icon| resolve_icon(&icon));
+
+        if let Some(icon_source) = icon_source {
+            let extension = icon_source
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("png");
+            let cached = icon_path(&current_focus_app_name, extension);
+
+            // Written through the blob store, which encrypts it along with the images
+            if fs::read(&icon_source)
+                .and_then(|data| blobs::write_file(&cached, &data))
+                .is_ok()
+            {
+                return cached;
+            }
+        }
+
+        icon_path(&current_focus_app_name, "png")
+    }
+
+    /// Return the `WM_CLASS` class name of the current focused window.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{}", Linux::current_focus_app_id()); // Output: "Code"
+    /// ```
+    fn current_focus_app_id() -> String {
+        focused_window().class.unwrap_or_default()
+    }
+
+    /// Return the title of the current focused window, from `_NET_WM_NAME` or `WM_NAME`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{}", Linux::current_focus_window_title()); // Output: "main.rs - paste-fork - Visual Studio Code"
+    /// ```
+    fn current_focus_window_title() -> String {
+        focused_window().title.unwrap_or_default()
+    }
+}
+
+impl ClipboardFormatProvider for Linux {
+    /// Return the data the `CLIPBOARD` selection currently holds in `format`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::clipboard::Format;
+    /// use crate::backend::platform::ClipboardFormatProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{:?}", Linux::clipboard_data(&Format::Html)); // Output: Some([60, 98, 62, ...])
+    /// ```
+    fn clipboard_data(format: &Format) -> Option<Vec<u8>> {
+        read_clipboard(format.mime().as_bytes())
+    }
+
+    /// Always returns `false`: the selection is served by `arboard`, which can't be extended
+    /// with formats of our own.
+    fn add_clipboard_data(_format: &Format, _data: &[u8]) -> bool {
+        false
+    }
+
+    /// Returns `false`, see `add_clipboard_data`. Only the HTML of copied text is saved on Linux.
+    fn can_add_clipboard_data() -> bool {
+        false
+    }
+
+    /// Return the names of the `TARGETS` the owner of the `CLIPBOARD` selection offers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::ClipboardFormatProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{:?}", Linux::clipboard_types()); // Output: ["TARGETS", "UTF8_STRING", "x-kde-passwordManagerHint"]
+    /// ```
+    fn clipboard_types() -> Vec<String> {
+        let Some(targets) = read_clipboard(b"TARGETS") else {
+            return Vec::new();
+        };
+        let Ok((conn, _)) = x11rb::connect(None) else {
+            return Vec::new();
+        };
+
+        // Atoms are shared by every client of the X server, so they can be named from another connection
+        targets
+            .chunks_exact(4)
+            .filter_map(first_u32)
+            .filter_map(|atom| conn.get_atom_name(atom).ok()?.reply().ok())
+            .map(|reply| String::from_utf8_lossy(&reply.name).to_string())
+            .collect()
+    }
+}
+
+impl PasteProvider for Linux {
+    /// Return the `_NET_ACTIVE_WINDOW`, or the window with the input focus without an EWMH window manager.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::PasteProvider;
+    /// use crate::backend::linux::Linux;
+    ///
+    /// println!("{:?}", Linux::focused_target()); // Output: Some(54525958)
+    /// ```
+    fn focused_target() -> Option<u64> {
+        let (conn, screen_num) = x11rb::connect(None).ok()?;
+        let root = conn.setup().roots[screen_num].root;
+
+        let window = active_window(&conn, root).or_else(|| {
+            let focus = conn.get_input_focus().ok()?.reply().ok()?.focus;
+            // `None` (0) and `PointerRoot` (1) aren't windows
+            (focus > 1 && focus != root).then_some(focus)
+        })?;
+
+        Some(window.into())
+    }
+
+    /// Ask the window manager to activate the window `target`, or focus it ourselves if there is none.
+    fn activate(target: u64) -> bool {
+        let Ok(window) = Window::try_from(target) else {
+            return false;
+        };
+        let Ok((conn, screen_num)) = x11rb::connect(None) else {
+            return false;
+        };
+        let root = conn.setup().roots[screen_num].root;
+
+        let exists = conn
+            .get_window_attributes(window)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .is_some();
+        if !exists {
+            return false;
+        }
+
+        let has_window_manager = intern_atom(&conn, b"_NET_SUPPORTING_WM_CHECK")
+            .and_then(|atom| get_property(&conn, root, atom, AtomEnum::WINDOW.into()))
+            .is_some_and(|value| !value.is_empty());
+
+        let sent = if has_window_manager {
+            // Source indication 2 (a pager): window managers ignore focus stealing from plain apps
+            let Some(net_active_window) = intern_atom(&conn, b"_NET_ACTIVE_WINDOW") else {
+                return false;
+            };
+            let event = ClientMessageEvent::new(
+                32,
+                window,
+                net_active_window,
+                [2, x11rb::CURRENT_TIME, 0, 0, 0],
+            );
+            conn.send_event(
+                false,
+                root,
+                EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                event,
+            )
+            .is_ok()
+        } else {
+            conn.set_input_focus(InputFocus::PARENT, window, x11rb::CURRENT_TIME)
+                .is_ok()
+        };
+
+        sent && conn.sync().is_ok()
+    }
+
+    /// Press and release `Ctrl V` through the XTEST extension.
+    fn send_paste_keystroke() -> bool {
+        fake_key_events(&[
+            (KEY_PRESS_EVENT, XK_CONTROL_L),
+            (KEY_PRESS_EVENT, XK_V),
+            (KEY_RELEASE_EVENT, XK_V),
+            (KEY_RELEASE_EVENT, XK_CONTROL_L),
+        ])
+    }
+
+    fn send_left_arrow_keystrokes(count: usize) -> bool {
+        let events = [(KEY_PRESS_EVENT, XK_LEFT), (KEY_RELEASE_EVENT, XK_LEFT)].repeat(count);
+        fake_key_events(&events)
+    }
+}
+
+const XK_CONTROL_L: Keysym = 0xffe3;
+const XK_LEFT: Keysym = 0xff51;
+const XK_V: Keysym = 0x0076;
+
+/// Synthesize key presses and releases through the XTEST extension, in the focused window.
+///
+/// Returns `false` without sending anything if the X server doesn't support XTEST,
+/// or no key of the keyboard types one of the keysyms.
+fn fake_key_events(events: &[(u8, Keysym)]) -> bool {
+    let Ok((conn, screen_num)) = x11rb::connect(None) else {
+        return false;
+    };
+    let root = conn.setup().roots[screen_num].root;
+
+    if !matches!(
+        conn.extension_information(xtest::X11_EXTENSION_NAME),
+        Ok(Some(_))
+    ) {
+        log::warn!("The X server doesn't support the XTEST extension");
+        return false;
+    }
+
+    let mut keys = Vec::with_capacity(events.len());
+    for &(kind, keysym) in events {
+        let Some(key) = keycode(&conn, keysym) else {
+            log::warn!("No key of the keyboard types the keysym {:#x}", keysym);
+            return false;
+        };
+        keys.push((kind, key));
+    }
+
+    for (kind, key) in keys {
+        if conn
+            .xtest_fake_input(kind, key, x11rb::CURRENT_TIME, root, 0, 0, 0)
+            .is_err()
+        {
+            return false;
+        }
+    }
+
+    conn.sync().is_ok()
+}
+
+/// How long the owner of the `CLIPBOARD` selection gets to answer a conversion request.
+const SELECTION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Ask the owner of the `CLIPBOARD` selection to convert it to `target`, and return the result.
+///
+/// Returns `None` if nobody owns the selection, it can't be converted to `target`, or the owner
+/// doesn't answer in time. Transfers too large for a single property (`INCR`) aren't supported.
+fn read_clipboard(target: &[u8]) -> Option<Vec<u8>> {
+    let (conn, screen_num) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots[screen_num].root;
+
+    let selection = intern_atom(&conn, b"CLIPBOARD")?;
+    let target = intern_atom(&conn, target)?;
+    let property = intern_atom(&conn, b"PASTE_FORK_SELECTION")?;
+    let incr = intern_atom(&conn, b"INCR")?;
+
+    // The converted data is written to a property of the requestor, so we need a window of our own
+    let window = conn.generate_id().ok()?;
+    conn.create_window(
+        x11rb::COPY_DEPTH_FROM_PARENT,
+        window,
+        root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        x11rb::COPY_FROM_PARENT,
+        &CreateWindowAux::new(),
+    )
+    .ok()?;
+    conn.convert_selection(window, selection, target, property, x11rb::CURRENT_TIME)
+        .ok()?;
+    conn.flush().ok()?;
+
+    let deadline = Instant::now() + SELECTION_TIMEOUT;
+    let mut data = None;
+
+    while Instant::now() < deadline {
+        match conn.poll_for_event() {
+            Ok(Some(Event::SelectionNotify(event))) if event.requestor == window => {
+                if event.property != x11rb::NONE {
+                    data = conn
+                        .get_property(true, window, property, AtomEnum::ANY, 0, u32::MAX / 4)
+                        .ok()
+                        .and_then(|cookie| cookie.reply().ok())
+                        .filter(|reply| reply.type_ != incr)
+                        .map(|reply| reply.value);
+                }
+                break;
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => thread::sleep(Duration::from_millis(5)),
+            Err(_) => break,
+        }
+    }
+
+    let _ = conn.destroy_window(window);
+    let _ = conn.flush();
+
+    data
+}
+
+/// Query the X server for the currently active window and its identifying properties.
+///
+/// Returns an empty `FocusedWindow` if there is no X server (e.g. a pure Wayland session)
+/// or the window manager does not support EWMH.
+fn focused_window() -> FocusedWindow {
+    let Ok((conn, screen_num)) = x11rb::connect(None) else {
+        return FocusedWindow::default();
+    };
+    let root = conn.setup().roots[screen_num].root;
+
+    let Some(window) = active_window(&conn, root) else {
+        return FocusedWindow::default();
+    };
+
+    let pid = intern_atom(&conn, b"_NET_WM_PID")
+        .and_then(|atom| get_property(&conn, window, atom, AtomEnum::CARDINAL.into()))
+        .and_then(|value| first_u32(&value));
+
+    // `WM_CLASS` is two NUL-terminated strings: the instance name followed by the class name
+    let class = get_property(
+        &conn,
+        window,
+        AtomEnum::WM_CLASS.into(),
+        AtomEnum::STRING.into(),
+    )
+    .and_then(|value| {
+        value
+            .split(|byte| *byte == 0)
+            .rfind(|part| !part.is_empty())
+            .map(|class| String::from_utf8_lossy(class).to_string())
+    });
+
+    // `_NET_WM_NAME` is UTF-8, the legacy `WM_NAME` is only there for older clients
+    let title = intern_atom(&conn, b"_NET_WM_NAME")
+        .zip(intern_atom(&conn, b"UTF8_STRING"))
+        .and_then(|(atom, utf8_string)| get_property(&conn, window, atom, utf8_string))
+        .filter(|value| !value.is_empty())
+        .or_else(|| {
+            get_property(
+                &conn,
+                window,
+                AtomEnum::WM_NAME.into(),
+                AtomEnum::STRING.into(),
+            )
+        })
+        .map(|value| String::from_utf8_lossy(&value).to_string());
+
+    FocusedWindow { pid, class, title }
+}
+
+/// Return the name of the application owning `window` and its `.desktop` entry, see `current_focus_app_name`.
+fn app_name(window: &FocusedWindow, entry: Option<&DesktopEntry>) -> String {
+    if let Some(name) = entry.and_then(|entry| entry.name.clone()) {
+        return name;
+    }
+
+    if let Some(class) = &window.class {
+        return class.clone();
+    }
+
+    if let Some(pid) = window.pid {
+        if let Ok(comm) = fs::read_to_string(format!("/proc/{}/comm", pid)) {
+            return comm.trim().to_string();
+        }
+    }
+
+    "Unknown".to_string()
+}
+
+/// Return the `_NET_ACTIVE_WINDOW` set on `root` by the window manager, if any.
+fn active_window(conn: &RustConnection, root: Window) -> Option<Window> {
+    intern_atom(conn, b"_NET_ACTIVE_WINDOW")
+        .and_then(|atom| get_property(conn, root, atom, AtomEnum::WINDOW.into()))
+        .and_then(|value| first_u32(&value))
+        .filter(|window| *window != 0)
+}
+
+/// Return the keycode typing `keysym` without any modifier, if the keyboard has one.
+fn keycode(conn: &RustConnection, keysym: Keysym) -> Option<Keycode> {
+    let setup = conn.setup();
+    let mapping = conn
+        .get_keyboard_mapping(setup.min_keycode, setup.max_keycode - setup.min_keycode + 1)
+        .ok()?
+        .reply()
+        .ok()?;
+
+    let index = mapping
+        .keysyms
+        .chunks(usize::from(mapping.keysyms_per_keycode).max(1))
+        .position(|keysyms| keysyms.first() == Some(&keysym))?;
+
+    Some(setup.min_keycode + index as Keycode)
+}
+
+fn intern_atom(conn: &RustConnection, name: &[u8]) -> Option<Atom> {
+    Some(conn.intern_atom(false, name).ok()?.reply().ok()?.atom)
+}
+
+fn get_property(
+    conn: &RustConnection,
+    window: Window,
+    property: Atom,
+    kind: Atom,
+) -> Option<Vec<u8>> {
+    let reply = conn
+        .get_property(false, window, property, kind, 0, u32::MAX / 4)
+        .ok()?
+        .reply()
+        .ok()?;
+
+    Some(reply.value)
+}
+
+fn first_u32(value: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(value.get(0..4)?.try_into().ok()?))
+}
+
+/// The XDG data directories, most important first.
+fn xdg_data_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(data_home) = var_os("XDG_DATA_HOME") {
+        dirs.push(PathBuf::from(data_home));
+    } else if let Some(home) = var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".local/share"));
+    }
+
+    let data_dirs = var_os("XDG_DATA_DIRS")
+        .map(|dirs| dirs.to_string_lossy().to_string())
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    dirs.extend(data_dirs.split(':').map(PathBuf::from));
+
+    dirs
+}
+
+/// Find the `.desktop` entry belonging to the focused window.
+///
+/// An entry matches if its file name or `StartupWMClass=` equals the window class,
+/// or if its `Exec=` command points to the same executable as the window's process.
+fn desktop_entry(window: &FocusedWindow) -> Option<DesktopEntry> {
+    let class = window.class.as_deref().map(str::to_lowercase);
+    let exe_name = window
+        .pid
+        .and_then(|pid| fs::read_link(format!("/proc/{}/exe", pid)).ok())
+        .and_then(|exe| {
+            exe.file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        });
+
+    if class.is_none() && exe_name.is_none() {
+        return None;
+    }
+
+    for dir in xdg_data_dirs() {
+        let Ok(entries) = fs::read_dir(dir.join("applications")) else {
+            continue;
+        };
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().and_then(|ext| ext.to_str()) != Some("desktop") {
+                continue;
+            }
+
+            let Ok(contents) = fs::read_to_string(&path) else {
+                continue;
+            };
+
+            let file_stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_lowercase());
+            let wm_class = desktop_key(&contents, "StartupWMClass").map(|c| c.to_lowercase());
+            let exec_name = desktop_key(&contents, "Exec").and_then(|exec| {
+                let program = exec.split_whitespace().next()?;
+                Some(
+                    Path::new(program)
+                        .file_name()?
+                        .to_string_lossy()
+                        .to_string(),
+                )
+            });
+
+            let matches_class = class.is_some() && (class == file_stem || class == wm_class);
+            let matches_exe = exe_name.is_some() && exe_name == exec_name;
+
+            if matches_class || matches_exe {
+                return Some(DesktopEntry {
+                    name: desktop_key(&contents, "Name"),
+                    icon: desktop_key(&contents, "Icon"),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Read an unlocalized key from the `[Desktop Entry]` group of a `.desktop` file.
+fn desktop_key(contents: &str, key: &str) -> Option<String> {
+    let mut in_main_group = false;
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_main_group = line == "[Desktop Entry]";
+            continue;
+        }
+
+        if !in_main_group {
+            continue;
+        }
+
+        if let Some((k, v)) = line.split_once('=') {
+            if k.trim() == key {
+                return Some(v.trim().to_string());
+            }
+        }
+    }
+
+    None
+}
+
+/// Resolve an `Icon=` value to an image file, preferring large PNGs from the `hicolor` theme.
+fn resolve_icon(icon: &str) -> Option<PathBuf> {
+    let icon_path = Path::new(icon);
+    if icon_path.is_absolute() {
+        return icon_path.exists().then(|| icon_path.to_path_buf());
+    }
+
+    const SIZES: &[&str] = &["256x256", "128x128", "96x96", "64x64", "48x48"];
+
+    for dir in xdg_data_dirs() {
+        let theme = dir.join("icons/hicolor");
+
+        for size in SIZES {
+            let candidate = theme.join(size).join("apps").join(format!("{}.png", icon));
+            if candidate.exists() {
+                return Some(candidate);
+            }
+        }
+
+        let candidate = theme.join("scalable/apps").join(format!("{}.svg", icon));
+        if candidate.exists() {
+            return Some(candidate);
+        }
+
+        for extension in ["png", "svg"] {
+            let candidate = dir.join("pixmaps").join(format!("{}.{}", icon, extension));
+            if candidate.exists() {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::protocol::xproto::KeyButMask;
+
+    /// Focus a stub window the way `paste::paste_into_previous_app` does, and check that it receives `Ctrl V`.
+    #[test]
+    #[ignore = "needs an X server with the XTEST extension, e.g. `xvfb-run cargo test -- --ignored`"]
+    fn pastes_into_the_activated_window() {
+        let (conn, screen_num) = x11rb::connect(None).unwrap();
+        let root = conn.setup().roots[screen_num].root;
+
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            100,
+            100,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new().event_mask(EventMask::KEY_PRESS | EventMask::STRUCTURE_NOTIFY),
+        )
+        .unwrap();
+        conn.map_window(window).unwrap();
+        conn.flush().unwrap();
+        while !matches!(conn.wait_for_event().unwrap(), Event::MapNotify(_)) {}
+
+        assert!(Linux::activate(window.into()));
+        assert_eq!(Linux::focused_target(), Some(window.into()));
+        assert!(Linux::send_paste_keystroke());
+
+        let v = keycode(&conn, XK_V).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while Instant::now() < deadline {
+            match conn.poll_for_event().unwrap() {
+                Some(Event::KeyPress(event)) if event.detail == v => {
+                    assert!(event.state.contains(KeyButMask::CONTROL));
+                    return;
+                }
+                Some(_) => {}
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+        panic!("The stub window didn't receive Ctrl V");
+    }
+}
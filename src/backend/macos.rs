@@ -1,118 +1,384 @@
+use core_foundation::array::{CFArray, CFArrayRef};
+use core_foundation::base::{CFRelease, CFType, CFTypeRef, TCFType};
+use core_foundation::dictionary::CFDictionary;
+use core_foundation::number::CFNumber;
 use core_foundation::runloop::{kCFRunLoopDefaultMode, CFRunLoopRunInMode};
-use objc2_app_kit::{NSBitmapImageFileType, NSBitmapImageRep, NSWorkspace};
-use objc2_foundation::NSDictionary;
-use std::env::current_exe;
-use std::fs::File;
-use std::io::Write;
+use core_foundation::string::CFString;
+use objc2_app_kit::{
+    NSApplicationActivationOptions, NSBitmapImageFileType, NSBitmapImageRep, NSPasteboard,
+    NSPasteboardType, NSPasteboardTypeHTML, NSPasteboardTypePNG, NSPasteboardTypeRTF,
+    NSRunningApplication, NSWorkspace,
+};
+use objc2_foundation::{NSArray, NSData, NSDictionary};
 use std::path::PathBuf;
 
-/// Return the name of the current focused application.
-///
-/// # Example
-///
-/// ```
-/// use create::backend::macos::current_focus_app_name;
-///
-/// println!("{}", current_focus_app_name()); // Output: "Code"
-/// ```
-pub fn current_focus_app_name() -> String {
-    unsafe {
-        CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+use crate::backend::blobs;
+use crate::backend::clipboard::Format;
+use crate::backend::platform::{ClipboardFormatProvider, FocusedAppProvider, PasteProvider};
+use crate::backend::storage::icon_path;
+
+/// The macOS implementation of the platform traits, backed by `NSWorkspace` and `NSPasteboard`.
+pub struct MacOS;
+
+impl FocusedAppProvider for MacOS {
+    /// Return the name of the current focused application.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{}", MacOS::current_focus_app_name()); // Output: "Code"
+    /// ```
+    fn current_focus_app_name() -> String {
+        unsafe {
+            CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+        }
+
+        let workspace = { NSWorkspace::sharedWorkspace() };
+
+        if let Some(app) = { workspace.frontmostApplication() } {
+            if let Some(name) = { app.localizedName() } {
+                return name.to_string();
+            }
+        }
+
+        "Unknown".to_string()
     }
 
-    let workspace = { NSWorkspace::sharedWorkspace() };
+    /// Return the path of the current focused application.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{:?}", MacOS::current_focus_app_path()); // Output: "/Applications/Visual Studio Code.app"
+    /// ```
+    fn current_focus_app_path() -> PathBuf {
+        unsafe {
+            CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+        }
+
+        let workspace = { NSWorkspace::sharedWorkspace() };
 
-    if let Some(app) = { workspace.frontmostApplication() } {
-        if let Some(name) = { app.localizedName() } {
-            return name.to_string();
+        let path_string = {
+            workspace
+                .frontmostApplication()
+                .and_then(|app| app.bundleURL())
+                .and_then(|url| url.path())
+        };
+
+        if let Some(ns_string) = path_string {
+            return PathBuf::from(ns_string.to_string());
         }
+
+        PathBuf::new()
     }
 
-    "Unknown".to_string()
-}
+    /// Return the icon file path of the current focused application.
+    ///
+    /// - Icon will be saved as a PNG file.
+    /// - Icon file name will be the same as the app name.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{:?}", MacOS::current_focus_app_icon_path()); // Output: "~/Library/Application Support/paste-fork/icons/6e1f…9a0b.png"
+    /// ```
+    fn current_focus_app_icon_path() -> PathBuf {
+        let current_focus_app_name = Self::current_focus_app_name();
+        let current_focus_app_icon_path = icon_path(&current_focus_app_name, "png");
 
-/// Return the path of the current focused application.
-///
-/// # Example
-///
-/// ```
-/// use create::backend::macos::current_focus_app_path;
-///
-/// println!("{:?}", current_focus_app_path()); // Output: "/Applications/Visual Studio Code.app"
-/// ```
-pub fn current_focus_app_path() -> PathBuf {
-    unsafe {
-        CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+        if !current_focus_app_icon_path.exists() {
+            unsafe {
+                CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+            }
+
+            let workspace = NSWorkspace::sharedWorkspace();
+
+            if let Some(app) = workspace.frontmostApplication() {
+                if let Some(icon) = app.icon() {
+                    if let Some(tiff_data) = icon.TIFFRepresentation() {
+                        if let Some(bitmap_rep) = NSBitmapImageRep::imageRepWithData(&tiff_data) {
+                            if let Some(png_data) = unsafe {
+                                bitmap_rep.representationUsingType_properties(
+                                    NSBitmapImageFileType::PNG,
+                                    &NSDictionary::new(),
+                                )
+                            } {
+                                // Written through the blob store, which encrypts it along with the images
+                                if let Err(err) = blobs::write_file(
+                                    &current_focus_app_icon_path,
+                                    unsafe { png_data.as_bytes_unchecked() },
+                                ) {
+                                    log::error!("Failed to cache app icon: {}", err);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        debug_assert!(
+            current_focus_app_icon_path.exists(),
+            "Icon file should exist after called."
+        );
+
+        current_focus_app_icon_path
     }
 
-    let workspace = { NSWorkspace::sharedWorkspace() };
+    /// Return the bundle identifier of the current focused application.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{}", MacOS::current_focus_app_id()); // Output: "com.microsoft.VSCode"
+    /// ```
+    fn current_focus_app_id() -> String {
+        unsafe {
+            CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+        }
+
+        let workspace = NSWorkspace::sharedWorkspace();
 
-    let path_string = {
         workspace
             .frontmostApplication()
-            .and_then(|app| app.bundleURL())
-            .and_then(|url| url.path())
-    };
+            .and_then(|app| app.bundleIdentifier())
+            .map(|bundle_id| bundle_id.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Return the title of the frontmost window of the current focused application.
+    ///
+    /// Window titles are only visible to apps with the Screen Recording permission,
+    /// without it this always returns an empty string.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::FocusedAppProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{}", MacOS::current_focus_window_title()); // Output: "main.rs — paste-fork"
+    /// ```
+    fn current_focus_window_title() -> String {
+        unsafe {
+            CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
+        }
 
-    if let Some(ns_string) = path_string {
-        return PathBuf::from(ns_string.to_string());
+        let workspace = NSWorkspace::sharedWorkspace();
+        let Some(pid) = workspace
+            .frontmostApplication()
+            .map(|app| app.processIdentifier() as i64)
+        else {
+            return String::new();
+        };
+
+        // Windows are listed front to back, so the first normal window of the app is the focused one
+        let windows: CFArray<CFDictionary<CFString, CFType>> = unsafe {
+            CFArray::wrap_under_create_rule(CGWindowListCopyWindowInfo(
+                K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY,
+                K_CG_NULL_WINDOW_ID,
+            ))
+        };
+
+        let number = |window: &CFDictionary<CFString, CFType>, key: &'static str| {
+            window
+                .find(CFString::from_static_string(key))
+                .and_then(|value| value.downcast::<CFNumber>())
+                .and_then(|value| value.to_i64())
+        };
+
+        windows
+            .iter()
+            .find(|window| {
+                number(window, "kCGWindowOwnerPID") == Some(pid)
+                    && number(window, "kCGWindowLayer") == Some(0)
+            })
+            .and_then(|window| {
+                window
+                    .find(CFString::from_static_string("kCGWindowName"))
+                    .and_then(|value| value.downcast::<CFString>())
+            })
+            .map(|title| title.to_string())
+            .unwrap_or_default()
     }
+}
 
-    PathBuf::new()
+const K_CG_WINDOW_LIST_OPTION_ON_SCREEN_ONLY: u32 = 1;
+const K_CG_NULL_WINDOW_ID: u32 = 0;
+const K_CG_EVENT_SOURCE_STATE_HID_SYSTEM_STATE: i32 = 1;
+const K_CG_HID_EVENT_TAP: u32 = 0;
+const K_CG_EVENT_FLAG_MASK_COMMAND: u64 = 0x0010_0000;
+const K_VK_ANSI_V: u16 = 0x09;
+const K_VK_LEFT_ARROW: u16 = 0x7B;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGWindowListCopyWindowInfo(option: u32, relative_to_window: u32) -> CFArrayRef;
+    fn CGEventSourceCreate(state_id: i32) -> CFTypeRef;
+    fn CGEventCreateKeyboardEvent(source: CFTypeRef, virtual_key: u16, key_down: bool)
+        -> CFTypeRef;
+    fn CGEventSetFlags(event: CFTypeRef, flags: u64);
+    fn CGEventPost(tap: u32, event: CFTypeRef);
+}
+
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
 }
 
-/// Return the icon file path of the current focused application.
-///
-/// - Icon will be saved as a PNG file.
-/// - Icon file name will be the same as the app name.
-///
-/// # Example
-///
-/// ```
-/// use create::backend::macos::current_focus_app_icon_path;
-///
-/// println!("{:?}", current_focus_app_icon_path()); // Output: "/Applications/THIS.app/Contents/MacOS/Code.png"
-/// ```
-pub fn current_focus_app_icon_path() -> PathBuf {
-    let current_focus_app_name = current_focus_app_name();
-    let current_focus_app_icon_path = current_exe()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .join(format!("{}.png", current_focus_app_name));
-
-    if !current_focus_app_icon_path.exists() {
+impl PasteProvider for MacOS {
+    /// Return the process id of the frontmost application.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::PasteProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{:?}", MacOS::focused_target()); // Output: Some(1234)
+    /// ```
+    fn focused_target() -> Option<u64> {
         unsafe {
             CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.0, false as _);
         }
 
         let workspace = NSWorkspace::sharedWorkspace();
 
-        if let Some(app) = workspace.frontmostApplication() {
-            if let Some(icon) = app.icon() {
-                if let Some(tiff_data) = icon.TIFFRepresentation() {
-                    if let Some(bitmap_rep) = NSBitmapImageRep::imageRepWithData(&tiff_data) {
-                        if let Some(png_data) = unsafe {
-                            bitmap_rep.representationUsingType_properties(
-                                NSBitmapImageFileType::PNG,
-                                &NSDictionary::new(),
-                            )
-                        } {
-                            if let Ok(mut file) = File::create(&current_focus_app_icon_path) {
-                                file.write_all(unsafe { png_data.as_bytes_unchecked() })
-                                    .unwrap();
-                            }
-                        }
-                    }
-                }
+        workspace
+            .frontmostApplication()
+            .map(|app| app.processIdentifier() as u64)
+    }
+
+    /// Bring the application with the process id `target` to the front.
+    fn activate(target: u64) -> bool {
+        let Ok(pid) = i32::try_from(target) else {
+            return false;
+        };
+
+        NSRunningApplication::runningApplicationWithProcessIdentifier(pid)
+            .is_some_and(|app| app.activateWithOptions(NSApplicationActivationOptions::empty()))
+    }
+
+    /// Post `⌘ V` to the frontmost application through a `CGEvent`.
+    fn send_paste_keystroke() -> bool {
+        is_accessibility_trusted() && post_keystroke(K_VK_ANSI_V, K_CG_EVENT_FLAG_MASK_COMMAND)
+    }
+
+    /// Post `←` `count` times to the frontmost application, like `send_paste_keystroke`.
+    fn send_left_arrow_keystrokes(count: usize) -> bool {
+        is_accessibility_trusted() && (0..count).all(|_| post_keystroke(K_VK_LEFT_ARROW, 0))
+    }
+}
+
+/// Events posted without the Accessibility permission are silently dropped, so it is checked first.
+fn is_accessibility_trusted() -> bool {
+    let trusted = unsafe { AXIsProcessTrusted() };
+    if !trusted {
+        log::warn!("Pasting needs the Accessibility permission, see System Settings > Privacy & Security > Accessibility");
+    }
+
+    trusted
+}
+
+/// Post a key press and release with the modifier `flags` to the frontmost application through `CGEvent`s.
+fn post_keystroke(virtual_key: u16, flags: u64) -> bool {
+    let mut posted = true;
+
+    unsafe {
+        let source = CGEventSourceCreate(K_CG_EVENT_SOURCE_STATE_HID_SYSTEM_STATE);
+
+        for key_down in [true, false] {
+            let event = CGEventCreateKeyboardEvent(source, virtual_key, key_down);
+            if event.is_null() {
+                posted = false;
+                break;
             }
+            CGEventSetFlags(event, flags);
+            CGEventPost(K_CG_HID_EVENT_TAP, event);
+            CFRelease(event);
         }
+
+        if !source.is_null() {
+            CFRelease(source);
+        }
+    }
+
+    posted
+}
+
+impl ClipboardFormatProvider for MacOS {
+    /// Return the data the general pasteboard currently holds in `format`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::clipboard::Format;
+    /// use crate::backend::platform::ClipboardFormatProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{:?}", MacOS::clipboard_data(&Format::Rtf)); // Output: Some([123, 92, 114, 116, 102, ...])
+    /// ```
+    fn clipboard_data(format: &Format) -> Option<Vec<u8>> {
+        let pasteboard = NSPasteboard::generalPasteboard();
+        let data = pasteboard.dataForType(pasteboard_type(format))?;
+
+        Some(data.to_vec())
     }
 
-    debug_assert!(
-        current_focus_app_icon_path.exists(),
-        "Icon file should exist after called."
-    );
+    /// Declare `format` on the general pasteboard and write `data` for it, keeping the other types.
+    fn add_clipboard_data(format: &Format, data: &[u8]) -> bool {
+        let pasteboard = NSPasteboard::generalPasteboard();
+        let pasteboard_type = pasteboard_type(format);
 
-    current_focus_app_icon_path
+        unsafe {
+            pasteboard.addTypes_owner(&NSArray::from_slice(&[pasteboard_type]), None);
+        }
+
+        pasteboard.setData_forType(Some(&NSData::with_bytes(data)), pasteboard_type)
+    }
+
+    /// Returns `true`, every type can be added to the general pasteboard.
+    fn can_add_clipboard_data() -> bool {
+        true
+    }
+
+    /// Return the UTIs of the types on the general pasteboard.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::platform::ClipboardFormatProvider;
+    /// use crate::backend::macos::MacOS;
+    ///
+    /// println!("{:?}", MacOS::clipboard_types()); // Output: ["public.utf8-plain-text", "org.nspasteboard.ConcealedType"]
+    /// ```
+    fn clipboard_types() -> Vec<String> {
+        NSPasteboard::generalPasteboard()
+            .types()
+            .map(|types| types.iter().map(|type_| type_.to_string()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The `NSPasteboard` type of a clipboard format.
+fn pasteboard_type(format: &Format) -> &'static NSPasteboardType {
+    unsafe {
+        match format {
+            Format::Html => NSPasteboardTypeHTML,
+            Format::Rtf => NSPasteboardTypeRTF,
+            Format::Png => NSPasteboardTypePNG,
+        }
+    }
 }
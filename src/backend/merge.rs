@@ -0,0 +1,380 @@
+use arboard::ImageData;
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::backend::blobs;
+use crate::backend::clipboard::{self, ContentTypes, Item};
+
+/// The most pixels a collage can have, 256 MiB of RGBA.
+const MAX_COLLAGE_PIXELS: usize = 64 * 1024 * 1024;
+
+/// What goes between the texts of merged items.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Separator {
+    #[default]
+    Newline,
+    Comma,
+    Tab,
+    Custom(String),
+}
+
+impl Separator {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Separator::Newline => "\n",
+            Separator::Comma => ", ",
+            Separator::Tab => "\t",
+            Separator::Custom(separator) => separator,
+        }
+    }
+}
+
+/// How merged images are laid out in the collage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollageLayout {
+    /// One below the other.
+    #[default]
+    Vertical,
+    /// Side by side.
+    Horizontal,
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    /// Less than two items were given.
+    TooFewItems,
+    /// Images can only be merged with images, texts and files with texts and files.
+    MixedTypes,
+    /// The item doesn't exist, or its image is missing from the blob store.
+    Missing(i64),
+    /// The merged text contains a secret that must not be saved, see `secrets::detect`.
+    Secret,
+    /// The collage would have more than `MAX_COLLAGE_PIXELS` pixels.
+    TooLarge,
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::TooFewItems => write!(f, "select at least two items to merge"),
+            MergeError::MixedTypes => write!(f, "images can't be merged with text"),
+            MergeError::Missing(id) => write!(f, "item {} is missing", id),
+            MergeError::Secret => write!(f, "the merged text contains a secret"),
+            MergeError::TooLarge => write!(f, "the merged image would be too large"),
+            MergeError::Sqlite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+impl From<rusqlite::Error> for MergeError {
+    fn from(err: rusqlite::Error) -> Self {
+        MergeError::Sqlite(err)
+    }
+}
+
+/// Merge items into a new item of the history, and return its id.
+///
+/// Texts (and the paths of files) are joined with `separator`, images are combined into a
+/// single collage laid out with `layout`. The items are merged in the order of `ids`,
+/// and stay in the history.
+///
+/// # Arguments
+///
+/// * `ids` - The items to merge, at least two.
+/// * `separator` - What goes between the texts, ignored for images.
+/// * `layout` - How the images are laid out, ignored for texts.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::clipboard;
+/// use crate::backend::merge::{self, CollageLayout, Separator};
+///
+/// let id = merge::merge(&[3, 1, 2], &Separator::Comma, CollageLayout::Vertical).unwrap();
+/// println!("{:?}", clipboard::get_record(id).unwrap().map(|item| item.content)); // Output: Some("Jane, Doe, Paris")
+/// ```
+pub fn merge(ids: &[i64], separator: &Separator, layout: CollageLayout) -> Result<i64, MergeError> {
+    if ids.len() < 2 {
+        return Err(MergeError::TooFewItems);
+    }
+
+    let items = ids
+        .iter()
+        .map(|&id| clipboard::get_record(id)?.ok_or(MergeError::Missing(id)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if merged_type(&items)? != ContentTypes::Image {
+        let text = join_text(&items, separator.as_str());
+        return clipboard::save_merged_text(&items, &text)?.ok_or(MergeError::Secret);
+    }
+
+    let images = items
+        .iter()
+        .map(|item| blobs::load_image(&item.content_hash).ok_or(MergeError::Missing(item.id)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(clipboard::save_merged_image(
+        &items,
+        &collage(&images, layout)?,
+    )?)
+}
+
+/// Return the type of the item merging `items` makes: an image if they are all images, text if none is.
+fn merged_type(items: &[Item]) -> Result<ContentTypes, MergeError> {
+    let image_count = items
+        .iter()
+        .filter(|item| item.content_type == ContentTypes::Image)
+        .count();
+
+    match image_count {
+        0 => Ok(ContentTypes::Text),
+        count if count == items.len() => Ok(ContentTypes::Image),
+        _ => Err(MergeError::MixedTypes),
+    }
+}
+
+/// Join the texts of items, `FILES` items being their paths.
+pub fn join_text(items: &[Item], separator: &str) -> String {
+    items
+        .iter()
+        .map(|item| item.content.as_str())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Combine images into a single one, aligned to the top (or the left) on a transparent background.
+///
+/// Fails with `MergeError::TooLarge` rather than allocate more than `MAX_COLLAGE_PIXELS`.
+pub fn collage(
+    images: &[ImageData],
+    layout: CollageLayout,
+) -> Result<ImageData<'static>, MergeError> {
+    let mut widths = images.iter().map(|image| image.width);
+    let mut heights = images.iter().map(|image| image.height);
+
+    let (width, height) = match layout {
+        CollageLayout::Vertical => (
+            Some(widths.max().unwrap_or(0)),
+            heights.try_fold(0, usize::checked_add),
+        ),
+        CollageLayout::Horizontal => (
+            widths.try_fold(0, usize::checked_add),
+            Some(heights.max().unwrap_or(0)),
+        ),
+    };
+
+    let (Some(width), Some(height)) = (width, height) else {
+        return Err(MergeError::TooLarge);
+    };
+
+    // Both sides are then at most `MAX_COLLAGE_PIXELS`, so they fit in a `u32`
+    let pixels = width.max(1).checked_mul(height.max(1));
+    if pixels.is_none_or(|pixels| pixels > MAX_COLLAGE_PIXELS) {
+        return Err(MergeError::TooLarge);
+    }
+
+    let mut canvas = RgbaImage::new(width as u32, height as u32);
+    let mut offset = 0;
+
+    for image in images {
+        let buffer = RgbaImage::from_raw(
+            image.width as u32,
+            image.height as u32,
+            image.bytes.to_vec(),
+        );
+
+        if let Some(buffer) = buffer {
+            let (x, y) = match layout {
+                CollageLayout::Vertical => (0, offset),
+                CollageLayout::Horizontal => (offset, 0),
+            };
+            imageops::replace(&mut canvas, &buffer, x as i64, y as i64);
+        }
+
+        offset += match layout {
+            CollageLayout::Vertical => image.height,
+            CollageLayout::Horizontal => image.width,
+        };
+    }
+
+    Ok(ImageData {
+        width,
+        height,
+        bytes: Cow::Owned(canvas.into_raw()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+    fn item(content_type: ContentTypes, content: &str) -> Item {
+        Item {
+            id: 1,
+            source_app: "Code".to_string(),
+            icon_path: String::new(),
+            content_type,
+            content: content.to_string(),
+            content_hash: String::new(),
+            timestamp: Utc::now(),
+            formats: Vec::new(),
+            redacted: false,
+            masked: false,
+            pinned: false,
+            tags: Vec::new(),
+            note: String::new(),
+            deleted_at: None,
+        }
+    }
+
+    /// An image of a single color.
+    fn image(width: usize, height: usize, color: [u8; 4]) -> ImageData<'static> {
+        ImageData {
+            width,
+            height,
+            bytes: Cow::Owned(color.repeat(width * height)),
+        }
+    }
+
+    /// The pixels of an image, row by row.
+    fn pixels(image: &ImageData) -> Vec<[u8; 4]> {
+        image
+            .bytes
+            .chunks(4)
+            .map(|pixel| pixel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn join_text_puts_the_separator_between_the_texts() {
+        let items = [
+            item(ContentTypes::Text, "Jane"),
+            item(ContentTypes::Files, "/home/jane/cv.pdf"),
+            item(ContentTypes::Text, "Paris"),
+        ];
+
+        assert_eq!(
+            join_text(&items, Separator::Newline.as_str()),
+            "Jane\n/home/jane/cv.pdf\nParis"
+        );
+        assert_eq!(
+            join_text(&items, Separator::Comma.as_str()),
+            "Jane, /home/jane/cv.pdf, Paris"
+        );
+        assert_eq!(
+            join_text(&items, Separator::Tab.as_str()),
+            "Jane\t/home/jane/cv.pdf\tParis"
+        );
+        assert_eq!(
+            join_text(&items, Separator::Custom(" | ".to_string()).as_str()),
+            "Jane | /home/jane/cv.pdf | Paris"
+        );
+    }
+
+    #[test]
+    fn vertical_collages_stack_the_images() {
+        let collage = collage(
+            &[image(1, 1, RED), image(1, 2, BLUE)],
+            CollageLayout::Vertical,
+        )
+        .unwrap();
+
+        assert_eq!((collage.width, collage.height), (1, 3));
+        assert_eq!(pixels(&collage), [RED, BLUE, BLUE]);
+    }
+
+    #[test]
+    fn horizontal_collages_put_the_images_side_by_side() {
+        let collage = collage(
+            &[image(1, 1, RED), image(2, 1, BLUE)],
+            CollageLayout::Horizontal,
+        )
+        .unwrap();
+
+        assert_eq!((collage.width, collage.height), (3, 1));
+        assert_eq!(pixels(&collage), [RED, BLUE, BLUE]);
+    }
+
+    #[test]
+    fn smaller_images_are_aligned_to_the_top_left() {
+        let images = [image(2, 2, RED), image(1, 1, BLUE)];
+
+        let vertical = collage(&images, CollageLayout::Vertical).unwrap();
+        assert_eq!((vertical.width, vertical.height), (2, 3));
+        assert_eq!(pixels(&vertical), [RED, RED, RED, RED, BLUE, TRANSPARENT]);
+
+        let horizontal = collage(&images, CollageLayout::Horizontal).unwrap();
+        assert_eq!((horizontal.width, horizontal.height), (3, 2));
+        assert_eq!(pixels(&horizontal), [RED, RED, BLUE, RED, RED, TRANSPARENT]);
+    }
+
+    #[test]
+    fn oversized_collages_are_rejected() {
+        // The canvas is checked before anything is allocated, so the images don't need any pixels
+        let huge = |width, height| ImageData {
+            width,
+            height,
+            bytes: Cow::Borrowed(&[]),
+        };
+
+        assert!(matches!(
+            collage(&[huge(10_000, 10_000), huge(1, 1)], CollageLayout::Vertical),
+            Err(MergeError::TooLarge)
+        ));
+        assert!(matches!(
+            collage(
+                &[huge(usize::MAX, 1), huge(1, 1)],
+                CollageLayout::Horizontal
+            ),
+            Err(MergeError::TooLarge)
+        ));
+        assert!(matches!(
+            collage(&[huge(1 << 40, 0), huge(1, 0)], CollageLayout::Horizontal),
+            Err(MergeError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn images_and_texts_cant_be_merged_together() {
+        let text = item(ContentTypes::Text, "Jane");
+        let files = item(ContentTypes::Files, "/home/jane/cv.pdf");
+        let image = item(ContentTypes::Image, "");
+
+        assert!(matches!(
+            merged_type(&[text.clone(), files]),
+            Ok(ContentTypes::Text)
+        ));
+        assert!(matches!(
+            merged_type(&[image.clone(), image.clone()]),
+            Ok(ContentTypes::Image)
+        ));
+        assert!(matches!(
+            merged_type(&[text, image]),
+            Err(MergeError::MixedTypes)
+        ));
+    }
+
+    #[test]
+    fn at_least_two_items_are_merged() {
+        assert!(matches!(
+            merge(&[], &Separator::Newline, CollageLayout::Vertical),
+            Err(MergeError::TooFewItems)
+        ));
+        assert!(matches!(
+            merge(&[1], &Separator::Newline, CollageLayout::Vertical),
+            Err(MergeError::TooFewItems)
+        ));
+    }
+}
@@ -0,0 +1,1176 @@
+use arboard::ImageData;
+use rusqlite::{params, Connection, Transaction};
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::backend::blobs;
+use crate::backend::utils::{hash_image, hash_text};
+
+/// A single schema upgrade step.
+///
+/// The step at index `n` of `MIGRATIONS` upgrades the database from version `n` to `n + 1`.
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// All of the schema upgrade steps, oldest first.
+///
+/// The schema version of a database (stored in `PRAGMA user_version`) is the number of
+/// steps that have been applied to it. A brand new database starts at version 0.
+///
+/// **Never edit or reorder a step that has been released.** Append a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    v1_create_history,
+    v2_full_text_search,
+    v3_pinned_items,
+    v4_content_hashes,
+    v5_image_blob_store,
+    v6_representations,
+    v7_file_lists,
+    v8_privacy_rules,
+    v9_masked_secrets,
+    v10_pinboards,
+    v11_tags_and_notes,
+    v12_trash,
+    v13_snippets,
+    v14_unindexed_private_text,
+    v15_settings,
+    v16_unindexed_trash,
+];
+
+/// The schema version this build of the app reads and writes.
+pub const LATEST_VERSION: i64 = MIGRATIONS.len() as i64;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database was written by a newer version of the app.
+    TooNew {
+        found: i64,
+        supported: i64,
+    },
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::TooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than the latest supported version {}",
+                found, supported
+            ),
+            MigrationError::Sqlite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(err: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(err)
+    }
+}
+
+/// Upgrades the database schema to `LATEST_VERSION`.
+///
+/// All pending steps are applied inside a single transaction, so a failing step
+/// leaves the database at the version it had before this function was called.
+/// The images of older databases are then moved to the blob store, see `extract_images`,
+/// and the database is switched to incremental vacuum, see `enable_incremental_vacuum`.
+///
+/// # Errors
+///
+/// Returns `MigrationError::TooNew` without touching the database if it was written
+/// by a newer version of the app.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::migrations;
+///
+/// let mut conn = rusqlite::Connection::open("clipboard.db").unwrap();
+/// migrations::migrate(&mut conn).unwrap();
+/// ```
+pub fn migrate(conn: &mut Connection) -> Result<(), MigrationError> {
+    let version = schema_version(conn)?;
+
+    if version > LATEST_VERSION {
+        return Err(MigrationError::TooNew {
+            found: version,
+            supported: LATEST_VERSION,
+        });
+    }
+
+    if version < LATEST_VERSION {
+        let tx = conn.transaction()?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            log::info!("Migrating database schema to version {}", index + 1);
+            migration(&tx)?;
+            tx.pragma_update(None, "user_version", index as i64 + 1)?;
+        }
+
+        tx.commit()?;
+    }
+
+    extract_images(conn)?;
+    enable_incremental_vacuum(conn)?;
+
+    Ok(())
+}
+
+/// Moves the images still stored in `history.content` (see `v5_image_blob_store`) to the blob store.
+///
+/// This runs outside of the migration transaction, one image at a time: an image is only removed from
+/// the database once it is in the blob store, and storing it again is a no-op. An interrupted run
+/// therefore loses nothing, and the next `migrate` picks up where it stopped.
+fn extract_images(conn: &Connection) -> rusqlite::Result<()> {
+    let ids = conn
+        .prepare("SELECT id FROM history WHERE content_type = 'IMAGE' AND LENGTH(content) > 0")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for id in ids {
+        let (content, content_hash): (Vec<u8>, String) = conn.query_row(
+            "SELECT content, content_hash FROM history WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let Ok(image) = image::load_from_memory(&content) else {
+            log::warn!("Dropping undecodable image item {}", id);
+            conn.execute("DELETE FROM history WHERE id = ?1", params![id])?;
+            continue;
+        };
+
+        let rgba_image = image.to_rgba8();
+        let size = blobs::store_image(
+            &content_hash,
+            &ImageData {
+                width: rgba_image.width() as usize,
+                height: rgba_image.height() as usize,
+                bytes: Cow::Borrowed(rgba_image.as_raw()),
+            },
+        )
+        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+
+        conn.execute(
+            "UPDATE history SET content = x'', size = ?2 WHERE id = ?1",
+            params![id, size as i64],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Switches the database to `auto_vacuum = INCREMENTAL`, so pruning can give freed pages back
+/// to the file system without rewriting the whole file (see `retention::prune`).
+///
+/// Existing databases only switch over with a full `VACUUM`, which can't run inside the migration
+/// transaction. It runs here instead, once: a database that switched is left alone, and an
+/// interrupted `VACUUM` leaves the database as it was, to be switched by the next `migrate`.
+fn enable_incremental_vacuum(conn: &Connection) -> rusqlite::Result<()> {
+    const INCREMENTAL: i64 = 2;
+    let auto_vacuum: i64 = conn.pragma_query_value(None, "auto_vacuum", |row| row.get(0))?;
+
+    if auto_vacuum != INCREMENTAL {
+        log::info!("Switching database to incremental vacuum");
+        conn.pragma_update(None, "auto_vacuum", "INCREMENTAL")?;
+        conn.execute_batch("VACUUM")?;
+    }
+
+    Ok(())
+}
+
+/// Returns the schema version recorded in the database.
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Version 1: the original `history` table.
+///
+/// Databases created before migrations existed already have this table
+/// (at `user_version` 0), hence the `IF NOT EXISTS`.
+fn v1_create_history(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY,
+                source_app TEXT NOT NULL,
+                icon_path TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                content BLOB NOT NULL,
+                timestamp TEXT NOT NULL DEFAULT (DATETIME('NOW', 'UTC'))
+        )",
+    )
+}
+
+/// Version 2: an FTS5 index over text content and source app names.
+///
+/// The index is kept in sync with `history` by triggers. Image rows are indexed
+/// with empty content, so they can still be found by their source app.
+fn v2_full_text_search(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE history_fts USING fts5(
+                content,
+                source_app,
+                tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        INSERT INTO history_fts (rowid, content, source_app)
+            SELECT id, CASE WHEN content_type = 'TEXT' THEN CAST(content AS TEXT) ELSE '' END, source_app
+            FROM history;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+                VALUES (new.id, CASE WHEN new.content_type = 'TEXT' THEN CAST(new.content AS TEXT) ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_delete AFTER DELETE ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+            INSERT INTO history_fts (rowid, content, source_app)
+                VALUES (new.id, CASE WHEN new.content_type = 'TEXT' THEN CAST(new.content AS TEXT) ELSE '' END, new.source_app);
+        END;",
+    )
+}
+
+/// Version 3: pinned items, which are exempt from retention pruning.
+///
+/// Also indexes `timestamp`, which both pagination and pruning sort on.
+fn v3_pinned_items(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+
+        CREATE INDEX idx_history_timestamp ON history (timestamp);",
+    )
+}
+
+/// Version 4: a `content_hash` column with a unique index per content type, used for deduplication.
+///
+/// Hashes of existing rows are computed here. If older versions left duplicates behind,
+/// only the most recently copied one is kept so the unique index can be created.
+fn v4_content_hashes(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE history ADD COLUMN content_hash TEXT NOT NULL DEFAULT ''")?;
+
+    let ids = tx
+        .prepare("SELECT id FROM history")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for id in ids {
+        let (content_type, content): (String, Vec<u8>) = tx.query_row(
+            "SELECT content_type, CAST(content AS BLOB) FROM history WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let content_hash = match content_type.as_str() {
+            "IMAGE" => match image::load_from_memory(&content) {
+                Ok(image) => {
+                    let rgba_image = image.to_rgba8();
+                    hash_image(&ImageData {
+                        width: rgba_image.width() as usize,
+                        height: rgba_image.height() as usize,
+                        bytes: Cow::Borrowed(rgba_image.as_raw()),
+                    })
+                }
+                // Undecodable images can't be pasted anyway, but must still get a unique hash
+                Err(_) => format!("invalid-{}", id),
+            },
+            _ => hash_text(&String::from_utf8_lossy(&content)),
+        };
+
+        tx.execute(
+            "UPDATE history SET content_hash = ?2 WHERE id = ?1",
+            params![id, content_hash],
+        )?;
+    }
+
+    tx.execute_batch(
+        "DELETE FROM history WHERE id IN (
+             SELECT id FROM (
+                 SELECT id, ROW_NUMBER() OVER (
+                     PARTITION BY content_type, content_hash
+                     ORDER BY timestamp DESC, id DESC
+                 ) AS position
+                 FROM history
+             )
+             WHERE position > 1
+         );
+
+        CREATE UNIQUE INDEX idx_history_content_hash ON history (content_type, content_hash);",
+    )
+}
+
+/// Version 5: images move out of the database into the blob store, and every row records its `size`.
+///
+/// Image rows keep an empty `content` and are looked up in the blob store by `content_hash`.
+/// `size` is the byte size of the text, or of the full-resolution PNG in the blob store.
+///
+/// The files are only written once the schema is upgraded, by `extract_images`, so that a rolled back
+/// migration never leaves any behind.
+fn v5_image_blob_store(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN size INTEGER NOT NULL DEFAULT 0;
+
+        UPDATE history SET size = LENGTH(CAST(content AS BLOB)) WHERE content_type = 'TEXT';",
+    )
+}
+
+/// Version 6: the representations of an item next to its plain text (HTML, RTF, images), see `clipboard::Format`.
+///
+/// They are deleted along with their item by a trigger.
+fn v6_representations(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE representations (
+            item_id INTEGER NOT NULL,
+            format  TEXT NOT NULL,
+            data    BLOB NOT NULL,
+            PRIMARY KEY (item_id, format)
+        ) WITHOUT ROWID;
+
+        CREATE TRIGGER history_representations_delete AFTER DELETE ON history BEGIN
+            DELETE FROM representations WHERE item_id = old.id;
+        END;",
+    )
+}
+
+/// Version 7: the files and folders of `FILES` items, with their size and modification time when they were copied.
+///
+/// The full-text index now also covers the paths of copied files, which `content` holds one per line.
+fn v7_file_lists(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE item_files (
+            item_id  INTEGER NOT NULL,
+            position INTEGER NOT NULL,
+            path     TEXT NOT NULL,
+            is_dir   INTEGER NOT NULL,
+            size     INTEGER NOT NULL,
+            modified TEXT,
+            PRIMARY KEY (item_id, position)
+        ) WITHOUT ROWID;
+
+        CREATE TRIGGER history_item_files_delete AFTER DELETE ON history BEGIN
+            DELETE FROM item_files WHERE item_id = old.id;
+        END;
+
+        DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_update;
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            INSERT INTO history_fts (rowid, content, source_app)
+                VALUES (new.id, CASE WHEN new.content_type IN ('TEXT', 'FILES') THEN CAST(new.content AS TEXT) ELSE '' END, new.source_app);
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+            INSERT INTO history_fts (rowid, content, source_app)
+                VALUES (new.id, CASE WHEN new.content_type IN ('TEXT', 'FILES') THEN CAST(new.content AS TEXT) ELSE '' END, new.source_app);
+        END;",
+    )
+}
+
+/// Version 8: items hidden (`redacted`) or made to expire (`expires_at`) by the privacy rules, see `privacy::CaptureAction`.
+fn v8_privacy_rules(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN redacted INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE history ADD COLUMN expires_at TEXT;
+
+        CREATE INDEX idx_history_expires_at ON history (expires_at) WHERE expires_at IS NOT NULL;",
+    )
+}
+
+/// Version 9: items whose secrets are hidden in the UI, see `secrets::mask`.
+fn v9_masked_secrets(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("ALTER TABLE history ADD COLUMN masked INTEGER NOT NULL DEFAULT 0;")
+}
+
+/// Version 10: pinboards, named and ordered collections of items.
+///
+/// `history.pinned` is kept by triggers: an item is pinned as long as it is on any pinboard,
+/// so retention pruning keeps skipping it. Items pinned before pinboards existed are moved
+/// to a "Pinned" pinboard.
+fn v10_pinboards(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE pinboards (
+            id       INTEGER PRIMARY KEY,
+            name     TEXT NOT NULL,
+            position INTEGER NOT NULL
+        );
+
+        CREATE TABLE pinboard_items (
+            pinboard_id INTEGER NOT NULL,
+            item_id     INTEGER NOT NULL,
+            position    INTEGER NOT NULL,
+            PRIMARY KEY (pinboard_id, item_id)
+        ) WITHOUT ROWID;
+
+        CREATE INDEX idx_pinboard_items_item_id ON pinboard_items (item_id);
+
+        CREATE TRIGGER pinboard_items_insert AFTER INSERT ON pinboard_items BEGIN
+            UPDATE history SET pinned = 1 WHERE id = new.item_id;
+        END;
+
+        CREATE TRIGGER pinboard_items_delete AFTER DELETE ON pinboard_items BEGIN
+            UPDATE history
+            SET pinned = EXISTS (SELECT 1 FROM pinboard_items WHERE item_id = old.item_id)
+            WHERE id = old.item_id;
+        END;
+
+        CREATE TRIGGER pinboards_delete AFTER DELETE ON pinboards BEGIN
+            DELETE FROM pinboard_items WHERE pinboard_id = old.id;
+        END;
+
+        CREATE TRIGGER history_pinboard_items_delete AFTER DELETE ON history BEGIN
+            DELETE FROM pinboard_items WHERE item_id = old.id;
+        END;
+
+        INSERT INTO pinboards (name, position)
+            SELECT 'Pinned', 0 WHERE EXISTS (SELECT 1 FROM history WHERE pinned = 1);
+
+        INSERT INTO pinboard_items (pinboard_id, item_id, position)
+            SELECT (SELECT id FROM pinboards WHERE name = 'Pinned'), id, ROW_NUMBER() OVER (ORDER BY timestamp DESC, id DESC) - 1
+            FROM history
+            WHERE pinned = 1;",
+    )
+}
+
+/// Version 11: a note per item, and tags stored once in `tags` and linked to items by `item_tags`,
+/// so renaming a tag renames it on every item.
+///
+/// The full-text index is recreated with the note and the tags of each item, which are kept
+/// up to date by triggers on `history`, `item_tags` and `tags`.
+fn v11_tags_and_notes(tx: &Transaction) -> rusqlite::Result<()> {
+    // Indexes the `history` rows matching `condition`
+    let fts_rows = |condition: &str| {
+        format!(
+            "INSERT INTO history_fts (rowid, content, source_app, note, tags)
+                SELECT id, CASE WHEN content_type IN ('TEXT', 'FILES') THEN CAST(content AS TEXT) ELSE '' END, source_app, note,
+                    COALESCE((SELECT GROUP_CONCAT(tags.name, ' ') FROM item_tags JOIN tags ON tags.id = item_tags.tag_id WHERE item_tags.item_id = history.id), '')
+                FROM history
+                WHERE {condition};"
+        )
+    };
+
+    tx.execute_batch(&format!(
+        "ALTER TABLE history ADD COLUMN note TEXT NOT NULL DEFAULT '';
+
+        CREATE TABLE tags (
+            id   INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+
+        CREATE TABLE item_tags (
+            item_id INTEGER NOT NULL,
+            tag_id  INTEGER NOT NULL,
+            PRIMARY KEY (item_id, tag_id)
+        ) WITHOUT ROWID;
+
+        CREATE INDEX idx_item_tags_tag_id ON item_tags (tag_id);
+
+        CREATE TRIGGER history_item_tags_delete AFTER DELETE ON history BEGIN
+            DELETE FROM item_tags WHERE item_id = old.id;
+        END;
+
+        CREATE TRIGGER tags_delete AFTER DELETE ON tags BEGIN
+            DELETE FROM item_tags WHERE tag_id = old.id;
+        END;
+
+        DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_update;
+        DROP TABLE history_fts;
+
+        CREATE VIRTUAL TABLE history_fts USING fts5(
+                content,
+                source_app,
+                note,
+                tags,
+                tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        {all_rows}
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            {new_row}
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app, note ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+            {new_row}
+        END;
+
+        CREATE TRIGGER item_tags_fts_insert AFTER INSERT ON item_tags BEGIN
+            DELETE FROM history_fts WHERE rowid = new.item_id;
+            {new_item_row}
+        END;
+
+        CREATE TRIGGER item_tags_fts_delete AFTER DELETE ON item_tags BEGIN
+            DELETE FROM history_fts WHERE rowid = old.item_id;
+            {old_item_row}
+        END;
+
+        CREATE TRIGGER tags_fts_update AFTER UPDATE OF name ON tags BEGIN
+            DELETE FROM history_fts WHERE rowid IN (SELECT item_id FROM item_tags WHERE tag_id = new.id);
+            {tagged_rows}
+        END;",
+        all_rows = fts_rows("1"),
+        new_row = fts_rows("id = new.id"),
+        new_item_row = fts_rows("id = new.item_id"),
+        old_item_row = fts_rows("id = old.item_id"),
+        tagged_rows = fts_rows("id IN (SELECT item_id FROM item_tags WHERE tag_id = new.id)"),
+    ))
+}
+
+/// Version 12: the trash. Deleted items keep their row, with the time they were deleted in `deleted_at`,
+/// until the trash is emptied.
+fn v12_trash(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE history ADD COLUMN deleted_at TEXT;
+
+        CREATE INDEX idx_history_deleted_at ON history (deleted_at) WHERE deleted_at IS NOT NULL;",
+    )
+}
+
+/// Version 13: snippets, reusable texts saved by the user, with their own full-text index.
+///
+/// Snippets are matched by name when imported, so the names are unique.
+fn v13_snippets(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE snippets (
+            id        INTEGER PRIMARY KEY,
+            name      TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            content   TEXT NOT NULL,
+            timestamp TEXT NOT NULL
+        );
+
+        CREATE VIRTUAL TABLE snippets_fts USING fts5(
+                name,
+                content,
+                tokenize = 'unicode61 remove_diacritics 2'
+        );
+
+        CREATE TRIGGER snippets_fts_insert AFTER INSERT ON snippets BEGIN
+            INSERT INTO snippets_fts (rowid, name, content) VALUES (new.id, new.name, new.content);
+        END;
+
+        CREATE TRIGGER snippets_fts_update AFTER UPDATE OF name, content ON snippets BEGIN
+            DELETE FROM snippets_fts WHERE rowid = old.id;
+            INSERT INTO snippets_fts (rowid, name, content) VALUES (new.id, new.name, new.content);
+        END;
+
+        CREATE TRIGGER snippets_fts_delete AFTER DELETE ON snippets BEGIN
+            DELETE FROM snippets_fts WHERE rowid = old.id;
+        END;",
+    )
+}
+
+/// Version 14: the text of redacted and masked items is kept out of the full-text index,
+/// so their secrets can't be found by searching for them. Their app, note and tags still are.
+///
+/// The indexing triggers of version 11 are recreated, and now also run when an item is redacted or masked.
+fn v14_unindexed_private_text(tx: &Transaction) -> rusqlite::Result<()> {
+    // Indexes the `history` rows matching `condition`
+    let fts_rows = |condition: &str| {
+        format!(
+            "INSERT INTO history_fts (rowid, content, source_app, note, tags)
+                SELECT id, CASE WHEN content_type IN ('TEXT', 'FILES') AND NOT redacted AND NOT masked THEN CAST(content AS TEXT) ELSE '' END, source_app, note,
+                    COALESCE((SELECT GROUP_CONCAT(tags.name, ' ') FROM item_tags JOIN tags ON tags.id = item_tags.tag_id WHERE item_tags.item_id = history.id), '')
+                FROM history
+                WHERE {condition};"
+        )
+    };
+
+    tx.execute_batch(&format!(
+        "DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_update;
+        DROP TRIGGER item_tags_fts_insert;
+        DROP TRIGGER item_tags_fts_delete;
+        DROP TRIGGER tags_fts_update;
+
+        DELETE FROM history_fts WHERE rowid IN (SELECT id FROM history WHERE redacted OR masked);
+        {private_rows}
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            {new_row}
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app, note, redacted, masked ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+            {new_row}
+        END;
+
+        CREATE TRIGGER item_tags_fts_insert AFTER INSERT ON item_tags BEGIN
+            DELETE FROM history_fts WHERE rowid = new.item_id;
+            {new_item_row}
+        END;
+
+        CREATE TRIGGER item_tags_fts_delete AFTER DELETE ON item_tags BEGIN
+            DELETE FROM history_fts WHERE rowid = old.item_id;
+            {old_item_row}
+        END;
+
+        CREATE TRIGGER tags_fts_update AFTER UPDATE OF name ON tags BEGIN
+            DELETE FROM history_fts WHERE rowid IN (SELECT item_id FROM item_tags WHERE tag_id = new.id);
+            {tagged_rows}
+        END;",
+        private_rows = fts_rows("redacted OR masked"),
+        new_row = fts_rows("id = new.id"),
+        new_item_row = fts_rows("id = new.item_id"),
+        old_item_row = fts_rows("id = old.item_id"),
+        tagged_rows = fts_rows("id IN (SELECT item_id FROM item_tags WHERE tag_id = new.id)"),
+    ))
+}
+
+/// Version 15: settings stored in the database itself, such as the key images are encrypted with
+/// (see `encryption::open`), which SQLCipher protects along with the rest of the database.
+fn v15_settings(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE settings (
+            name  TEXT PRIMARY KEY,
+            value BLOB NOT NULL
+        ) WITHOUT ROWID;",
+    )
+}
+
+/// Version 16: items in the trash are kept out of the full-text index, they are back in it when restored.
+///
+/// The indexing triggers of version 14 are recreated, and now also run when an item is trashed or restored.
+fn v16_unindexed_trash(tx: &Transaction) -> rusqlite::Result<()> {
+    // Indexes the `history` rows matching `condition` that aren't in the trash
+    let fts_rows = |condition: &str| {
+        format!(
+            "INSERT INTO history_fts (rowid, content, source_app, note, tags)
+                SELECT id, CASE WHEN content_type IN ('TEXT', 'FILES') AND NOT redacted AND NOT masked THEN CAST(content AS TEXT) ELSE '' END, source_app, note,
+                    COALESCE((SELECT GROUP_CONCAT(tags.name, ' ') FROM item_tags JOIN tags ON tags.id = item_tags.tag_id WHERE item_tags.item_id = history.id), '')
+                FROM history
+                WHERE ({condition}) AND deleted_at IS NULL;"
+        )
+    };
+
+    tx.execute_batch(&format!(
+        "DROP TRIGGER history_fts_insert;
+        DROP TRIGGER history_fts_update;
+        DROP TRIGGER item_tags_fts_insert;
+        DROP TRIGGER item_tags_fts_delete;
+        DROP TRIGGER tags_fts_update;
+
+        DELETE FROM history_fts WHERE rowid IN (SELECT id FROM history WHERE deleted_at IS NOT NULL);
+
+        CREATE TRIGGER history_fts_insert AFTER INSERT ON history BEGIN
+            {new_row}
+        END;
+
+        CREATE TRIGGER history_fts_update AFTER UPDATE OF content_type, content, source_app, note, redacted, masked, deleted_at ON history BEGIN
+            DELETE FROM history_fts WHERE rowid = old.id;
+            {new_row}
+        END;
+
+        CREATE TRIGGER item_tags_fts_insert AFTER INSERT ON item_tags BEGIN
+            DELETE FROM history_fts WHERE rowid = new.item_id;
+            {new_item_row}
+        END;
+
+        CREATE TRIGGER item_tags_fts_delete AFTER DELETE ON item_tags BEGIN
+            DELETE FROM history_fts WHERE rowid = old.item_id;
+            {old_item_row}
+        END;
+
+        CREATE TRIGGER tags_fts_update AFTER UPDATE OF name ON tags BEGIN
+            DELETE FROM history_fts WHERE rowid IN (SELECT item_id FROM item_tags WHERE tag_id = new.id);
+            {tagged_rows}
+        END;",
+        new_row = fts_rows("id = new.id"),
+        new_item_row = fts_rows("id = new.item_id"),
+        old_item_row = fts_rows("id = old.item_id"),
+        tagged_rows = fts_rows("id IN (SELECT item_id FROM item_tags WHERE tag_id = new.id)"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `history` table of the app before migrations existed, at `user_version` 0.
+    const BASELINE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS history (
+            id INTEGER PRIMARY KEY,
+            source_app TEXT NOT NULL,
+            icon_path TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            content BLOB NOT NULL,
+            timestamp TEXT NOT NULL DEFAULT (DATETIME('NOW', 'UTC'))
+        )";
+
+    /// A database as the app left it at schema `version`, with a few items in it.
+    ///
+    /// - `hello world`, copied twice before content hashes existed (version 4).
+    /// - An image that can't be decoded, before images moved to the blob store (version 5).
+    /// - A pinned text, once items could be pinned (version 3).
+    fn fixture(version: usize) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        if version == 0 {
+            conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        } else {
+            let tx = conn.transaction().unwrap();
+            for migration in &MIGRATIONS[..version] {
+                migration(&tx).unwrap();
+            }
+            tx.pragma_update(None, "user_version", version as i64)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let insert = |source_app: &str, content_type: &str, content: &[u8], timestamp: &str| {
+            conn.query_row(
+                "INSERT INTO history (source_app, icon_path, content_type, content, timestamp)
+                 VALUES (?1, '', ?2, ?3, ?4)
+                 RETURNING id",
+                params![source_app, content_type, content, timestamp],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap()
+        };
+
+        let hello = insert("Code", "TEXT", b"hello world", "2025-01-01 10:00:00");
+
+        if version < 4 {
+            insert("Terminal", "TEXT", b"hello world", "2025-01-02 10:00:00");
+        } else {
+            conn.execute(
+                "UPDATE history SET content_hash = ?2 WHERE id = ?1",
+                params![hello, hash_text("hello world")],
+            )
+            .unwrap();
+        }
+
+        if version < 5 {
+            insert("Preview", "IMAGE", b"not a png", "2025-01-03 10:00:00");
+        }
+
+        if version >= 8 {
+            let redacted = insert(
+                "Terminal",
+                "TEXT",
+                b"secret redacted",
+                "2025-01-05 10:00:00",
+            );
+            conn.execute(
+                "UPDATE history SET redacted = 1, content_hash = ?2 WHERE id = ?1",
+                params![redacted, hash_text("secret redacted")],
+            )
+            .unwrap();
+        }
+
+        if version >= 9 {
+            let masked = insert("Terminal", "TEXT", b"secret masked", "2025-01-06 10:00:00");
+            conn.execute(
+                "UPDATE history SET masked = 1, content_hash = ?2 WHERE id = ?1",
+                params![masked, hash_text("secret masked")],
+            )
+            .unwrap();
+        }
+
+        if version >= 12 {
+            let trashed = insert("Finder", "TEXT", b"trashed", "2025-01-07 10:00:00");
+            conn.execute(
+                "UPDATE history SET deleted_at = '2025-01-08 10:00:00', content_hash = ?2 WHERE id = ?1",
+                params![trashed, hash_text("trashed")],
+            )
+            .unwrap();
+        }
+
+        if version >= 3 {
+            let pinned = insert("Notes", "TEXT", b"pinned", "2025-01-04 10:00:00");
+
+            if version >= 4 {
+                conn.execute(
+                    "UPDATE history SET content_hash = ?2 WHERE id = ?1",
+                    params![pinned, hash_text("pinned")],
+                )
+                .unwrap();
+            }
+
+            if version >= 10 {
+                conn.execute_batch(&format!(
+                    "INSERT INTO pinboards (id, name, position) VALUES (1, 'Pinned', 0);
+                     INSERT INTO pinboard_items (pinboard_id, item_id, position) VALUES (1, {pinned}, 0);"
+                ))
+                .unwrap();
+            } else {
+                conn.execute(
+                    "UPDATE history SET pinned = 1 WHERE id = ?1",
+                    params![pinned],
+                )
+                .unwrap();
+            }
+        }
+
+        conn
+    }
+
+    /// Insert a 1×1 image the way the app did before version 5, as a PNG in `content`, and return its hash.
+    fn insert_image(conn: &Connection, pixel: [u8; 4]) -> String {
+        let content_hash = hash_image(&ImageData {
+            width: 1,
+            height: 1,
+            bytes: Cow::Borrowed(&pixel),
+        });
+        let mut png = Vec::new();
+        image::RgbaImage::from_raw(1, 1, pixel.to_vec())
+            .unwrap()
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        conn.execute(
+            "INSERT INTO history (source_app, icon_path, content_type, content, content_hash)
+             VALUES ('Preview', '', 'IMAGE', ?1, ?2)",
+            params![png, content_hash],
+        )
+        .unwrap();
+
+        content_hash
+    }
+
+    fn names(conn: &Connection, sql: &str) -> Vec<String> {
+        let mut names = conn
+            .prepare(sql)
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .unwrap();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn upgrades_every_historical_schema() {
+        for version in 0..MIGRATIONS.len() {
+            let mut conn = fixture(version);
+            migrate(&mut conn).unwrap();
+
+            assert_eq!(
+                schema_version(&conn).unwrap(),
+                LATEST_VERSION,
+                "from version {version}"
+            );
+
+            let columns = conn
+                .prepare("SELECT name FROM pragma_table_info('history')")
+                .unwrap()
+                .query_map([], |row| row.get::<_, String>(0))
+                .unwrap()
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(
+                columns,
+                [
+                    "id",
+                    "source_app",
+                    "icon_path",
+                    "content_type",
+                    "content",
+                    "timestamp",
+                    "pinned",
+                    "content_hash",
+                    "size",
+                    "redacted",
+                    "expires_at",
+                    "masked",
+                    "note",
+                    "deleted_at",
+                ],
+                "from version {version}"
+            );
+
+            assert_eq!(
+                names(
+                    &conn,
+                    "SELECT name FROM sqlite_master WHERE type = 'index' AND name LIKE 'idx_%'"
+                ),
+                [
+                    "idx_history_content_hash",
+                    "idx_history_deleted_at",
+                    "idx_history_expires_at",
+                    "idx_history_timestamp",
+                    "idx_item_tags_tag_id",
+                    "idx_pinboard_items_item_id",
+                ],
+                "from version {version}"
+            );
+
+            assert_eq!(
+                names(
+                    &conn,
+                    "SELECT name FROM sqlite_master WHERE type = 'trigger'"
+                ),
+                [
+                    "history_fts_delete",
+                    "history_fts_insert",
+                    "history_fts_update",
+                    "history_item_files_delete",
+                    "history_item_tags_delete",
+                    "history_pinboard_items_delete",
+                    "history_representations_delete",
+                    "item_tags_fts_delete",
+                    "item_tags_fts_insert",
+                    "pinboard_items_delete",
+                    "pinboard_items_insert",
+                    "pinboards_delete",
+                    "snippets_fts_delete",
+                    "snippets_fts_insert",
+                    "snippets_fts_update",
+                    "tags_delete",
+                    "tags_fts_update",
+                ],
+                "from version {version}"
+            );
+
+            // Duplicates left by older versions are merged into the latest copy, undecodable images are dropped
+            let items: Vec<(String, String, String)> = conn
+                .prepare(
+                    "SELECT source_app, CAST(content AS TEXT), content_hash FROM history
+                     WHERE NOT redacted AND NOT masked AND deleted_at IS NULL
+                     ORDER BY id",
+                )
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap();
+            let hello_app = if version < 4 { "Terminal" } else { "Code" };
+            let mut expected = vec![(
+                hello_app.to_string(),
+                "hello world".to_string(),
+                hash_text("hello world"),
+            )];
+            if version >= 3 {
+                expected.push((
+                    "Notes".to_string(),
+                    "pinned".to_string(),
+                    hash_text("pinned"),
+                ));
+            }
+            assert_eq!(items, expected, "from version {version}");
+
+            let matches = names(
+                &conn,
+                "SELECT CAST(history.content AS TEXT) FROM history_fts JOIN history ON history.id = history_fts.rowid
+                 WHERE history_fts MATCH 'hello OR pinned OR source_app:Code'",
+            );
+            assert_eq!(
+                matches.len(),
+                expected.len(),
+                "from version {version}: {matches:?}"
+            );
+
+            // Only the app of private items is indexed
+            let private_matches = |query: &str| -> i64 {
+                conn.query_row(
+                    "SELECT COUNT(*) FROM history_fts WHERE history_fts MATCH ?1",
+                    params![query],
+                    |row| row.get(0),
+                )
+                .unwrap()
+            };
+            assert_eq!(private_matches("secret"), 0, "from version {version}");
+            assert_eq!(
+                private_matches("trashed OR source_app:Finder"),
+                0,
+                "from version {version}"
+            );
+            assert_eq!(
+                private_matches("source_app:Terminal"),
+                (version >= 8) as i64 + (version >= 9) as i64 + (version < 4) as i64,
+                "from version {version}"
+            );
+
+            if version >= 3 {
+                let (pinned, pinboard): (bool, String) = conn
+                    .query_row(
+                        "SELECT history.pinned, pinboards.name FROM history
+                         JOIN pinboard_items ON pinboard_items.item_id = history.id
+                         JOIN pinboards ON pinboards.id = pinboard_items.pinboard_id
+                         WHERE CAST(history.content AS TEXT) = 'pinned'",
+                        [],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .unwrap();
+                assert!(pinned, "from version {version}");
+                assert_eq!(pinboard, "Pinned", "from version {version}");
+            }
+        }
+    }
+
+    #[test]
+    fn images_are_moved_to_the_blob_store_once_the_schema_is_upgraded() {
+        let mut conn = fixture(4);
+
+        let content_hash = insert_image(&conn, [10, 20, 30, 255]);
+
+        // A migration that is rolled back leaves no file behind
+        let tx = conn.transaction().unwrap();
+        for migration in &MIGRATIONS[4..] {
+            migration(&tx).unwrap();
+        }
+        drop(tx);
+        assert!(!blobs::image_path(&content_hash).exists());
+
+        migrate(&mut conn).unwrap();
+
+        let (content, size): (Vec<u8>, i64) = conn
+            .query_row(
+                "SELECT content, size FROM history WHERE content_hash = ?1",
+                params![content_hash],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(content.is_empty());
+        assert_eq!(size, blobs::read_png(&content_hash).unwrap().len() as i64);
+        assert!(blobs::thumbnail_path(&content_hash).exists());
+    }
+
+    #[test]
+    fn interrupted_image_extraction_resumes() {
+        // As left by a run that stopped after upgrading the schema, before moving the image
+        let mut conn = fixture(LATEST_VERSION as usize);
+
+        let content_hash = insert_image(&conn, [30, 20, 10, 255]);
+
+        migrate(&mut conn).unwrap();
+
+        let content: Vec<u8> = conn
+            .query_row(
+                "SELECT content FROM history WHERE content_hash = ?1",
+                params![content_hash],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(content.is_empty());
+        assert!(blobs::image_path(&content_hash).exists());
+    }
+
+    #[test]
+    fn private_items_are_unindexed_when_they_change() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let matches = |conn: &Connection| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM history_fts WHERE history_fts MATCH 'hunter2'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        conn.execute_batch(
+            "INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash)
+             VALUES (1, 'Terminal', '', 'TEXT', 'password hunter2', 'hash')",
+        )
+        .unwrap();
+        assert_eq!(matches(&conn), 1);
+
+        conn.execute_batch("UPDATE history SET masked = 1 WHERE id = 1")
+            .unwrap();
+        assert_eq!(matches(&conn), 0);
+
+        // Tagging an item reindexes it, still without its text
+        conn.execute_batch(
+            "INSERT INTO tags (id, name) VALUES (1, 'work');
+             INSERT INTO item_tags (item_id, tag_id) VALUES (1, 1);",
+        )
+        .unwrap();
+        assert_eq!(matches(&conn), 0);
+
+        conn.execute_batch("UPDATE history SET masked = 0, redacted = 1 WHERE id = 1")
+            .unwrap();
+        assert_eq!(matches(&conn), 0);
+
+        conn.execute_batch("UPDATE history SET redacted = 0 WHERE id = 1")
+            .unwrap();
+        assert_eq!(matches(&conn), 1);
+    }
+
+    #[test]
+    fn trashed_items_are_unindexed_until_restored() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        let matches = |conn: &Connection| -> i64 {
+            conn.query_row(
+                "SELECT COUNT(*) FROM history_fts WHERE history_fts MATCH 'invoice'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        conn.execute_batch(
+            "INSERT INTO history (id, source_app, icon_path, content_type, content, content_hash)
+             VALUES (1, 'Mail', '', 'TEXT', 'invoice 42', 'hash')",
+        )
+        .unwrap();
+        assert_eq!(matches(&conn), 1);
+
+        conn.execute_batch("UPDATE history SET deleted_at = DATETIME('NOW', 'UTC') WHERE id = 1")
+            .unwrap();
+        assert_eq!(matches(&conn), 0);
+
+        // Tagging a trashed item doesn't index it again
+        conn.execute_batch(
+            "INSERT INTO tags (id, name) VALUES (1, 'work');
+             INSERT INTO item_tags (item_id, tag_id) VALUES (1, 1);",
+        )
+        .unwrap();
+        assert_eq!(matches(&conn), 0);
+
+        conn.execute_batch("UPDATE history SET deleted_at = NULL WHERE id = 1")
+            .unwrap();
+        assert_eq!(matches(&conn), 1);
+    }
+
+    #[test]
+    fn new_databases_start_at_the_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), LATEST_VERSION);
+
+        // Migrating again is a no-op
+        migrate(&mut conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), LATEST_VERSION);
+    }
+
+    #[test]
+    fn databases_are_switched_to_incremental_vacuum() {
+        let auto_vacuum = |conn: &Connection| {
+            conn.pragma_query_value(None, "auto_vacuum", |row| row.get::<_, i64>(0))
+                .unwrap()
+        };
+
+        let mut conn = fixture(LATEST_VERSION as usize);
+        assert_eq!(auto_vacuum(&conn), 0);
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(auto_vacuum(&conn), 2);
+    }
+
+    #[test]
+    fn newer_databases_are_left_untouched() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", LATEST_VERSION + 1)
+            .unwrap();
+
+        let result = migrate(&mut conn);
+
+        assert!(matches!(
+            result,
+            Err(MigrationError::TooNew { found, supported }) if found == LATEST_VERSION + 1 && supported == LATEST_VERSION
+        ));
+        assert_eq!(schema_version(&conn).unwrap(), LATEST_VERSION + 1);
+        assert!(names(&conn, "SELECT name FROM sqlite_master").is_empty());
+    }
+}
@@ -1,3 +1,24 @@
+pub mod archive;
+pub mod blobs;
+pub mod client;
 pub mod clipboard;
+pub mod config;
+pub mod daemon;
+pub mod encryption;
+#[cfg(target_os = "linux")]
+pub mod linux;
+#[cfg(target_os = "macos")]
 pub mod macos;
+pub mod merge;
+pub mod migrations;
+pub mod paste;
+pub mod platform;
+pub mod privacy;
+pub mod retention;
+pub mod search;
+pub mod secrets;
+pub mod snippets;
+pub mod stack;
+pub mod storage;
+pub mod transforms;
 pub mod utils;
@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::backend::config::{self, PasteMode};
+use crate::backend::platform;
+
+/// What owned the focus before the `Paste` window opened, see `platform::focused_target`. `0` if unknown.
+static PREVIOUS_TARGET: AtomicU64 = AtomicU64::new(0);
+
+/// Remember the app that owns the focus, right before the `Paste` window takes it.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::paste;
+///
+/// paste::remember_focus();
+/// window.set_visible(true);
+/// ```
+pub fn remember_focus() {
+    let target = platform::focused_target().unwrap_or(0);
+    log::trace!("Focused before the Paste window: {}", target);
+
+    PREVIOUS_TARGET.store(target, Ordering::SeqCst);
+}
+
+/// Give the focus back to the app remembered by `remember_focus`, and paste the clipboard into it.
+///
+/// This is called once the picked item is on the system clipboard. With `mode = "copy"` in the
+/// `[paste]` config, or when the keystroke can't be synthesized (see `platform::send_paste_keystroke`),
+/// the item is only left on the clipboard, for the user to paste.
+///
+/// Returns whether the paste keystroke was sent.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::{client, paste};
+///
+/// client::set_clipboard(42, false).unwrap();
+/// println!("{}", paste::paste_into_previous_app().await); // Output: true
+/// ```
+pub async fn paste_into_previous_app() -> bool {
+    let policy = &config::get().paste;
+    let target = PREVIOUS_TARGET.swap(0, Ordering::SeqCst);
+
+    if target == 0 || !platform::activate(target) {
+        log::warn!("The app focused before the Paste window is gone, only copying");
+        return false;
+    }
+
+    if policy.mode == PasteMode::Copy {
+        return false;
+    }
+
+    // The app only receives the keystroke once it has the focus back
+    tokio::time::sleep(Duration::from_millis(policy.delay_ms)).await;
+
+    let sent = platform::send_paste_keystroke();
+    if !sent {
+        log::warn!("Failed to paste, the item is left on the clipboard");
+    }
+
+    sent
+}
+
+/// Move the caret of the app `paste_into_previous_app` pasted into `count` characters back,
+/// e.g. to the `{cursor}` of a snippet.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::{client, paste};
+///
+/// let rendered = client::copy_snippet(1, &Default::default()).unwrap();
+/// if paste::paste_into_previous_app().await {
+///     paste::move_cursor_back(rendered.cursor_offset);
+/// }
+/// ```
+pub fn move_cursor_back(count: usize) -> bool {
+    if count == 0 {
+        return true;
+    }
+
+    let moved = platform::send_left_arrow_keystrokes(count);
+    if !moved {
+        log::warn!("Failed to move the cursor back by {} characters", count);
+    }
+
+    moved
+}
@@ -0,0 +1,250 @@
+use std::path::PathBuf;
+
+use crate::backend::clipboard::Format;
+
+/// Platform-specific information about the application that currently owns the focus.
+///
+/// Every supported OS provides exactly one implementation, which is selected at
+/// compile time through the [`Platform`] alias. The capture pipeline only talks to
+/// the free functions of this module, so it never needs to know which OS it runs on.
+pub trait FocusedAppProvider {
+    /// Return the display name of the current focused application.
+    fn current_focus_app_name() -> String;
+
+    /// Return the path of the current focused application.
+    ///
+    /// This is the bundle path on macOS and the executable path on Linux.
+    fn current_focus_app_path() -> PathBuf;
+
+    /// Return the icon file path of the current focused application.
+    fn current_focus_app_icon_path() -> PathBuf;
+
+    /// Return a stable identifier of the current focused application.
+    ///
+    /// This is the bundle identifier on macOS and the `WM_CLASS` class name on Linux.
+    fn current_focus_app_id() -> String;
+
+    /// Return the title of the current focused window, or an empty string if it has none.
+    fn current_focus_window_title() -> String;
+}
+
+/// Platform-specific access to the clipboard formats that `arboard` doesn't cover, such as RTF.
+pub trait ClipboardFormatProvider {
+    /// Return the data the system clipboard currently holds in `format`, if it offers that format.
+    fn clipboard_data(format: &Format) -> Option<Vec<u8>>;
+
+    /// Add `data` in `format` to what is currently on the system clipboard, without replacing it.
+    ///
+    /// Returns `false` if the platform can't add a format to an existing clipboard.
+    fn add_clipboard_data(format: &Format, data: &[u8]) -> bool;
+
+    /// Whether `add_clipboard_data` works on this platform. If it doesn't, formats other than HTML
+    /// (which `arboard` restores itself) are not saved, as they could never be pasted again.
+    fn can_add_clipboard_data() -> bool;
+
+    /// Return every type the system clipboard currently offers: UTIs on macOS, X11 targets on Linux.
+    fn clipboard_types() -> Vec<String>;
+}
+
+/// Platform-specific control of the focus and the keyboard, used to paste into other apps.
+pub trait PasteProvider {
+    /// Return an identifier of what currently owns the focus, to give it back later with `activate`.
+    ///
+    /// This is the process id of the frontmost app on macOS and the active X11 window on Linux.
+    fn focused_target() -> Option<u64>;
+
+    /// Give the focus to `target`, a value returned by `focused_target`.
+    ///
+    /// Returns `false` if the target is gone or can't be activated.
+    fn activate(target: u64) -> bool;
+
+    /// Synthesize the paste keystroke (`⌘ V` on macOS, `Ctrl V` on Linux) in the focused app.
+    ///
+    /// Returns `false` if the platform doesn't allow it, e.g. without the Accessibility permission
+    /// on macOS, or without the XTEST extension (or an X server at all) on Linux.
+    fn send_paste_keystroke() -> bool;
+
+    /// Move the caret of the focused app `count` characters back, by synthesizing `←` `count` times.
+    ///
+    /// Fails like `send_paste_keystroke`.
+    fn send_left_arrow_keystrokes(count: usize) -> bool;
+}
+
+#[cfg(target_os = "macos")]
+pub type Platform = crate::backend::macos::MacOS;
+
+#[cfg(target_os = "linux")]
+pub type Platform = crate::backend::linux::Linux;
+
+/// Return the name of the current focused application.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::current_focus_app_name;
+///
+/// println!("{}", current_focus_app_name()); // Output: "Code"
+/// ```
+pub fn current_focus_app_name() -> String {
+    Platform::current_focus_app_name()
+}
+
+/// Return the path of the current focused application.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::current_focus_app_path;
+///
+/// println!("{:?}", current_focus_app_path()); // Output: "/Applications/Visual Studio Code.app"
+/// ```
+pub fn current_focus_app_path() -> PathBuf {
+    Platform::current_focus_app_path()
+}
+
+/// Return the icon file path of the current focused application.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::current_focus_app_icon_path;
+///
+/// println!("{:?}", current_focus_app_icon_path()); // Output: "~/Library/Application Support/paste-fork/icons/Code.png"
+/// ```
+pub fn current_focus_app_icon_path() -> PathBuf {
+    Platform::current_focus_app_icon_path()
+}
+
+/// Return a stable identifier of the current focused application.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::current_focus_app_id;
+///
+/// println!("{}", current_focus_app_id()); // Output: "com.microsoft.VSCode"
+/// ```
+pub fn current_focus_app_id() -> String {
+    Platform::current_focus_app_id()
+}
+
+/// Return the title of the current focused window.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::current_focus_window_title;
+///
+/// println!("{}", current_focus_window_title()); // Output: "main.rs — paste-fork"
+/// ```
+pub fn current_focus_window_title() -> String {
+    Platform::current_focus_window_title()
+}
+
+/// Return the data the system clipboard currently holds in `format`, if it offers that format.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::clipboard::Format;
+/// use crate::backend::platform::clipboard_data;
+///
+/// println!("{:?}", clipboard_data(&Format::Rtf)); // Output: Some([123, 92, 114, 116, 102, ...])
+/// ```
+pub fn clipboard_data(format: &Format) -> Option<Vec<u8>> {
+    Platform::clipboard_data(format)
+}
+
+/// Return every type the system clipboard currently offers.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::clipboard_types;
+///
+/// println!("{:?}", clipboard_types()); // Output: ["public.utf8-plain-text", "org.nspasteboard.ConcealedType"]
+/// ```
+pub fn clipboard_types() -> Vec<String> {
+    Platform::clipboard_types()
+}
+
+/// Add `data` in `format` to what is currently on the system clipboard, without replacing it.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::clipboard::Format;
+/// use crate::backend::platform::add_clipboard_data;
+///
+/// clipboard.set_text("Hello").unwrap();
+/// add_clipboard_data(&Format::Rtf, br"{\rtf1 \b Hello\b0}");
+/// ```
+pub fn add_clipboard_data(format: &Format, data: &[u8]) -> bool {
+    Platform::add_clipboard_data(format, data)
+}
+
+/// Whether formats can be added to the system clipboard, see `ClipboardFormatProvider::can_add_clipboard_data`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::can_add_clipboard_data;
+///
+/// println!("{}", can_add_clipboard_data()); // Output: true
+/// ```
+pub fn can_add_clipboard_data() -> bool {
+    Platform::can_add_clipboard_data()
+}
+
+/// Return an identifier of what currently owns the focus, see `PasteProvider::focused_target`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::focused_target;
+///
+/// println!("{:?}", focused_target()); // Output: Some(54525958)
+/// ```
+pub fn focused_target() -> Option<u64> {
+    Platform::focused_target()
+}
+
+/// Give the focus to a target returned by `focused_target`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::{activate, focused_target};
+///
+/// let target = focused_target().unwrap();
+/// println!("{}", activate(target)); // Output: true
+/// ```
+pub fn activate(target: u64) -> bool {
+    Platform::activate(target)
+}
+
+/// Synthesize the paste keystroke in the focused app.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::send_paste_keystroke;
+///
+/// println!("{}", send_paste_keystroke()); // Output: true
+/// ```
+pub fn send_paste_keystroke() -> bool {
+    Platform::send_paste_keystroke()
+}
+
+/// Move the caret of the focused app `count` characters back.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::send_left_arrow_keystrokes;
+///
+/// println!("{}", send_left_arrow_keystrokes(3)); // Output: true
+/// ```
+pub fn send_left_arrow_keystrokes(count: usize) -> bool {
+    Platform::send_left_arrow_keystrokes(count)
+}
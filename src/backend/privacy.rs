@@ -0,0 +1,417 @@
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::backend::config::{self, AppRule, PrivacyMode, RuleAction};
+use crate::backend::platform::{
+    current_focus_app_id, current_focus_app_name, current_focus_app_path,
+    current_focus_window_title,
+};
+
+/// Copies from these apps are never recorded, unless a rule of the user says otherwise.
+const BUILTIN_IGNORED_APPS: &[&str] = &["Passwords", "Keychain Access", "Bitwarden"];
+
+/// How long copies are kept by an `expire` rule without `expire_after_minutes`.
+const DEFAULT_EXPIRE_MINUTES: u64 = 5;
+
+static RULES: Lazy<Vec<Rule>> = Lazy::new(|| compile_rules(&config::get().privacy.rules));
+
+/// The application a clipboard change comes from.
+#[derive(Clone, Debug, Default)]
+pub struct FocusedApp {
+    pub name: String,
+    pub id: String,
+    pub path: String,
+    pub window_title: String,
+}
+
+impl FocusedApp {
+    /// Query the platform for the current focused application.
+    pub fn current() -> Self {
+        FocusedApp {
+            name: current_focus_app_name(),
+            id: current_focus_app_id(),
+            path: current_focus_app_path().to_string_lossy().to_string(),
+            window_title: current_focus_window_title(),
+        }
+    }
+}
+
+/// What happens to a clipboard change.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CaptureAction {
+    /// Save it as usual.
+    Record,
+    /// Don't save it at all.
+    Ignore,
+    /// Save it, but hide its content in the history.
+    Redact,
+    /// Save it, but delete it once the duration has passed.
+    Expire(Duration),
+}
+
+impl CaptureAction {
+    fn from_config(action: RuleAction, expire_after_minutes: Option<u64>) -> Self {
+        match action {
+            RuleAction::Record => CaptureAction::Record,
+            RuleAction::Ignore => CaptureAction::Ignore,
+            RuleAction::Redact => CaptureAction::Redact,
+            RuleAction::Expire => CaptureAction::Expire(Duration::minutes(
+                expire_after_minutes.unwrap_or(DEFAULT_EXPIRE_MINUTES) as i64,
+            )),
+        }
+    }
+
+    /// When an item saved now with this action should be deleted, if ever.
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        match self {
+            CaptureAction::Expire(duration) => Some(Utc::now() + *duration),
+            _ => None,
+        }
+    }
+}
+
+/// A flag the app that copied something puts on the clipboard next to it.
+///
+/// See <http://nspasteboard.org> for the macOS conventions. On Linux, KDE's Klipper and
+/// KeePassXC use the `x-kde-passwordManagerHint` target for concealed data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClipboardMarker {
+    Concealed,
+    Transient,
+    AutoGenerated,
+}
+
+/// The clipboard types that flag a copy, and the marker they stand for.
+const MARKER_TYPES: &[(&str, ClipboardMarker)] = &[
+    ("org.nspasteboard.ConcealedType", ClipboardMarker::Concealed),
+    ("org.nspasteboard.TransientType", ClipboardMarker::Transient),
+    (
+        "org.nspasteboard.AutoGeneratedType",
+        ClipboardMarker::AutoGenerated,
+    ),
+    // Older types some apps still use instead of the nspasteboard.org ones
+    ("com.agilebits.onepassword", ClipboardMarker::Concealed),
+    (
+        "de.petermaurer.TransientPasteboardType",
+        ClipboardMarker::Transient,
+    ),
+    ("com.typeit4me.clipping", ClipboardMarker::Transient),
+    ("Pasteboard generator type", ClipboardMarker::AutoGenerated),
+    ("x-kde-passwordManagerHint", ClipboardMarker::Concealed),
+];
+
+/// Return the markers among the types the system clipboard currently offers.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::platform::clipboard_types;
+/// use crate::backend::privacy;
+///
+/// println!("{:?}", privacy::markers(&clipboard_types())); // Output: [Concealed]
+/// ```
+pub fn markers(types: &[String]) -> Vec<ClipboardMarker> {
+    MARKER_TYPES
+        .iter()
+        .filter(|(marker_type, _)| types.iter().any(|type_| type_ == marker_type))
+        .map(|(_, marker)| *marker)
+        .collect()
+}
+
+/// Decide what happens to a clipboard change flagged with `markers`, whichever app it comes from.
+///
+/// Returns one action per marker, none if there are no markers.
+pub fn action_for_markers(markers: &[ClipboardMarker]) -> Vec<CaptureAction> {
+    let policy = &config::get().privacy.markers;
+
+    markers
+        .iter()
+        .map(|marker| {
+            let action = match marker {
+                ClipboardMarker::Concealed => policy.concealed,
+                ClipboardMarker::Transient => policy.transient,
+                ClipboardMarker::AutoGenerated => policy.auto_generated,
+            };
+
+            CaptureAction::from_config(action, policy.expire_after_minutes)
+        })
+        .collect()
+}
+
+/// A compiled `AppRule`.
+struct Rule {
+    app: Option<String>, // Lowercased
+    app_id: Option<String>,
+    path: Option<String>,
+    window_title: Option<Regex>,
+    action: CaptureAction,
+}
+
+impl Rule {
+    /// Returns `None` if the window title isn't a valid regular expression.
+    fn compile(rule: &AppRule) -> Option<Self> {
+        let window_title = match rule.window_title.as_deref().map(Regex::new).transpose() {
+            Ok(window_title) => window_title,
+            Err(err) => {
+                log::error!("Skipping privacy rule {:?}: {}", rule, err);
+                return None;
+            }
+        };
+
+        Some(Rule {
+            app: rule.app.as_ref().map(|app| app.to_lowercase()),
+            app_id: rule.app_id.clone(),
+            path: rule.path.clone(),
+            window_title,
+            action: CaptureAction::from_config(rule.action, rule.expire_after_minutes),
+        })
+    }
+
+    fn matches(&self, app: &FocusedApp) -> bool {
+        let has_matcher = self.app.is_some()
+            || self.app_id.is_some()
+            || self.path.is_some()
+            || self.window_title.is_some();
+
+        has_matcher
+            && self
+                .app
+                .as_ref()
+                .is_none_or(|name| app.name.to_lowercase().contains(name))
+            && self.app_id.as_ref().is_none_or(|id| app.id == *id)
+            && self
+                .path
+                .as_ref()
+                .is_none_or(|path| app.path.contains(path))
+            && self
+                .window_title
+                .as_ref()
+                .is_none_or(|title| title.is_match(&app.window_title))
+    }
+}
+
+/// Compiles the rules of the user, followed by the built-in password managers.
+fn compile_rules(rules: &[AppRule]) -> Vec<Rule> {
+    rules
+        .iter()
+        .filter_map(Rule::compile)
+        .chain(BUILTIN_IGNORED_APPS.iter().map(|app| Rule {
+            app: Some(app.to_lowercase()),
+            app_id: None,
+            path: None,
+            window_title: None,
+            action: CaptureAction::Ignore,
+        }))
+        .collect()
+}
+
+/// Decide what happens to a clipboard change, depending on the app it comes from.
+///
+/// The rules of `config.toml` are checked in order, followed by the built-in password managers.
+/// The first matching rule wins. If none matches, the copy is recorded in blocklist mode
+/// and ignored in allowlist mode.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::privacy::{self, CaptureAction, FocusedApp};
+///
+/// let action = privacy::action_for(&FocusedApp::current());
+/// println!("{:?}", action); // Output: Record
+/// ```
+pub fn action_for(app: &FocusedApp) -> CaptureAction {
+    first_match(&RULES, config::get().privacy.mode, app)
+}
+
+/// The action of the first of `rules` matching `app`, see `action_for`.
+fn first_match(rules: &[Rule], mode: PrivacyMode, app: &FocusedApp) -> CaptureAction {
+    if let Some(rule) = rules.iter().find(|rule| rule.matches(app)) {
+        return rule.action.clone();
+    }
+
+    match mode {
+        PrivacyMode::Blocklist => CaptureAction::Record,
+        PrivacyMode::Allowlist => CaptureAction::Ignore,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str, id: &str, path: &str, window_title: &str) -> FocusedApp {
+        FocusedApp {
+            name: name.to_string(),
+            id: id.to_string(),
+            path: path.to_string(),
+            window_title: window_title.to_string(),
+        }
+    }
+
+    fn rule(action: RuleAction) -> AppRule {
+        AppRule {
+            action,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn every_set_field_has_to_match() {
+        let rules = compile_rules(&[AppRule {
+            app: Some("keepass".to_string()),
+            app_id: Some("org.keepassxc.KeePassXC".to_string()),
+            path: Some("/usr/bin".to_string()),
+            window_title: Some("(?i)^vault".to_string()),
+            ..rule(RuleAction::Redact)
+        }]);
+        let keepass = app(
+            "KeePassXC",
+            "org.keepassxc.KeePassXC",
+            "/usr/bin/keepassxc",
+            "Vault - KeePassXC",
+        );
+
+        assert_eq!(
+            first_match(&rules, PrivacyMode::Blocklist, &keepass),
+            CaptureAction::Redact
+        );
+
+        for other in [
+            FocusedApp {
+                name: "Terminal".to_string(),
+                ..keepass.clone()
+            },
+            FocusedApp {
+                id: "org.keepassxc".to_string(),
+                ..keepass.clone()
+            },
+            FocusedApp {
+                path: "/opt/keepassxc".to_string(),
+                ..keepass.clone()
+            },
+            FocusedApp {
+                window_title: "Open Vault".to_string(),
+                ..keepass.clone()
+            },
+        ] {
+            assert_eq!(
+                first_match(&rules, PrivacyMode::Blocklist, &other),
+                CaptureAction::Record,
+                "{other:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rules_without_matchers_never_match() {
+        let rules = compile_rules(&[rule(RuleAction::Ignore)]);
+
+        assert_eq!(
+            first_match(
+                &rules,
+                PrivacyMode::Blocklist,
+                &app("Code", "code", "/usr/bin/code", "main.rs")
+            ),
+            CaptureAction::Record
+        );
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = compile_rules(&[
+            AppRule {
+                window_title: Some("Private Browsing".to_string()),
+                ..rule(RuleAction::Ignore)
+            },
+            AppRule {
+                app: Some("Firefox".to_string()),
+                expire_after_minutes: Some(10),
+                ..rule(RuleAction::Expire)
+            },
+            AppRule {
+                app: Some("fire".to_string()),
+                ..rule(RuleAction::Record)
+            },
+        ]);
+
+        assert_eq!(
+            first_match(
+                &rules,
+                PrivacyMode::Blocklist,
+                &app("firefox", "", "", "Private Browsing")
+            ),
+            CaptureAction::Ignore
+        );
+        assert_eq!(
+            first_match(
+                &rules,
+                PrivacyMode::Blocklist,
+                &app("firefox", "", "", "Docs")
+            ),
+            CaptureAction::Expire(Duration::minutes(10))
+        );
+    }
+
+    #[test]
+    fn user_rules_come_before_builtin_ones() {
+        let bitwarden = app("Bitwarden", "", "", "");
+
+        assert_eq!(
+            first_match(&compile_rules(&[]), PrivacyMode::Blocklist, &bitwarden),
+            CaptureAction::Ignore
+        );
+
+        let rules = compile_rules(&[AppRule {
+            app: Some("bitwarden".to_string()),
+            ..rule(RuleAction::Expire)
+        }]);
+        assert_eq!(
+            first_match(&rules, PrivacyMode::Blocklist, &bitwarden),
+            CaptureAction::Expire(Duration::minutes(DEFAULT_EXPIRE_MINUTES as i64))
+        );
+    }
+
+    #[test]
+    fn allowlist_ignores_unmatched_apps() {
+        let rules = compile_rules(&[AppRule {
+            app: Some("Code".to_string()),
+            ..rule(RuleAction::Record)
+        }]);
+
+        assert_eq!(
+            first_match(&rules, PrivacyMode::Allowlist, &app("Code", "", "", "")),
+            CaptureAction::Record
+        );
+        assert_eq!(
+            first_match(&rules, PrivacyMode::Allowlist, &app("Terminal", "", "", "")),
+            CaptureAction::Ignore
+        );
+    }
+
+    #[test]
+    fn invalid_window_titles_skip_the_rule() {
+        let rules = compile_rules(&[AppRule {
+            window_title: Some("(unclosed".to_string()),
+            ..rule(RuleAction::Ignore)
+        }]);
+
+        assert_eq!(rules.len(), BUILTIN_IGNORED_APPS.len());
+    }
+
+    #[test]
+    fn finds_markers_among_clipboard_types() {
+        let types = [
+            "text/plain",
+            "x-kde-passwordManagerHint",
+            "org.nspasteboard.TransientType",
+        ]
+        .map(str::to_string);
+
+        assert_eq!(
+            markers(&types),
+            [ClipboardMarker::Transient, ClipboardMarker::Concealed]
+        );
+        assert_eq!(markers(&["text/plain".to_string()]), []);
+    }
+}
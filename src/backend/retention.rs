@@ -0,0 +1,366 @@
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, params_from_iter, Connection};
+use std::thread;
+use std::time::Instant;
+
+use crate::backend::blobs;
+use crate::backend::clipboard::{self, db_conn, TIMESTAMP_FORMAT};
+use crate::backend::config::{self, RetentionPolicy};
+
+/// How often items saved with an expiry (see `privacy::CaptureAction::Expire`) are looked for.
+const EXPIRY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Prune the clipboard history according to the retention policy, forever.
+///
+/// The history is pruned once right away and then every `prune_interval_minutes`.
+/// Expired items are deleted in between, every minute. Nothing is pruned while the history is locked.
+/// This function never returns, so it should be run on its own thread.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::retention;
+///
+/// std::thread::spawn(retention::run_pruner);
+/// ```
+pub fn run_pruner() {
+    let policy = &config::get().retention;
+    let interval = std::time::Duration::from_secs(policy.prune_interval_minutes.max(1) * 60);
+
+    let mut last_prune: Option<Instant> = None;
+
+    loop {
+        if clipboard::is_locked() {
+            log::trace!("Clipboard history is locked, not pruning");
+        } else if last_prune.is_none_or(|last_prune| last_prune.elapsed() >= interval) {
+            match db_conn().and_then(|conn| prune(&conn, policy)) {
+                Ok(0) => log::trace!("Nothing to prune"),
+                Ok(deleted) => log::info!("Pruned {} clipboard items", deleted),
+                Err(err) => log::error!("Failed to prune clipboard history: {}", err),
+            }
+            last_prune = Some(Instant::now());
+        } else {
+            match db_conn().and_then(|conn| delete_expired(&conn)) {
+                Ok(0) => log::trace!("Nothing expired"),
+                Ok(deleted) => log::info!("Deleted {} expired clipboard items", deleted),
+                Err(err) => log::error!("Failed to delete expired clipboard items: {}", err),
+            }
+        }
+
+        thread::sleep(EXPIRY_CHECK_INTERVAL.min(interval));
+    }
+}
+
+/// Delete every unpinned item that violates the retention policy, then shrink the database file
+/// and remove the images no item refers to anymore.
+///
+/// Expired items and items trashed more than `empty_trash_after_days` ago are deleted first,
+/// then the limits are applied in order: age, item count, then total text size and total image size.
+/// Whenever a limit is exceeded, the least recently copied items are deleted first.
+/// Items in the trash don't count towards `max_items`, as they are already on their way out.
+/// File lists are text, so they count towards `max_text_bytes`.
+///
+/// Returns the number of deleted items.
+pub fn prune(conn: &Connection, policy: &RetentionPolicy) -> rusqlite::Result<usize> {
+    let mut deleted = delete_expired_items(conn)?;
+
+    deleted += conn.execute(
+        "DELETE FROM history WHERE deleted_at < ?1",
+        params![
+            (Utc::now() - Duration::days(policy.empty_trash_after_days as i64))
+                .format(TIMESTAMP_FORMAT)
+                .to_string()
+        ],
+    )?;
+
+    if let Some(max_age_days) = policy.max_age_days {
+        deleted += delete_older_than(conn, Utc::now() - Duration::days(max_age_days as i64))?;
+    }
+
+    if let Some(max_items) = policy.max_items {
+        deleted += conn.execute(
+            "DELETE FROM history WHERE id IN (
+                 SELECT id FROM history
+                 WHERE pinned = 0 AND deleted_at IS NULL
+                 ORDER BY timestamp DESC, id DESC
+                 LIMIT -1 OFFSET ?1
+             )",
+            params![max_items as i64],
+        )?;
+    }
+
+    if let Some(max_text_bytes) = policy.max_text_bytes {
+        deleted += prune_by_size(conn, &["TEXT", "FILES"], max_text_bytes)?;
+    }
+
+    if let Some(max_image_bytes) = policy.max_image_bytes {
+        deleted += prune_by_size(conn, &["IMAGE"], max_image_bytes)?;
+    }
+
+    if deleted > 0 {
+        blobs::collect_garbage(conn)?;
+        reclaim_space(conn)?;
+    }
+
+    Ok(deleted)
+}
+
+/// Delete every unpinned item last copied before `cutoff`, then shrink the database file
+/// and remove the images no item refers to anymore.
+///
+/// Returns the number of deleted items.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::retention;
+///
+/// let deleted = retention::clear_older_than(chrono::Utc::now() - chrono::Duration::days(30)).unwrap();
+/// println!("{}", deleted); // Output: 12
+/// ```
+pub fn clear_older_than(cutoff: DateTime<Utc>) -> rusqlite::Result<usize> {
+    let conn = db_conn()?;
+    let deleted = delete_older_than(&conn, cutoff)?;
+
+    if deleted > 0 {
+        blobs::collect_garbage(&conn)?;
+        reclaim_space(&conn)?;
+    }
+
+    Ok(deleted)
+}
+
+/// Delete every unpinned item whose expiry has passed, and the images they referred to.
+///
+/// Returns the number of deleted items.
+pub fn delete_expired(conn: &Connection) -> rusqlite::Result<usize> {
+    let deleted = delete_expired_items(conn)?;
+
+    if deleted > 0 {
+        blobs::collect_garbage(conn)?;
+    }
+
+    Ok(deleted)
+}
+
+fn delete_older_than(conn: &Connection, cutoff: DateTime<Utc>) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM history WHERE pinned = 0 AND timestamp < ?1",
+        params![cutoff.format(TIMESTAMP_FORMAT).to_string()],
+    )
+}
+
+fn delete_expired_items(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM history WHERE pinned = 0 AND expires_at <= ?1",
+        params![Utc::now().format(TIMESTAMP_FORMAT).to_string()],
+    )
+}
+
+/// Delete the least recently copied items of the given content types until their total size fits in `max_bytes`.
+fn prune_by_size(
+    conn: &Connection,
+    content_types: &[&str],
+    max_bytes: u64,
+) -> rusqlite::Result<usize> {
+    let placeholders = vec!["?"; content_types.len()].join(", ");
+
+    conn.execute(
+        &format!(
+            "DELETE FROM history WHERE id IN (
+                 SELECT id FROM (
+                     SELECT id, SUM(size) OVER (ORDER BY timestamp DESC, id DESC) AS total_bytes
+                     FROM history
+                     WHERE pinned = 0 AND content_type IN ({})
+                 )
+                 WHERE total_bytes > {}
+             )",
+            placeholders, max_bytes as i64
+        ),
+        params_from_iter(content_types),
+    )
+}
+
+/// Return the pages freed by deleted items to the file system.
+///
+/// Databases are switched to `auto_vacuum = INCREMENTAL` when they are opened
+/// (see `migrations::migrate`), so this never rewrites the whole file.
+fn reclaim_space(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("PRAGMA incremental_vacuum")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::migrations;
+
+    fn open_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    fn days_ago(days: i64) -> String {
+        (Utc::now() - Duration::days(days))
+            .format(TIMESTAMP_FORMAT)
+            .to_string()
+    }
+
+    /// Insert an item of `size` bytes last copied `age` days ago, and return its id.
+    fn insert(conn: &Connection, content_type: &str, size: u64, age: i64) -> i64 {
+        conn.query_row(
+            "INSERT INTO history (source_app, icon_path, content_type, content, content_hash, size, timestamp)
+             VALUES ('Code', '', ?1, x'', HEX(RANDOMBLOB(16)), ?2, ?3)
+             RETURNING id",
+            params![content_type, size as i64, days_ago(age)],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn update(conn: &Connection, id: i64, assignment: &str) {
+        conn.execute(
+            &format!("UPDATE history SET {} WHERE id = ?1", assignment),
+            params![id],
+        )
+        .unwrap();
+    }
+
+    fn ids(conn: &Connection) -> Vec<i64> {
+        conn.prepare("SELECT id FROM history ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn items_older_than_max_age_are_deleted() {
+        let conn = open_db();
+        insert(&conn, "TEXT", 1, 40);
+        let recent = insert(&conn, "TEXT", 1, 1);
+
+        let policy = RetentionPolicy {
+            max_age_days: Some(30),
+            ..Default::default()
+        };
+
+        assert_eq!(prune(&conn, &policy).unwrap(), 1);
+        assert_eq!(ids(&conn), [recent]);
+    }
+
+    #[test]
+    fn only_the_most_recent_items_are_kept() {
+        let conn = open_db();
+        insert(&conn, "TEXT", 1, 3);
+        let older = insert(&conn, "IMAGE", 1, 2);
+        let newest = insert(&conn, "FILES", 1, 1);
+
+        // Trashed items don't take the place of the items still in the history
+        let trashed = insert(&conn, "TEXT", 1, 0);
+        update(&conn, trashed, "deleted_at = DATETIME('NOW')");
+
+        let policy = RetentionPolicy {
+            max_items: Some(2),
+            ..Default::default()
+        };
+
+        assert_eq!(prune(&conn, &policy).unwrap(), 1);
+        assert_eq!(ids(&conn), [older, newest, trashed]);
+    }
+
+    #[test]
+    fn text_and_file_lists_share_the_text_size_cap() {
+        let conn = open_db();
+        insert(&conn, "TEXT", 40, 3);
+        let files = insert(&conn, "FILES", 40, 2);
+        let newest_text = insert(&conn, "TEXT", 40, 1);
+        let image = insert(&conn, "IMAGE", 1000, 4);
+
+        let policy = RetentionPolicy {
+            max_text_bytes: Some(100),
+            ..Default::default()
+        };
+
+        assert_eq!(prune(&conn, &policy).unwrap(), 1);
+        assert_eq!(ids(&conn), [files, newest_text, image]);
+    }
+
+    #[test]
+    fn images_have_a_size_cap_of_their_own() {
+        let conn = open_db();
+        insert(&conn, "IMAGE", 600, 2);
+        let new_image = insert(&conn, "IMAGE", 600, 1);
+        let text = insert(&conn, "TEXT", 1000, 3);
+
+        let policy = RetentionPolicy {
+            max_image_bytes: Some(1000),
+            ..Default::default()
+        };
+
+        assert_eq!(prune(&conn, &policy).unwrap(), 1);
+        assert_eq!(ids(&conn), [new_image, text]);
+    }
+
+    #[test]
+    fn pinned_items_are_never_pruned() {
+        let conn = open_db();
+        let pinned = insert(&conn, "TEXT", 1000, 100);
+        update(
+            &conn,
+            pinned,
+            "pinned = 1, expires_at = DATETIME('NOW', '-1 day')",
+        );
+        insert(&conn, "TEXT", 1000, 100);
+
+        let policy = RetentionPolicy {
+            max_age_days: Some(30),
+            max_items: Some(0),
+            max_text_bytes: Some(0),
+            ..Default::default()
+        };
+
+        assert_eq!(prune(&conn, &policy).unwrap(), 1);
+        assert_eq!(ids(&conn), [pinned]);
+    }
+
+    #[test]
+    fn expired_items_are_deleted() {
+        let conn = open_db();
+        let expired = insert(&conn, "TEXT", 1, 0);
+        update(&conn, expired, "expires_at = DATETIME('NOW', '-1 minute')");
+        let expiring = insert(&conn, "TEXT", 1, 0);
+        update(&conn, expiring, "expires_at = DATETIME('NOW', '+1 hour')");
+        let kept = insert(&conn, "TEXT", 1, 0);
+
+        assert_eq!(delete_expired(&conn).unwrap(), 1);
+        assert_eq!(ids(&conn), [expiring, kept]);
+        assert_eq!(prune(&conn, &RetentionPolicy::default()).unwrap(), 0);
+    }
+
+    #[test]
+    fn the_trash_is_emptied_after_a_while() {
+        let conn = open_db();
+        let long_trashed = insert(&conn, "TEXT", 1, 40);
+        update(
+            &conn,
+            long_trashed,
+            "deleted_at = DATETIME('NOW', '-31 days')",
+        );
+        let recently_trashed = insert(&conn, "TEXT", 1, 40);
+        update(
+            &conn,
+            recently_trashed,
+            "deleted_at = DATETIME('NOW', '-1 day')",
+        );
+
+        let policy = RetentionPolicy {
+            empty_trash_after_days: 30,
+            ..Default::default()
+        };
+
+        assert_eq!(prune(&conn, &policy).unwrap(), 1);
+        assert_eq!(ids(&conn), [recently_trashed]);
+    }
+}
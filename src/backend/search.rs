@@ -0,0 +1,330 @@
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+
+use crate::backend::clipboard::ContentTypes;
+
+/// A parsed search bar query.
+///
+/// # Syntax
+///
+/// - `hello world`: items containing words starting with `hello` **and** `world`.
+/// - `"hello world"`: items containing the exact phrase.
+/// - `app:Code` / `app:"Visual Studio"`: items copied from an app whose name contains the value.
+/// - `type:text` / `type:image`: items of the given content type.
+/// - `type:snippet`: snippets only, see `snippets::search`.
+/// - `before:2025-12-31`: items copied before that (local) day.
+/// - `after:2025-12-01`: items copied on or after that (local) day.
+/// - `#work`: items tagged `work`, case-insensitive.
+///
+/// Qualifiers with an invalid value are treated as plain words.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchQuery {
+    pub terms: Vec<Term>,
+    pub app: Option<String>,
+    pub content_type: Option<ContentTypes>,
+    pub before: Option<DateTime<Utc>>,
+    pub after: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub snippets_only: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Term {
+    /// A single word, matched as a prefix.
+    Word(String),
+    /// A quoted phrase, matched exactly.
+    Phrase(String),
+}
+
+impl SearchQuery {
+    /// Parses the raw text of the search bar.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use crate::backend::search::{SearchQuery, Term};
+    ///
+    /// let query = SearchQuery::parse(r#"app:Code "fn main" type:text"#);
+    /// println!("{:?}", query.terms); // Output: [Phrase("fn main")]
+    /// ```
+    pub fn parse(input: &str) -> Self {
+        let mut query = SearchQuery::default();
+
+        for (token, quoted) in tokenize(input) {
+            if quoted {
+                query.terms.push(Term::Phrase(token));
+                continue;
+            }
+
+            if let Some(tag) = token.strip_prefix('#').and_then(normalize_tag) {
+                query.tags.push(tag);
+                continue;
+            }
+
+            if let Some((key, value)) = token.split_once(':') {
+                if query.apply_qualifier(key, value) {
+                    continue;
+                }
+            }
+
+            let word = token.trim_end_matches('*');
+            if !word.is_empty() {
+                query.terms.push(Term::Word(word.to_string()));
+            }
+        }
+
+        query
+    }
+
+    /// Builds the FTS5 `MATCH` expression for the free-text part of the query.
+    ///
+    /// Every term is quoted, so characters that have a special meaning in the FTS5
+    /// query syntax are matched literally. Returns `None` if there are no terms.
+    pub fn fts_expression(&self) -> Option<String> {
+        if self.terms.is_empty() {
+            return None;
+        }
+
+        let expression = self
+            .terms
+            .iter()
+            .map(|term| match term {
+                Term::Word(word) => format!("{}*", fts_quote(word)),
+                Term::Phrase(phrase) => fts_quote(phrase),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Some(expression)
+    }
+
+    /// Applies a `key:value` qualifier, returning `false` if it isn't a valid one.
+    fn apply_qualifier(&mut self, key: &str, value: &str) -> bool {
+        if value.is_empty() {
+            return false;
+        }
+
+        match key.to_lowercase().as_str() {
+            "app" => self.app = Some(value.to_string()),
+            "type" => match value.to_lowercase().as_str() {
+                "text" => self.content_type = Some(ContentTypes::Text),
+                "image" => self.content_type = Some(ContentTypes::Image),
+                "file" | "files" => self.content_type = Some(ContentTypes::Files),
+                "snippet" | "snippets" => self.snippets_only = true,
+                _ => return false,
+            },
+            "before" => match start_of_local_day(value) {
+                Some(timestamp) => self.before = Some(timestamp),
+                None => return false,
+            },
+            "after" => match start_of_local_day(value) {
+                Some(timestamp) => self.after = Some(timestamp),
+                None => return false,
+            },
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+/// Splits the input on whitespace, keeping double-quoted sections together.
+///
+/// Returns each token along with whether it was a standalone quoted phrase.
+/// A quoted section right after a qualifier (e.g. `app:"Visual Studio"`) becomes
+/// part of that qualifier's token instead.
+fn tokenize(input: &str) -> Vec<(String, bool)> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut quoted = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                if !in_quotes && current.is_empty() {
+                    quoted = true;
+                }
+                in_quotes = !in_quotes;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push((std::mem::take(&mut current), quoted));
+                }
+                quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push((current, quoted));
+    }
+
+    tokens
+}
+
+/// Cleans up a tag typed in by the user: without the leading `#`, and with dashes instead of whitespace
+/// so it stays a single `#tag` token in the search bar.
+///
+/// Returns `None` if nothing is left.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::search::normalize_tag;
+///
+/// println!("{:?}", normalize_tag("#to do")); // Output: Some("to-do")
+/// ```
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag
+        .trim()
+        .trim_start_matches('#')
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-");
+
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// Wraps a string in double quotes for use in an FTS5 query.
+fn fts_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// Parses a `YYYY-MM-DD` date and returns the start of that day in the local timezone.
+fn start_of_local_day(date: &str) -> Option<DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let local = Local
+        .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+        .earliest()?;
+
+    Some(local.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn words(words: &[&str]) -> Vec<Term> {
+        words
+            .iter()
+            .map(|word| Term::Word(word.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_words_and_phrases() {
+        let query = SearchQuery::parse(r#"  hello "fn main"  world* "#);
+
+        assert_eq!(
+            query.terms,
+            [
+                Term::Word("hello".to_string()),
+                Term::Phrase("fn main".to_string()),
+                Term::Word("world".to_string()),
+            ]
+        );
+        assert_eq!(SearchQuery::parse(""), SearchQuery::default());
+        assert_eq!(SearchQuery::parse("* **").terms, []);
+    }
+
+    #[test]
+    fn parses_qualifiers() {
+        let query = SearchQuery::parse(
+            r#"app:"Visual Studio" TYPE:Image before:2025-12-31 after:2025-12-01 #Work #to-do"#,
+        );
+
+        assert_eq!(query.terms, []);
+        assert_eq!(query.app.as_deref(), Some("Visual Studio"));
+        assert_eq!(query.content_type, Some(ContentTypes::Image));
+        assert_eq!(query.before, start_of_local_day("2025-12-31"));
+        assert_eq!(query.after, start_of_local_day("2025-12-01"));
+        assert!(query.before.is_some() && query.after.is_some());
+        assert_eq!(query.tags, ["Work", "to-do"]);
+        assert!(!query.snippets_only);
+
+        assert_eq!(
+            SearchQuery::parse("type:files").content_type,
+            Some(ContentTypes::Files)
+        );
+        assert!(SearchQuery::parse("type:snippet").snippets_only);
+    }
+
+    #[test]
+    fn invalid_qualifiers_are_words() {
+        let query = SearchQuery::parse("type:video before:yesterday app: http://example.com # ##");
+
+        assert_eq!(
+            query.terms,
+            words(&[
+                "type:video",
+                "before:yesterday",
+                "app:",
+                "http://example.com",
+                "#",
+                "##"
+            ])
+        );
+        assert_eq!(
+            query,
+            SearchQuery {
+                terms: query.terms.clone(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn quotes_fts_terms() {
+        assert_eq!(SearchQuery::parse("app:Code").fts_expression(), None);
+        assert_eq!(
+            SearchQuery::parse(r#"hello "fn main""#)
+                .fts_expression()
+                .as_deref(),
+            Some(r#""hello"* "fn main""#)
+        );
+        assert_eq!(
+            SearchQuery::parse(r#"say"hi""#).fts_expression().as_deref(),
+            Some(r#""sayhi"*"#)
+        );
+    }
+
+    #[test]
+    fn fts_syntax_is_matched_literally() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE fts USING fts5(content);
+             INSERT INTO fts (rowid, content) VALUES (1, 'foo-bar AND (baz) NEAR col:value ^start');",
+        )
+        .unwrap();
+
+        let matches = |input: &str| -> i64 {
+            let expression = SearchQuery::parse(input).fts_expression().unwrap();
+            conn.query_row(
+                "SELECT COUNT(*) FROM fts WHERE fts MATCH ?1",
+                [expression],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        for input in [
+            "foo-bar",
+            "AND",
+            "(baz)",
+            "NEAR(",
+            "col:value",
+            "^start",
+            "OR NOT",
+        ] {
+            // Doesn't fail to parse, whether it matches or not
+            let _ = matches(input);
+        }
+        assert_eq!(matches("foo-bar"), 1);
+        assert_eq!(matches("(baz"), 1);
+        assert_eq!(matches("col:value"), 1);
+        assert_eq!(matches(r#""foo-bar and""#), 1);
+        assert_eq!(matches("nothing"), 0);
+    }
+}
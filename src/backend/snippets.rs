@@ -0,0 +1,632 @@
+use arboard::Clipboard;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::backend::clipboard::{self, db_conn, TIMESTAMP_FORMAT};
+use crate::backend::search::SearchQuery;
+
+/// The format of `{date}` without one, e.g. `2025-12-31`.
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A reusable text saved by the user, with placeholders filled in when it is pasted.
+///
+/// # Placeholders
+///
+/// - `{date}` / `{date:%d/%m/%Y %H:%M}`: the current local time, in a `chrono` format (`%Y-%m-%d` by default).
+/// - `{clipboard}`: the text on the clipboard.
+/// - `{cursor}`: where the caret is left once the snippet is pasted.
+/// - `{input:Name}`: a field filled in by the user, once per name.
+/// - `{{` / `}}`: a literal `{` / `}`.
+///
+/// Anything else between braces is kept as it is.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snippet {
+    pub id: i64,
+    pub name: String,
+    pub content: String,          // The template, with its placeholders
+    pub timestamp: DateTime<Utc>, // When the snippet was last saved or pasted
+}
+
+/// A snippet with its placeholders filled in, see `render`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rendered {
+    pub text: String,
+    /// The number of characters between `{cursor}` and the end of `text`, `0` without a `{cursor}`.
+    pub cursor_offset: usize,
+}
+
+/// The file snippets are shared with, see `export` and `import`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SnippetFile {
+    snippets: Vec<SharedSnippet>,
+}
+
+impl SnippetFile {
+    fn encode(&self, file_type: FileType) -> Result<String, SnippetError> {
+        match file_type {
+            FileType::Toml => {
+                toml::to_string_pretty(self).map_err(|err| SnippetError::Toml(err.to_string()))
+            }
+            FileType::Json => Ok(serde_json::to_string_pretty(self)?),
+        }
+    }
+
+    fn decode(data: &str, file_type: FileType) -> Result<Self, SnippetError> {
+        match file_type {
+            FileType::Toml => {
+                toml::from_str(data).map_err(|err| SnippetError::Toml(err.to_string()))
+            }
+            FileType::Json => Ok(serde_json::from_str(data)?),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedSnippet {
+    name: String,
+    content: String,
+}
+
+#[derive(Debug)]
+pub enum SnippetError {
+    /// No snippet has this id.
+    Missing(i64),
+    /// The format of a `{date:...}` placeholder isn't a valid `chrono` format.
+    InvalidDateFormat(String),
+    /// The file isn't a `.toml` or a `.json` file.
+    UnknownFileType(String),
+    Io(io::Error),
+    Toml(String),
+    Json(serde_json::Error),
+    Clipboard(arboard::Error),
+    Sqlite(rusqlite::Error),
+}
+
+impl fmt::Display for SnippetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnippetError::Missing(id) => write!(f, "snippet {} is missing", id),
+            SnippetError::InvalidDateFormat(format) => {
+                write!(f, "invalid date format {}", format)
+            }
+            SnippetError::UnknownFileType(path) => {
+                write!(f, "{} isn't a .toml or a .json file", path)
+            }
+            SnippetError::Io(err) => write!(f, "{}", err),
+            SnippetError::Toml(err) => write!(f, "invalid snippets file: {}", err),
+            SnippetError::Json(err) => write!(f, "invalid snippets file: {}", err),
+            SnippetError::Clipboard(err) => write!(f, "{}", err),
+            SnippetError::Sqlite(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SnippetError {}
+
+impl From<io::Error> for SnippetError {
+    fn from(err: io::Error) -> Self {
+        SnippetError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SnippetError {
+    fn from(err: serde_json::Error) -> Self {
+        SnippetError::Json(err)
+    }
+}
+
+impl From<arboard::Error> for SnippetError {
+    fn from(err: arboard::Error) -> Self {
+        SnippetError::Clipboard(err)
+    }
+}
+
+impl From<rusqlite::Error> for SnippetError {
+    fn from(err: rusqlite::Error) -> Self {
+        SnippetError::Sqlite(err)
+    }
+}
+
+/// A part of a snippet template.
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Date(String),
+    Clipboard,
+    Cursor,
+    Input(String),
+}
+
+/// Get the snippets matching a search bar query.
+///
+/// Snippets are matched by the free-text terms of the query, in their name and content, and ranked
+/// by BM25 relevance. Queries with a qualifier only history items have (e.g. `app:Code`) match none,
+/// and without any term, only `type:snippet` lists every snippet, most recently used first.
+///
+/// # Arguments
+///
+/// * `filter` - A search bar query, see `SearchQuery`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::snippets;
+///
+/// let snippets = snippets::search("signature").unwrap();
+/// println!("{:?}", snippets); // Output: [Snippet { id: 1, name: "Email signature", content: "Best regards,\n{input:Name}", timestamp: 2025-12-27T17:11:28Z }]
+/// ```
+pub fn search(filter: &str) -> rusqlite::Result<Vec<Snippet>> {
+    let query = SearchQuery::parse(filter);
+    let has_history_qualifiers = query.app.is_some()
+        || query.content_type.is_some()
+        || query.before.is_some()
+        || query.after.is_some()
+        || !query.tags.is_empty();
+
+    if has_history_qualifiers || (query.terms.is_empty() && !query.snippets_only) {
+        return Ok(Vec::new());
+    }
+
+    let conn = db_conn()?;
+    let (joins, condition, order, values) = match query.fts_expression() {
+        Some(expression) => (
+            "JOIN snippets_fts ON snippets_fts.rowid = snippets.id",
+            "snippets_fts MATCH ?1",
+            "bm25(snippets_fts, 2.0, 1.0), snippets.timestamp DESC",
+            vec![expression],
+        ),
+        None => ("", "TRUE", "snippets.timestamp DESC", Vec::new()),
+    };
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT snippets.id, snippets.name, snippets.content, snippets.timestamp
+         FROM snippets {joins}
+         WHERE {condition}
+         ORDER BY {order}"
+    ))?;
+
+    let snippet_iter = stmt.query_map(params_from_iter(values), row_to_snippet)?;
+
+    snippet_iter.collect()
+}
+
+/// Get a single snippet.
+pub fn get_snippet(id: i64) -> rusqlite::Result<Option<Snippet>> {
+    let conn = db_conn()?;
+
+    conn.query_row(
+        "SELECT id, name, content, timestamp FROM snippets WHERE id = ?1",
+        params![id],
+        row_to_snippet,
+    )
+    .optional()
+}
+
+/// Saves a snippet, replacing the content of the snippet with the same name (case-insensitive) if there is one.
+///
+/// Returns the id of the snippet.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::snippets;
+///
+/// let id = snippets::save_snippet("Today", "{date:%A %d %B}").unwrap();
+/// ```
+pub fn save_snippet(name: &str, content: &str) -> rusqlite::Result<i64> {
+    let conn = db_conn()?;
+
+    save_to(&conn, name, content)
+}
+
+/// Saves a snippet into `conn`, see `save_snippet`.
+fn save_to(conn: &Connection, name: &str, content: &str) -> rusqlite::Result<i64> {
+    conn.query_row(
+        "INSERT INTO snippets (name, content, timestamp) VALUES (?1, ?2, ?3)
+         ON CONFLICT (name) DO UPDATE SET content = excluded.content, timestamp = excluded.timestamp
+         RETURNING id",
+        params![name.trim(), content, now()],
+        |row| row.get(0),
+    )
+}
+
+pub fn delete_snippet(id: i64) -> rusqlite::Result<()> {
+    let conn = db_conn()?;
+
+    conn.execute("DELETE FROM snippets WHERE id = ?1", params![id])?;
+
+    Ok(())
+}
+
+/// Renders a snippet and puts it on the system clipboard, without saving it to the history.
+///
+/// The snippet is bumped to the top of the `type:snippet` list.
+///
+/// # Arguments
+///
+/// * `id` - The snippet to paste.
+/// * `inputs` - The values of its `{input:Name}` placeholders, by name. Missing ones are left empty.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::snippets;
+///
+/// let inputs = [("Name".to_string(), "Jane".to_string())].into();
+/// println!("{:?}", snippets::write_to_clipboard(1, &inputs)); // Output: Ok(Rendered { text: "Best regards,\nJane", cursor_offset: 0 })
+/// ```
+pub fn write_to_clipboard(
+    id: i64,
+    inputs: &HashMap<String, String>,
+) -> Result<Rendered, SnippetError> {
+    let snippet = get_snippet(id)?.ok_or(SnippetError::Missing(id))?;
+
+    // Only read the clipboard when the snippet needs it, it may hold something that isn't text
+    let clipboard_text = if parse(&snippet.content).contains(&Part::Clipboard) {
+        Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_text())
+            .unwrap_or_default()
+    } else {
+        String::new()
+    };
+
+    let rendered = render(&snippet.content, inputs, &clipboard_text, Local::now())?;
+    clipboard::write_text_to_clipboard(&rendered.text)?;
+
+    db_conn()?.execute(
+        "UPDATE snippets SET timestamp = ?1 WHERE id = ?2",
+        params![now(), id],
+    )?;
+
+    Ok(rendered)
+}
+
+/// Return the names of the `{input:Name}` placeholders of a template, in order and without duplicates.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::snippets;
+///
+/// println!("{:?}", snippets::inputs("Dear {input:Name}, {input:Greeting} {input:Name}")); // Output: ["Name", "Greeting"]
+/// ```
+pub fn inputs(template: &str) -> Vec<String> {
+    let mut names = Vec::new();
+
+    for part in parse(template) {
+        if let Part::Input(name) = part {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    names
+}
+
+/// Fill in the placeholders of a template, see `Snippet`.
+///
+/// # Arguments
+///
+/// * `template` - The content of a snippet.
+/// * `inputs` - The values of the `{input:Name}` placeholders, by name.
+/// * `clipboard` - The text `{clipboard}` is replaced with.
+/// * `now` - The time `{date}` is replaced with.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::snippets;
+///
+/// let rendered = snippets::render("Hi {input:Name}, {cursor}!", &[("Name".to_string(), "Jane".to_string())].into(), "", chrono::Local::now()).unwrap();
+/// println!("{:?}", rendered); // Output: Rendered { text: "Hi Jane, !", cursor_offset: 1 }
+/// ```
+pub fn render(
+    template: &str,
+    inputs: &HashMap<String, String>,
+    clipboard: &str,
+    now: DateTime<Local>,
+) -> Result<Rendered, SnippetError> {
+    let mut text = String::with_capacity(template.len());
+    let mut cursor = None;
+
+    for part in parse(template) {
+        match part {
+            Part::Text(part) => text.push_str(&part),
+            Part::Date(format) => write!(text, "{}", now.format(&format))
+                .map_err(|_| SnippetError::InvalidDateFormat(format))?,
+            Part::Clipboard => text.push_str(clipboard),
+            Part::Cursor => {
+                cursor.get_or_insert(text.len());
+            }
+            Part::Input(name) => {
+                text.push_str(inputs.get(&name).map(String::as_str).unwrap_or_default())
+            }
+        }
+    }
+
+    // Editors move over a `\r\n` line break with a single `←`
+    let cursor_offset = cursor
+        .map(|cursor| text[cursor..].replace("\r\n", "\n").chars().count())
+        .unwrap_or(0);
+
+    Ok(Rendered {
+        text,
+        cursor_offset,
+    })
+}
+
+/// Write every snippet to a file, to share them with a team.
+///
+/// The format follows the extension of the file: `.toml` or `.json`, each with a list of
+/// `snippets` that have a `name` and a `content`.
+///
+/// Returns the number of exported snippets.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::snippets;
+///
+/// let count = snippets::export(std::path::Path::new("snippets.toml")).unwrap();
+/// println!("{}", count); // Output: 12
+/// ```
+pub fn export(path: &Path) -> Result<usize, SnippetError> {
+    let file_type = file_type(path)?;
+    let conn = db_conn()?;
+    let file = export_from(&conn)?;
+
+    fs::write(path, file.encode(file_type)?)?;
+
+    Ok(file.snippets.len())
+}
+
+/// Save the snippets of a file written by `export`.
+///
+/// A snippet with the name of an existing one replaces its content. The snippets are saved in a
+/// single transaction, so none of them is if one fails.
+///
+/// Returns the number of imported snippets.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::snippets;
+///
+/// let count = snippets::import(std::path::Path::new("team-snippets.json")).unwrap();
+/// println!("{}", count); // Output: 12
+/// ```
+pub fn import(path: &Path) -> Result<usize, SnippetError> {
+    let file_type = file_type(path)?;
+    let file = SnippetFile::decode(&fs::read_to_string(path)?, file_type)?;
+    let conn = db_conn()?;
+
+    import_into(&conn, &file)
+}
+
+/// Every snippet of `conn`, most recently used first, see `export`.
+fn export_from(conn: &Connection) -> rusqlite::Result<SnippetFile> {
+    let snippets = conn
+        .prepare("SELECT name, content FROM snippets ORDER BY timestamp DESC")?
+        .query_map([], |row| {
+            Ok(SharedSnippet {
+                name: row.get(0)?,
+                content: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(SnippetFile { snippets })
+}
+
+/// Save the snippets of `file` into `conn`, see `import`.
+fn import_into(conn: &Connection, file: &SnippetFile) -> Result<usize, SnippetError> {
+    let tx = conn.unchecked_transaction()?;
+
+    for snippet in &file.snippets {
+        save_to(&tx, &snippet.name, &snippet.content)?;
+    }
+
+    tx.commit()?;
+
+    Ok(file.snippets.len())
+}
+
+#[derive(Clone, Copy)]
+enum FileType {
+    Toml,
+    Json,
+}
+
+fn file_type(path: &Path) -> Result<FileType, SnippetError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+
+    match extension.as_deref() {
+        Some("toml") => Ok(FileType::Toml),
+        Some("json") => Ok(FileType::Json),
+        _ => Err(SnippetError::UnknownFileType(path.display().to_string())),
+    }
+}
+
+/// Split a template into its text and its placeholders.
+fn parse(template: &str) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            text.push(c);
+            rest = &rest[2..];
+            continue;
+        }
+
+        let placeholder = rest
+            .strip_prefix('{')
+            .and_then(|inner| inner.split_once('}'))
+            .and_then(|(inner, after)| Some((placeholder(inner)?, after)));
+
+        match placeholder {
+            Some((part, after)) => {
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.push(part);
+                rest = after;
+            }
+            None => {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+
+    parts
+}
+
+/// Parse what is between the braces of a placeholder, `None` if it isn't one.
+fn placeholder(inner: &str) -> Option<Part> {
+    match inner.split_once(':') {
+        Some(("date", format)) if !format.is_empty() => Some(Part::Date(format.to_string())),
+        Some(("input", name)) if !name.trim().is_empty() => {
+            Some(Part::Input(name.trim().to_string()))
+        }
+        None if inner == "date" => Some(Part::Date(DEFAULT_DATE_FORMAT.to_string())),
+        None if inner == "clipboard" => Some(Part::Clipboard),
+        None if inner == "cursor" => Some(Part::Cursor),
+        _ => None,
+    }
+}
+
+fn now() -> String {
+    Utc::now().format(TIMESTAMP_FORMAT).to_string()
+}
+
+fn row_to_snippet(row: &Row) -> rusqlite::Result<Snippet> {
+    let timestamp: String = row.get(3)?;
+    let timestamp = NaiveDateTime::parse_from_str(&timestamp, TIMESTAMP_FORMAT)
+        .map(|naive| Utc.from_utc_datetime(&naive))
+        .unwrap_or_else(|_| Utc::now());
+
+    Ok(Snippet {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        content: row.get(2)?,
+        timestamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::migrations;
+
+    fn open_db() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    fn all_snippets(conn: &Connection) -> Vec<(String, String)> {
+        conn.prepare("SELECT name, content FROM snippets ORDER BY name")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    /// Export the snippets of a database, and import them into another one that has a snippet
+    /// with the same name.
+    fn round_trip(file_type: FileType) {
+        let source = open_db();
+        save_to(&source, "Signature", "Best regards,\n{input:Name}").unwrap();
+        save_to(&source, "Today", "{date:%A %d %B} \"quoted\" {{braces}}").unwrap();
+
+        let data = export_from(&source).unwrap().encode(file_type).unwrap();
+
+        let target = open_db();
+        save_to(&target, "Signature", "Cheers").unwrap();
+        let imported =
+            import_into(&target, &SnippetFile::decode(&data, file_type).unwrap()).unwrap();
+
+        assert_eq!(imported, 2);
+        assert_eq!(all_snippets(&target), all_snippets(&source));
+    }
+
+    #[test]
+    fn toml_exports_import_back() {
+        round_trip(FileType::Toml);
+    }
+
+    #[test]
+    fn json_exports_import_back() {
+        round_trip(FileType::Json);
+    }
+
+    fn render_with(template: &str, inputs: &[(&str, &str)]) -> Rendered {
+        let inputs = inputs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let now = Local.with_ymd_and_hms(2025, 12, 31, 9, 5, 0).unwrap();
+
+        render(template, &inputs, "copied", now).unwrap()
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let rendered = render_with(
+            "{date} {date:%H:%M} {clipboard} {input:Name}{input:Missing}",
+            &[("Name", "Jane")],
+        );
+
+        assert_eq!(rendered.text, "2025-12-31 09:05 copied Jane");
+        assert_eq!(rendered.cursor_offset, 0);
+    }
+
+    #[test]
+    fn keeps_everything_else() {
+        let rendered = render_with("{{date}} {unknown} {date {input:} }} {", &[]);
+
+        assert_eq!(rendered.text, "{date} {unknown} {date {input:} } {");
+    }
+
+    #[test]
+    fn counts_characters_after_the_cursor() {
+        let rendered = render_with("<p>{cursor}</p>\r\n{cursor}é", &[]);
+
+        assert_eq!(rendered.text, "<p></p>\r\né");
+        assert_eq!(rendered.cursor_offset, 6);
+    }
+
+    #[test]
+    fn rejects_invalid_date_formats() {
+        let now = Local::now();
+
+        assert!(matches!(
+            render("{date:%Q}", &HashMap::new(), "", now),
+            Err(SnippetError::InvalidDateFormat(_))
+        ));
+    }
+
+    #[test]
+    fn lists_inputs_once() {
+        assert_eq!(
+            inputs("{input:Name} {input: Company } {input:Name} {clipboard}"),
+            ["Name", "Company"]
+        );
+    }
+}
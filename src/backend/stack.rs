@@ -0,0 +1,206 @@
+use crate::backend::clipboard::Item;
+
+/// The order the items of a `PasteStack` are pasted in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StackOrder {
+    /// The first item added is pasted first.
+    #[default]
+    Fifo,
+    /// The last item added is pasted first.
+    Lifo,
+}
+
+impl StackOrder {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StackOrder::Fifo => "FIFO",
+            StackOrder::Lifo => "LIFO",
+        }
+    }
+}
+
+/// Items picked in the `Paste` window to be pasted one after the other, e.g. to fill in a form.
+///
+/// Every item is only on the stack once, and leaves it when it is pasted, see `pop`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::stack::{PasteStack, StackOrder};
+///
+/// let mut stack = PasteStack::default();
+/// stack.toggle(first_name);
+/// stack.toggle(last_name);
+/// stack.reverse();
+///
+/// println!("{:?}", stack.order()); // Output: Lifo
+/// println!("{:?}", stack.pop().map(|item| item.content)); // Output: Some("Doe")
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PasteStack {
+    /// In the order they were added, whatever the `order`
+    items: Vec<Item>,
+    order: StackOrder,
+}
+
+impl PasteStack {
+    /// Add an item on top of the stack, or take it off if it is already on it.
+    pub fn toggle(&mut self, item: Item) {
+        if self.contains(item.id) {
+            self.remove(item.id);
+        } else {
+            self.items.push(item);
+        }
+    }
+
+    /// Take an item off the stack without pasting it, e.g. because it was deleted.
+    pub fn remove(&mut self, id: i64) {
+        self.items.retain(|item| item.id != id);
+    }
+
+    pub fn contains(&self, id: i64) -> bool {
+        self.items.iter().any(|item| item.id == id)
+    }
+
+    /// Switch between pasting the items first in, first out (FIFO) and last in, first out (LIFO).
+    pub fn reverse(&mut self) {
+        self.order = match self.order {
+            StackOrder::Fifo => StackOrder::Lifo,
+            StackOrder::Lifo => StackOrder::Fifo,
+        };
+    }
+
+    pub fn order(&self) -> StackOrder {
+        self.order
+    }
+
+    /// Return the items in the order they will be pasted.
+    pub fn items(&self) -> Vec<Item> {
+        match self.order {
+            StackOrder::Fifo => self.items.clone(),
+            StackOrder::Lifo => self.items.iter().rev().cloned().collect(),
+        }
+    }
+
+    /// Return when an item will be pasted: `1` for the next one, `None` if it isn't on the stack.
+    pub fn position(&self, id: i64) -> Option<usize> {
+        let index = self.items.iter().position(|item| item.id == id)?;
+
+        Some(match self.order {
+            StackOrder::Fifo => index + 1,
+            StackOrder::Lifo => self.items.len() - index,
+        })
+    }
+
+    /// Take the next item to paste off the stack.
+    pub fn pop(&mut self) -> Option<Item> {
+        if self.items.is_empty() {
+            return None;
+        }
+
+        Some(match self.order {
+            StackOrder::Fifo => self.items.remove(0),
+            StackOrder::Lifo => self.items.pop()?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::clipboard::ContentTypes;
+    use chrono::Utc;
+
+    fn item(id: i64) -> Item {
+        Item {
+            id,
+            source_app: "Code".to_string(),
+            icon_path: String::new(),
+            content_type: ContentTypes::Text,
+            content: format!("item {id}"),
+            content_hash: String::new(),
+            timestamp: Utc::now(),
+            formats: Vec::new(),
+            redacted: false,
+            masked: false,
+            pinned: false,
+            tags: Vec::new(),
+            note: String::new(),
+            deleted_at: None,
+        }
+    }
+
+    fn stack_of(ids: &[i64]) -> PasteStack {
+        let mut stack = PasteStack::default();
+        for id in ids {
+            stack.toggle(item(*id));
+        }
+        stack
+    }
+
+    fn ids(items: &[Item]) -> Vec<i64> {
+        items.iter().map(|item| item.id).collect()
+    }
+
+    #[test]
+    fn toggle_adds_then_removes_an_item() {
+        let mut stack = stack_of(&[1, 2]);
+        assert!(stack.contains(1));
+        assert!(stack.contains(2));
+
+        stack.toggle(item(1));
+        assert!(!stack.contains(1));
+        assert_eq!(ids(&stack.items()), [2]);
+
+        // Added again, it goes back on top
+        stack.toggle(item(1));
+        assert_eq!(ids(&stack.items()), [2, 1]);
+    }
+
+    #[test]
+    fn reverse_switches_the_order() {
+        let mut stack = stack_of(&[1, 2, 3]);
+        assert_eq!(stack.order(), StackOrder::Fifo);
+        assert_eq!(ids(&stack.items()), [1, 2, 3]);
+
+        stack.reverse();
+        assert_eq!(stack.order(), StackOrder::Lifo);
+        assert_eq!(ids(&stack.items()), [3, 2, 1]);
+
+        stack.reverse();
+        assert_eq!(stack.order(), StackOrder::Fifo);
+    }
+
+    #[test]
+    fn position_follows_the_order() {
+        let mut stack = stack_of(&[1, 2, 3]);
+        assert_eq!(stack.position(1), Some(1));
+        assert_eq!(stack.position(3), Some(3));
+        assert_eq!(stack.position(4), None);
+
+        stack.reverse();
+        assert_eq!(stack.position(1), Some(3));
+        assert_eq!(stack.position(3), Some(1));
+        assert_eq!(stack.position(4), None);
+    }
+
+    #[test]
+    fn pop_takes_the_next_item_in_order() {
+        let mut stack = stack_of(&[1, 2, 3]);
+        assert_eq!(stack.pop().map(|item| item.id), Some(1));
+
+        stack.reverse();
+        assert_eq!(stack.pop().map(|item| item.id), Some(3));
+        assert_eq!(stack.pop().map(|item| item.id), Some(2));
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+}
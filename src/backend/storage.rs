@@ -0,0 +1,240 @@
+use std::env::{args, current_exe, var_os};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::backend::utils::hash_text;
+
+/// Name of the per-user directory that holds all of the app's data.
+const APP_DIR_NAME: &str = "paste-fork";
+const DB_FILE_NAME: &str = "clipboard.db";
+const ICON_DIR_NAME: &str = "icons";
+const BLOB_DIR_NAME: &str = "blobs";
+const THUMBNAIL_DIR_NAME: &str = "thumbnails";
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Environment variable overriding the database location.
+pub const DB_PATH_ENV: &str = "PASTE_FORK_DB";
+/// Command-line flag overriding the database location, e.g. `--db /tmp/clipboard.db`.
+pub const DB_PATH_FLAG: &str = "--db";
+
+/// Returns the per-user directory that holds all of the app's data.
+///
+/// - **macOS**: `~/Library/Application Support/paste-fork`
+/// - **Linux**: `$XDG_DATA_HOME/paste-fork`, falling back to `~/.local/share/paste-fork`
+///
+/// The directory is created if it doesn't exist yet.
+#[cfg(not(test))]
+pub fn data_dir() -> PathBuf {
+    let home = var_os("HOME").map(PathBuf::from).unwrap_or_default();
+
+    #[cfg(target_os = "macos")]
+    let base = home.join("Library/Application Support");
+
+    #[cfg(not(target_os = "macos"))]
+    let base = var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| home.join(".local/share"));
+
+    let dir = base.join(APP_DIR_NAME);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Tests get a temporary data directory of their own, so they never touch the data of the user.
+///
+/// Every test runs on a thread of its own and gets a directory of its own, so collecting
+/// the garbage of one test's database never deletes the images of another.
+#[cfg(test)]
+pub fn data_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "{}-test-{}-{:?}",
+        APP_DIR_NAME,
+        std::process::id(),
+        std::thread::current().id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Returns the location of the clipboard history database.
+///
+/// The location is resolved in the following order:
+/// 1. The `--db <path>` command-line flag.
+/// 2. The `PASTE_FORK_DB` environment variable.
+/// 3. `clipboard.db` inside `data_dir()`.
+///
+/// If neither override is given and a database from an older version still lives next to
+/// the executable, it is moved into `data_dir()` first. Tests ignore both overrides.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::storage;
+///
+/// println!("{:?}", storage::db_path()); // Output: "/home/finn/.local/share/paste-fork/clipboard.db"
+/// ```
+pub fn db_path() -> PathBuf {
+    let overridden = if cfg!(test) {
+        None
+    } else {
+        db_path_from_args().or_else(|| var_os(DB_PATH_ENV).map(PathBuf::from))
+    };
+
+    if let Some(path) = overridden {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).unwrap();
+        }
+        return path;
+    }
+
+    let path = data_dir().join(DB_FILE_NAME);
+    if !cfg!(test) {
+        migrate_legacy_db(&path);
+    }
+    path
+}
+
+/// Returns the location of the Unix socket the daemon listens on, next to the database it serves.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::storage;
+///
+/// println!("{:?}", storage::socket_path()); // Output: "/home/finn/.local/share/paste-fork/clipboard.sock"
+/// ```
+pub fn socket_path() -> PathBuf {
+    db_path().with_extension("sock")
+}
+
+/// Returns the location of the user settings file.
+pub fn config_path() -> PathBuf {
+    data_dir().join(CONFIG_FILE_NAME)
+}
+
+/// Returns the directory holding the files the database at `db_path()` refers to:
+/// images, thumbnails and app icons.
+///
+/// The default database keeps them in `data_dir()`. Any other database (see `--db`) gets its own
+/// `<name>.blobs` directory next to it, so that cleaning up one database never deletes the files
+/// of another (see `blobs::collect_garbage`).
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::storage;
+///
+/// println!("{:?}", storage::files_dir()); // Output: "/tmp/clipboard.blobs" (with `--db /tmp/clipboard.db`)
+/// ```
+pub fn files_dir() -> PathBuf {
+    let db_path = db_path();
+
+    if db_path == data_dir().join(DB_FILE_NAME) {
+        data_dir()
+    } else {
+        db_path.with_extension(BLOB_DIR_NAME)
+    }
+}
+
+/// Returns the directory where the icons of source applications are cached.
+///
+/// The directory is created if it doesn't exist yet.
+pub fn icon_cache_dir() -> PathBuf {
+    let dir = files_dir().join(ICON_DIR_NAME);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Returns where the icon of the application `app_name` is cached, in `icon_cache_dir()`.
+///
+/// The file is named after the hash of the name, which may contain anything, e.g. a `/`.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::storage;
+///
+/// println!("{:?}", storage::icon_path("Visual Studio Code", "png")); // Output: "~/.local/share/paste-fork/icons/6e1f…9a0b.png"
+/// ```
+pub fn icon_path(app_name: &str, extension: &str) -> PathBuf {
+    icon_cache_dir().join(format!("{}.{}", hash_text(app_name), extension))
+}
+
+/// Returns the directory where full-resolution images are stored, named by content hash.
+///
+/// The directory is created if it doesn't exist yet.
+pub fn blob_dir() -> PathBuf {
+    let dir = files_dir().join(BLOB_DIR_NAME);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Returns the directory where image thumbnails are stored, named by content hash.
+///
+/// The directory is created if it doesn't exist yet.
+pub fn thumbnail_dir() -> PathBuf {
+    let dir = files_dir().join(THUMBNAIL_DIR_NAME);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Reads the value of `--db <path>` or `--db=<path>` from the command line.
+fn db_path_from_args() -> Option<PathBuf> {
+    let mut args = args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == DB_PATH_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+
+        if let Some(path) = arg
+            .strip_prefix(DB_PATH_FLAG)
+            .and_then(|s| s.strip_prefix('='))
+        {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    None
+}
+
+/// Moves a database created by an older version (which lived next to the executable)
+/// to `target`, unless `target` already exists.
+fn migrate_legacy_db(target: &Path) {
+    let Some(legacy_path) = current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(DB_FILE_NAME)))
+    else {
+        return;
+    };
+
+    if !legacy_path.exists() || target.exists() {
+        return;
+    }
+
+    log::info!(
+        "Moving database from {} to {}",
+        legacy_path.display(),
+        target.display()
+    );
+
+    // `rename` fails across file systems, in which case fall back to copy & delete
+    if fs::rename(&legacy_path, target).is_err() {
+        match fs::copy(&legacy_path, target) {
+            Ok(_) => {
+                let _ = fs::remove_file(&legacy_path);
+            }
+            Err(err) => {
+                log::error!(
+                    "Failed to move database, a new one will be created: {}",
+                    err
+                );
+                let _ = fs::remove_file(target);
+            }
+        }
+    }
+}
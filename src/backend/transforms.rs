@@ -0,0 +1,466 @@
+use base64::engine::general_purpose;
+use base64::prelude::*;
+use once_cell::sync::Lazy;
+use std::fmt;
+
+/// The transform pasting an item as plain text, unchanged.
+pub const PLAIN_TEXT: &str = "plain_text";
+
+/// Every transform offered by the command palette of the `Paste` window, in the order they are listed.
+///
+/// A new transform only has to implement `Transform` and be added here.
+static REGISTRY: Lazy<Vec<Box<dyn Transform>>> = Lazy::new(|| {
+    vec![
+        Box::new(FnTransform(PLAIN_TEXT, "Strip Formatting", |text| {
+            Ok(text.to_string())
+        })),
+        Box::new(FnTransform("trim", "Trim Whitespace", |text| {
+            Ok(trim(text))
+        })),
+        Box::new(FnTransform("upper_case", "UPPER CASE", |text| {
+            Ok(text.to_uppercase())
+        })),
+        Box::new(FnTransform("lower_case", "lower case", |text| {
+            Ok(text.to_lowercase())
+        })),
+        Box::new(FnTransform("title_case", "Title Case", |text| {
+            Ok(title_case(text))
+        })),
+        Box::new(FnTransform("snake_case", "snake_case", |text| {
+            Ok(map_lines(text, snake_case))
+        })),
+        Box::new(FnTransform("camel_case", "camelCase", |text| {
+            Ok(map_lines(text, camel_case))
+        })),
+        Box::new(FnTransform("json_pretty", "Pretty-Print JSON", json_pretty)),
+        Box::new(FnTransform("json_minify", "Minify JSON", json_minify)),
+        Box::new(FnTransform("url_encode", "URL Encode", |text| {
+            Ok(url_encode(text))
+        })),
+        Box::new(FnTransform("url_decode", "URL Decode", url_decode)),
+        Box::new(FnTransform("base64_encode", "Base64 Encode", |text| {
+            Ok(general_purpose::STANDARD.encode(text))
+        })),
+        Box::new(FnTransform("base64_decode", "Base64 Decode", base64_decode)),
+        Box::new(FnTransform("sort_lines", "Sort Lines", |text| {
+            Ok(sort_lines(text))
+        })),
+        Box::new(FnTransform(
+            "dedupe_lines",
+            "Remove Duplicate Lines",
+            |text| Ok(dedupe_lines(text)),
+        )),
+        Box::new(FnTransform("shell_escape", "Escape for Shell", |text| {
+            Ok(shell_escape(text))
+        })),
+        Box::new(FnTransform("sql_escape", "Escape for SQL", |text| {
+            Ok(sql_escape(text))
+        })),
+    ]
+});
+
+/// A change applied to the text of an item when it is pasted, e.g. `UPPER CASE`.
+///
+/// The result is always pasted as plain text, so every transform strips the formatting too.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::transforms;
+///
+/// let transform = transforms::get("snake_case").unwrap();
+/// println!("{}", transform.name()); // Output: snake_case
+/// println!("{:?}", transform.apply("Hello World")); // Output: Ok("hello_world")
+/// ```
+pub trait Transform: Send + Sync {
+    /// Identifies the transform over JSON-RPC, e.g. `json_pretty`.
+    fn id(&self) -> &'static str;
+
+    /// Shown in the command palette, e.g. `Pretty-Print JSON`.
+    fn name(&self) -> &'static str;
+
+    fn apply(&self, text: &str) -> Result<String, TransformError>;
+}
+
+/// A transform that is a plain function: its id, its name and the function.
+struct FnTransform(
+    &'static str,
+    &'static str,
+    fn(&str) -> Result<String, TransformError>,
+);
+
+impl Transform for FnTransform {
+    fn id(&self) -> &'static str {
+        self.0
+    }
+
+    fn name(&self) -> &'static str {
+        self.1
+    }
+
+    fn apply(&self, text: &str) -> Result<String, TransformError> {
+        (self.2)(text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TransformError {
+    /// No transform has this id.
+    Unknown(String),
+    /// The text can't be transformed, e.g. invalid JSON for `json_pretty`.
+    InvalidInput(String),
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::Unknown(id) => write!(f, "unknown transform {}", id),
+            TransformError::InvalidInput(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Return every transform, in the order of the command palette.
+pub fn all() -> &'static [Box<dyn Transform>] {
+    &REGISTRY
+}
+
+/// Return the transform with the id `id`, if any.
+pub fn get(id: &str) -> Option<&'static dyn Transform> {
+    REGISTRY
+        .iter()
+        .find(|transform| transform.id() == id)
+        .map(|transform| transform.as_ref())
+}
+
+/// Transform a text with the transform `id`.
+///
+/// # Arguments
+///
+/// * `id` - The id of the transform, see `all`.
+/// * `text` - The text of the item to paste.
+///
+/// # Example
+///
+/// ```
+/// use crate::backend::transforms;
+///
+/// println!("{:?}", transforms::apply("json_minify", "{ \"a\": [1, 2] }")); // Output: Ok("{\"a\":[1,2]}")
+/// println!("{:?}", transforms::apply("url_decode", "%E0%A4%A")); // Output: Err(InvalidInput("invalid URL encoding"))
+/// ```
+pub fn apply(id: &str, text: &str) -> Result<String, TransformError> {
+    get(id)
+        .ok_or_else(|| TransformError::Unknown(id.to_string()))?
+        .apply(text)
+}
+
+/// Apply `f` to every line of a text, keeping its line breaks.
+fn map_lines(text: &str, f: fn(&str) -> String) -> String {
+    text.split('\n')
+        .map(|line| match line.strip_suffix('\r') {
+            Some(line) => f(line) + "\r",
+            None => f(line),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Remove the whitespace around a text, and at the end of its lines.
+fn trim(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Capitalize the first letter of every word, and lowercase the others.
+fn title_case(text: &str) -> String {
+    let mut title = String::with_capacity(text.len());
+    let mut is_word_start = true;
+
+    for c in text.chars() {
+        if is_word_start {
+            title.extend(c.to_uppercase());
+        } else {
+            title.extend(c.to_lowercase());
+        }
+        is_word_start = c.is_whitespace();
+    }
+
+    title
+}
+
+/// Split an identifier or a phrase into its words, e.g. `parseHTTPResponse v2` into `parse`, `HTTP`, `Response`, `v2`.
+fn words(text: &str) -> Vec<String> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+
+        // A word starts at an uppercase letter after a lowercase one or a digit (`camelCase`),
+        // or at the last letter of an acronym followed by a lowercase one (`HTTPResponse`)
+        if c.is_uppercase() && !word.is_empty() {
+            let previous = chars[i - 1];
+            let next = chars.get(i + 1).copied();
+
+            if previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next.is_some_and(char::is_lowercase))
+            {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+
+        word.push(c);
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+fn snake_case(text: &str) -> String {
+    words(text)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn camel_case(text: &str) -> String {
+    words(text)
+        .iter()
+        .enumerate()
+        .map(|(i, word)| {
+            let word = word.to_lowercase();
+            if i == 0 {
+                return word;
+            }
+
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => word,
+            }
+        })
+        .collect()
+}
+
+/// Parse a JSON text, keeping the order of its keys (the `preserve_order` feature of `serde_json`).
+fn parse_json(text: &str) -> Result<serde_json::Value, TransformError> {
+    serde_json::from_str(text)
+        .map_err(|err| TransformError::InvalidInput(format!("invalid JSON: {}", err)))
+}
+
+fn json_pretty(text: &str) -> Result<String, TransformError> {
+    Ok(serde_json::to_string_pretty(&parse_json(text)?).unwrap())
+}
+
+fn json_minify(text: &str) -> Result<String, TransformError> {
+    Ok(parse_json(text)?.to_string())
+}
+
+/// Percent-encode every byte of a text, except the unreserved characters of RFC 3986.
+fn url_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+/// Decode the percent-encoded bytes of a text. A `+` is kept, as it is only a space in forms.
+fn url_decode(text: &str) -> Result<String, TransformError> {
+    let invalid = || TransformError::InvalidInput("invalid URL encoding".to_string());
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).ok_or_else(invalid)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+            decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded)
+        .map_err(|_| TransformError::InvalidInput("the decoded URL isn't text".to_string()))
+}
+
+/// Decode standard or URL-safe base64, padded or not, ignoring line breaks.
+fn base64_decode(text: &str) -> Result<String, TransformError> {
+    let text = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+
+    let decoded = [
+        general_purpose::STANDARD,
+        general_purpose::STANDARD_NO_PAD,
+        general_purpose::URL_SAFE,
+        general_purpose::URL_SAFE_NO_PAD,
+    ]
+    .iter()
+    .find_map(|engine| engine.decode(&text).ok())
+    .ok_or_else(|| TransformError::InvalidInput("invalid base64".to_string()))?;
+
+    String::from_utf8(decoded)
+        .map_err(|_| TransformError::InvalidInput("the decoded base64 isn't text".to_string()))
+}
+
+/// Join lines back together, ending with a line break if `text` did.
+fn join_lines(lines: Vec<&str>, text: &str) -> String {
+    let mut joined = lines.join("\n");
+    if text.ends_with('\n') {
+        joined.push('\n');
+    }
+    joined
+}
+
+fn sort_lines(text: &str) -> String {
+    let mut lines = text.lines().collect::<Vec<_>>();
+    lines.sort();
+    join_lines(lines, text)
+}
+
+/// Remove the lines already seen, keeping the order of the others.
+fn dedupe_lines(text: &str) -> String {
+    let mut seen = std::collections::HashSet::new();
+    let lines = text.lines().filter(|line| seen.insert(*line)).collect();
+    join_lines(lines, text)
+}
+
+/// Quote a text as a single argument of a POSIX shell.
+fn shell_escape(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Quote a text as an SQL string literal.
+fn sql_escape(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform(id: &str, text: &str) -> String {
+        apply(id, text).unwrap()
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        let mut ids = all().iter().map(|t| t.id()).collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), all().len());
+    }
+
+    #[test]
+    fn unknown_transform() {
+        assert_eq!(
+            apply("rot13", "abc"),
+            Err(TransformError::Unknown("rot13".to_string()))
+        );
+    }
+
+    #[test]
+    fn plain_text_and_trim() {
+        assert_eq!(transform(PLAIN_TEXT, " <b>bold</b> "), " <b>bold</b> ");
+        assert_eq!(transform("trim", "\n  a  \n b\t\n\n"), "a\n b");
+    }
+
+    #[test]
+    fn cases() {
+        assert_eq!(transform("upper_case", "straße"), "STRASSE");
+        assert_eq!(transform("lower_case", "ÉTÉ"), "été");
+        assert_eq!(
+            transform("title_case", "the qUICK  brown-fox"),
+            "The Quick  Brown-fox"
+        );
+        assert_eq!(
+            transform("snake_case", "parseHTTPResponse v2"),
+            "parse_http_response_v2"
+        );
+        assert_eq!(
+            transform("snake_case", "Hello World\nfoo-bar"),
+            "hello_world\nfoo_bar"
+        );
+        assert_eq!(
+            transform("camel_case", "user_id\r\nHTTP status code"),
+            "userId\r\nhttpStatusCode"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let text = r#"{"b": 1, "a": [true, null]}"#;
+        assert_eq!(
+            transform("json_pretty", text),
+            "{\n  \"b\": 1,\n  \"a\": [\n    true,\n    null\n  ]\n}"
+        );
+        assert_eq!(transform("json_minify", text), r#"{"b":1,"a":[true,null]}"#);
+        assert!(matches!(
+            apply("json_pretty", "{a: 1}"),
+            Err(TransformError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn url() {
+        assert_eq!(transform("url_encode", "a b&c=é~"), "a%20b%26c%3D%C3%A9~");
+        assert_eq!(transform("url_decode", "a%20b%26c%3D%C3%A9+"), "a b&c=é+");
+        assert!(apply("url_decode", "100%").is_err());
+        assert!(apply("url_decode", "%zz").is_err());
+        assert!(apply("url_decode", "%FF").is_err());
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(transform("base64_encode", "hello?"), "aGVsbG8/");
+        assert_eq!(transform("base64_decode", "aGVs\nbG8/"), "hello?");
+        assert_eq!(transform("base64_decode", "aGVsbG8_"), "hello?");
+        assert_eq!(transform("base64_decode", "aGk"), "hi");
+        assert!(apply("base64_decode", "not base64!").is_err());
+        assert!(apply("base64_decode", "/w==").is_err());
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(transform("sort_lines", "b\nc\na\n"), "a\nb\nc\n");
+        assert_eq!(transform("dedupe_lines", "b\na\nb\na"), "b\na");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            transform("shell_escape", "it's $HOME"),
+            r#"'it'\''s $HOME'"#
+        );
+        assert_eq!(transform("sql_escape", "O'Brien"), "'O''Brien'");
+    }
+}
@@ -1,8 +1,6 @@
 use arboard::ImageData;
-use base64::engine::general_purpose;
-use base64::prelude::*;
 use chrono::{DateTime, Local, Utc};
-use std::borrow::Cow;
+use sha2::{Digest, Sha256};
 
 /// Converts a timestamp to a human-readable relative time string.
 ///
@@ -53,28 +51,51 @@ pub fn humanize_time(timestamp: DateTime<Utc>) -> String {
     local_ts.format("%Y-%m-%d").to_string()
 }
 
-/// Helper function to convert a Base64 string into `arboard::ImageData`.
-///
-/// This process involves:
-/// 1. Decoding the Base64 string into bytes.
-/// 2. Loading the image from memory (auto-detecting format like PNG, JPEG).
-/// 3. Converting the image to **RGBA8** format (required by system clipboards).
-/// 4. Extracting raw pixels and dimensions.
-///
-/// # Panics
-/// This function will **panic** if:
-/// - The input string is not valid Base64.
-/// - The decoded bytes do not represent a valid image.
-pub fn b64_to_img_data(content: &str) -> ImageData<'_> {
-    let image_bytes = general_purpose::STANDARD.decode(content).unwrap();
-    let dynamic_image = image::load_from_memory(&image_bytes).unwrap();
-    let rgba_image = dynamic_image.to_rgba8();
-    let (width, height) = rgba_image.dimensions();
-    let pixels = rgba_image.into_raw();
-
-    ImageData {
-        width: width as usize,
-        height: height as usize,
-        bytes: Cow::Owned(pixels),
+/// Converts a size in bytes to a human-readable string, e.g. "1.5 MB".
+pub fn humanize_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
+
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = "B";
+
+    for next_unit in UNITS {
+        if size < 1000.0 {
+            break;
+        }
+        size /= 1000.0;
+        unit = next_unit;
     }
+
+    format!("{:.1} {}", size, unit)
+}
+
+/// Returns the hex-encoded SHA-256 hash of a text, used to deduplicate text items.
+pub fn hash_text(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Returns the hex-encoded SHA-256 hash of an image, used to deduplicate image items.
+///
+/// The hash covers the dimensions and the raw RGBA8 pixels rather than an encoded file,
+/// so the same picture always gets the same hash, no matter how it was encoded.
+pub fn hash_image(content: &ImageData) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update((content.width as u64).to_le_bytes());
+    hasher.update((content.height as u64).to_le_bytes());
+    hasher.update(content.bytes.as_ref());
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Whether `content_hash` looks like one of `hash_text` or `hash_image`,
+/// i.e. is safe to use as a file name.
+pub fn is_valid_hash(content_hash: &str) -> bool {
+    content_hash.len() == 64
+        && content_hash
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
 }
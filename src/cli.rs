@@ -0,0 +1,592 @@
+use chrono::{Duration, Local, Utc};
+use serde::Serialize;
+use serde_json::json;
+use std::error::Error;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+use crate::backend::archive;
+use crate::backend::client;
+use crate::backend::clipboard::{self, ContentTypes, Item};
+use crate::backend::daemon::RpcError;
+use crate::backend::encryption;
+use crate::backend::retention;
+use crate::backend::secrets;
+use crate::backend::snippets::{self, Snippet};
+use crate::backend::storage::DB_PATH_FLAG;
+use crate::backend::{blobs, storage};
+
+/// The subcommands, e.g. `paste-fork list --limit 5`. Anything else launches the `Paste` window.
+const COMMANDS: &[&str] = &[
+    "list", "search", "get", "copy", "delete", "restore", "trash", "clear", "export", "import",
+    "snippets", "help",
+];
+
+/// Environment variable holding the passphrase of the history, in `passphrase` encryption mode.
+const PASSPHRASE_ENV: &str = "PASTE_FORK_PASSPHRASE";
+
+/// The default number of items printed by `list` and `search`.
+const DEFAULT_LIMIT: usize = 20;
+
+/// The maximum number of characters of an item shown by `list` and `search`.
+const PREVIEW_LENGTH: usize = 80;
+
+const USAGE: &str = "Usage: paste-fork [--db <path>] <command> [options]
+
+Commands:
+  list [--limit <n>] [--type text|image|files] [--app <name>]
+                            Print the most recent items
+  search <query> [--limit <n>]
+                            Print the items matching a search bar query, e.g. \"#work app:Code\"
+  get <id>                  Write an item to stdout, images as PNG
+  copy <id> [--plain]       Put an item back on the clipboard
+  delete <id>... [--permanent]
+                            Move items to the trash, or delete them for good
+  restore <id>...           Move items out of the trash
+  trash [--empty]           Print the items in the trash, or delete them for good
+  clear --older-than <age>  Delete unpinned items last copied before <age> ago, e.g. 30d, 12h, 2w
+  export <file>             Write the whole history to a zip archive
+  import <file>             Merge a zip archive written by export into the history
+  snippets list [<query>]   Print every snippet, or the snippets matching a query
+  snippets add <name>       Save the text read from stdin as a snippet, replacing the one named <name>
+  snippets delete <id>...   Delete snippets
+  snippets export <file>    Write every snippet to a .toml or .json file, to share them
+  snippets import <file>    Save the snippets of a .toml or .json file written by snippets export
+
+Options:
+  --json                    Print JSON instead of text
+
+Without a command, the Paste window is launched.";
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+/// The parsed command line of a subcommand.
+#[derive(Debug, Default)]
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    json: bool,
+    plain: bool,
+    permanent: bool,
+    empty: bool,
+    limit: Option<usize>,
+    content_type: Option<ContentTypes>,
+    app: Option<String>,
+    older_than: Option<Duration>,
+}
+
+/// Run the subcommand given on the command line, if any.
+///
+/// Subcommands read and write the history database directly, so they also work while the
+/// daemon isn't running. Only `copy` goes through the daemon (starting it if needed), since
+/// the clipboard contents are lost on Linux when the process owning them exits.
+///
+/// Returns the exit code of the subcommand, or `None` if the command line has none.
+///
+/// # Example
+///
+/// ```shell
+/// $ paste-fork list --limit 1 --type text --json
+/// [{"id":42,"source_app":"Code","content_type":"TEXT","content":"fn main() {}",...}]
+/// $ paste-fork get 42 > main.rs
+/// ```
+pub fn run() -> Option<i32> {
+    let args = match parse(std::env::args().skip(1))? {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return Some(2);
+        }
+    };
+
+    let result = match args.command.as_str() {
+        "list" => list(&args),
+        "search" => search(&args),
+        "get" => get(&args),
+        "copy" => copy(&args),
+        "delete" => delete(&args),
+        "restore" => restore(&args),
+        "trash" => trash(&args),
+        "clear" => clear(&args),
+        "export" => export(&args),
+        "import" => import(&args),
+        "snippets" => snippets(&args),
+        _ => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Some(1)
+        }
+    }
+}
+
+/// Parse the command line, returning `None` if the first argument (after `--db <path>`) isn't a subcommand.
+fn parse(mut raw: impl Iterator<Item = String>) -> Option<Result<Args, String>> {
+    let mut args = Args::default();
+
+    while let Some(arg) = raw.next() {
+        // Already read by `storage::db_path`
+        if arg == DB_PATH_FLAG {
+            raw.next();
+            continue;
+        }
+        if arg.starts_with(&format!("{}=", DB_PATH_FLAG)) {
+            continue;
+        }
+
+        if args.command.is_empty() {
+            let command = if arg == "--help" || arg == "-h" {
+                "help"
+            } else {
+                arg.as_str()
+            };
+
+            if !COMMANDS.contains(&command) {
+                return None;
+            }
+            args.command = command.to_string();
+            continue;
+        }
+
+        if let Err(err) = parse_option(&mut args, arg, &mut raw) {
+            return Some(Err(err));
+        }
+    }
+
+    (!args.command.is_empty()).then_some(Ok(args))
+}
+
+/// Apply an option (reading its value from `raw`) or a positional argument of a subcommand.
+fn parse_option(
+    args: &mut Args,
+    arg: String,
+    raw: &mut impl Iterator<Item = String>,
+) -> Result<(), String> {
+    let mut value = |name: &str| {
+        raw.next()
+            .ok_or_else(|| format!("{} requires a value", name))
+    };
+
+    match arg.as_str() {
+        "--json" => args.json = true,
+        "--plain" => args.plain = true,
+        "--permanent" => args.permanent = true,
+        "--empty" => args.empty = true,
+        "--limit" => {
+            let limit = value("--limit")?;
+            args.limit = Some(
+                limit
+                    .parse()
+                    .map_err(|_| format!("invalid limit {}", limit))?,
+            );
+        }
+        "--type" => {
+            let content_type = value("--type")?;
+            args.content_type = Some(match content_type.to_lowercase().as_str() {
+                "text" => ContentTypes::Text,
+                "image" => ContentTypes::Image,
+                "file" | "files" => ContentTypes::Files,
+                _ => return Err(format!("invalid type {}", content_type)),
+            });
+        }
+        "--app" => args.app = Some(value("--app")?),
+        "--older-than" => {
+            let age = value("--older-than")?;
+            args.older_than = Some(parse_age(&age).ok_or_else(|| format!("invalid age {}", age))?);
+        }
+        option if option.starts_with("--") => return Err(format!("unknown option {}", option)),
+        _ => args.positional.push(arg),
+    }
+
+    Ok(())
+}
+
+/// Parse an age like `90m`, `12h`, `30d` or `2w`.
+fn parse_age(age: &str) -> Option<Duration> {
+    let unit = age.chars().last()?;
+    let amount = age[..age.len() - unit.len_utf8()].parse::<u32>().ok()? as i64;
+
+    match unit {
+        's' => Duration::try_seconds(amount),
+        'm' => Duration::try_minutes(amount),
+        'h' => Duration::try_hours(amount),
+        'd' => Duration::try_days(amount),
+        'w' => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+fn list(args: &Args) -> CliResult {
+    unlock()?;
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT);
+
+    let mut filter = Vec::new();
+    if let Some(content_type) = &args.content_type {
+        filter.push(format!("type:{}", content_type.as_str().to_lowercase()));
+    }
+    if let Some(app) = &args.app {
+        filter.push(format!("app:\"{}\"", app.replace('"', "")));
+    }
+
+    let items = if filter.is_empty() {
+        clipboard::get_recent_records(limit as i64)?
+    } else {
+        clipboard::get_page(None, limit as i64, &filter.join(" "))?.items
+    };
+
+    print_items(&items, args.json)
+}
+
+fn search(args: &Args) -> CliResult {
+    if args.positional.is_empty() {
+        return Err("search requires a query".into());
+    }
+    unlock()?;
+
+    let limit = args.limit.unwrap_or(DEFAULT_LIMIT);
+    let page = clipboard::get_page(None, limit as i64, &args.positional.join(" "))?;
+
+    print_items(&page.items, args.json)
+}
+
+fn get(args: &Args) -> CliResult {
+    let [id] = ids(args)?[..] else {
+        return Err("get requires a single id".into());
+    };
+    unlock()?;
+
+    let item = clipboard::get_record(id)?.ok_or_else(|| format!("no item {}", id))?;
+
+    if args.json {
+        return print_json(&item);
+    }
+
+    let mut stdout = io::stdout().lock();
+    match item.content_type {
+        ContentTypes::Image => {
+            stdout.write_all(&blobs::read_png(&item.content_hash)?)?
+        }
+        ContentTypes::Text | ContentTypes::Files => stdout.write_all(item.content.as_bytes())?,
+    }
+    stdout.flush()?;
+
+    Ok(())
+}
+
+fn copy(args: &Args) -> CliResult {
+    let [id] = ids(args)?[..] else {
+        return Err("copy requires a single id".into());
+    };
+
+    client::ensure_daemon();
+
+    match client::set_clipboard(id, args.plain) {
+        Err(err) if err.code == RpcError::UNREACHABLE => {
+            // Without a daemon, write the clipboard ourselves. On Linux, it is only kept if a
+            // clipboard manager takes over when this process exits.
+            unlock()?;
+            let item = clipboard::get_record(id)?.ok_or_else(|| format!("no item {}", id))?;
+            clipboard::write_to_clipboard(&item, args.plain)?;
+            clipboard::update_timestamp(id)?;
+            Ok(())
+        }
+        result => Ok(result?),
+    }
+}
+
+fn delete(args: &Args) -> CliResult {
+    let ids = ids(args)?;
+    if ids.is_empty() {
+        return Err("delete requires at least one id".into());
+    }
+    unlock()?;
+
+    for &id in &ids {
+        if clipboard::get_record(id)?.is_none() {
+            return Err(format!("no item {}", id).into());
+        }
+    }
+    if args.permanent {
+        for &id in &ids {
+            clipboard::delete_record(id)?;
+        }
+    } else {
+        clipboard::delete_many(&ids)?;
+    }
+
+    if args.json {
+        return print_json(&json!({ "deleted": ids }));
+    }
+    Ok(())
+}
+
+fn restore(args: &Args) -> CliResult {
+    let ids = ids(args)?;
+    if ids.is_empty() {
+        return Err("restore requires at least one id".into());
+    }
+    unlock()?;
+
+    let restored = clipboard::restore_items(&ids)?;
+
+    if args.json {
+        return print_json(&json!({ "restored": restored }));
+    }
+    Ok(())
+}
+
+fn trash(args: &Args) -> CliResult {
+    unlock()?;
+
+    if !args.empty {
+        return print_items(&clipboard::get_trash()?, args.json);
+    }
+
+    let deleted = clipboard::empty_trash()?;
+
+    if args.json {
+        return print_json(&json!({ "deleted": deleted }));
+    }
+    println!("Deleted {} items", deleted);
+    Ok(())
+}
+
+fn clear(args: &Args) -> CliResult {
+    let age = args
+        .older_than
+        .ok_or("clear requires --older-than, e.g. --older-than 30d")?;
+    unlock()?;
+
+    let deleted = retention::clear_older_than(Utc::now() - age)?;
+
+    if args.json {
+        return print_json(&json!({ "deleted": deleted }));
+    }
+    println!("Deleted {} items", deleted);
+    Ok(())
+}
+
+fn export(args: &Args) -> CliResult {
+    let [path] = &args.positional[..] else {
+        return Err("export requires a single file".into());
+    };
+    unlock()?;
+
+    let count = archive::export(Path::new(path))?;
+
+    if args.json {
+        return print_json(&json!({ "exported": count }));
+    }
+    println!("Exported {} items to {}", count, path);
+    Ok(())
+}
+
+fn import(args: &Args) -> CliResult {
+    let [path] = &args.positional[..] else {
+        return Err("import requires a single file".into());
+    };
+    unlock()?;
+
+    let summary = archive::import(Path::new(path))?;
+
+    if args.json {
+        return print_json(&summary);
+    }
+    println!(
+        "Imported {} new items, merged {} into existing ones",
+        summary.added, summary.merged
+    );
+    if summary.skipped > 0 {
+        println!(
+            "Skipped {} images missing from the archive",
+            summary.skipped
+        );
+    }
+    Ok(())
+}
+
+fn snippets(args: &Args) -> CliResult {
+    let Some((action, rest)) = args.positional.split_first() else {
+        return Err("snippets requires list, add, delete, export or import".into());
+    };
+    unlock()?;
+
+    match (action.as_str(), rest) {
+        ("list", query) => {
+            let query = if query.is_empty() {
+                "type:snippet".to_string()
+            } else {
+                query.join(" ")
+            };
+            print_snippets(&snippets::search(&query)?, args.json)
+        }
+        ("add", [name]) => {
+            let mut content = String::new();
+            io::stdin().lock().read_to_string(&mut content)?;
+
+            let id = snippets::save_snippet(name, content.trim_end_matches(['\r', '\n']))?;
+
+            if args.json {
+                return print_json(&json!({ "id": id }));
+            }
+            println!("{}", id);
+            Ok(())
+        }
+        ("delete", ids) if !ids.is_empty() => {
+            let ids = ids
+                .iter()
+                .map(|id| id.parse().map_err(|_| format!("invalid id {}", id)))
+                .collect::<Result<Vec<i64>, _>>()?;
+
+            for &id in &ids {
+                if snippets::get_snippet(id)?.is_none() {
+                    return Err(format!("no snippet {}", id).into());
+                }
+            }
+            for &id in &ids {
+                snippets::delete_snippet(id)?;
+            }
+
+            if args.json {
+                return print_json(&json!({ "deleted": ids }));
+            }
+            Ok(())
+        }
+        ("export", [path]) => {
+            let count = snippets::export(Path::new(path))?;
+
+            if args.json {
+                return print_json(&json!({ "exported": count }));
+            }
+            println!("Exported {} snippets to {}", count, path);
+            Ok(())
+        }
+        ("import", [path]) => {
+            let count = snippets::import(Path::new(path))?;
+
+            if args.json {
+                return print_json(&json!({ "imported": count }));
+            }
+            println!("Imported {} snippets", count);
+            Ok(())
+        }
+        ("add", _) => Err("snippets add requires a single name".into()),
+        ("delete", _) => Err("snippets delete requires at least one id".into()),
+        ("export" | "import", _) => {
+            Err(format!("snippets {} requires a single file", action).into())
+        }
+        _ => Err(format!("unknown snippets action {}", action).into()),
+    }
+}
+
+/// The positional arguments, as item ids.
+fn ids(args: &Args) -> Result<Vec<i64>, String> {
+    args.positional
+        .iter()
+        .map(|id| id.parse().map_err(|_| format!("invalid id {}", id)))
+        .collect()
+}
+
+/// Unlock the history, asking for the passphrase in `passphrase` encryption mode.
+///
+/// The passphrase is read from `$PASTE_FORK_PASSPHRASE`, or from stdin (with a prompt on stderr).
+fn unlock() -> Result<(), Box<dyn Error>> {
+    // Only the daemon encrypts a database still in plain text, see `encryption::open`
+    if encryption::is_enabled() {
+        client::ensure_daemon();
+    }
+
+    if !clipboard::is_locked() {
+        return Ok(());
+    }
+
+    // In `keyring` mode, retry the key to report why it didn't open the database
+    if !encryption::needs_passphrase() {
+        return Ok(clipboard::unlock(None)?);
+    }
+
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => {
+            eprint!("Passphrase for {}: ", storage::db_path().display());
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    Ok(clipboard::unlock(Some(&passphrase))?)
+}
+
+/// Print items as a JSON array, or one per line as `id`, `time`, `app` and a preview separated by tabs.
+fn print_items(items: &[Item], as_json: bool) -> CliResult {
+    if as_json {
+        return print_json(&items);
+    }
+
+    for item in items {
+        println!(
+            "{}\t{}\t{}\t{}",
+            item.id,
+            item.timestamp
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            item.source_app,
+            preview(item)
+        );
+    }
+
+    Ok(())
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> CliResult {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
+
+/// Print snippets as a JSON array, or one per line as `id`, `name` and a preview separated by tabs.
+fn print_snippets(snippets: &[Snippet], as_json: bool) -> CliResult {
+    if as_json {
+        return print_json(&snippets);
+    }
+
+    for snippet in snippets {
+        println!(
+            "{}\t{}\t{}",
+            snippet.id,
+            snippet.name,
+            shorten(&snippet.content)
+        );
+    }
+
+    Ok(())
+}
+
+/// A single-line preview of an item, hiding what the `Paste` window hides.
+fn preview(item: &Item) -> String {
+    let text = match item.content_type {
+        _ if item.redacted => return "[hidden]".to_string(),
+        ContentTypes::Image => return "[image]".to_string(),
+        ContentTypes::Files => item.content.lines().collect::<Vec<_>>().join(" "),
+        ContentTypes::Text if item.masked => secrets::mask(&item.content),
+        ContentTypes::Text => item.content.clone(),
+    };
+
+    shorten(&text)
+}
+
+/// A text on a single line, cut after `PREVIEW_LENGTH` characters.
+fn shorten(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    match text.char_indices().nth(PREVIEW_LENGTH) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
@@ -1,31 +1,69 @@
 mod backend;
+mod cli;
 
-use arboard::Clipboard;
-use dioxus::html::{input_data::keyboard_types::Key};
+use dioxus::html::{geometry::PixelsVector2D, input_data::keyboard_types::Key, ScrollBehavior};
 use dioxus::prelude::*;
 use dioxus_desktop::{
     tao::dpi::{LogicalPosition, LogicalSize},
-    use_global_shortcut, use_window, Config, WindowBuilder,
+    use_global_shortcut, use_window,
+    wry::{
+        http::{Request, Response, StatusCode},
+        WebViewId,
+    },
+    Config, WindowBuilder,
 };
 use global_hotkey::HotKeyState;
 use once_cell::sync::Lazy;
-use std::process::Command;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::sync::{Arc, RwLock};
 use std::thread;
-use std::{collections::HashMap, sync::atomic::Ordering};
 use tokio::sync::mpsc;
 
+use crate::backend::blobs;
 use crate::backend::clipboard::{self, ContentTypes};
-use crate::backend::clipboard::{update_timestamp, IS_INTERNAL_PASTE};
-use crate::backend::utils::{b64_to_img_data, humanize_time};
+use crate::backend::daemon::{self, DAEMON_FLAG};
+use crate::backend::client;
+use crate::backend::encryption;
+use crate::backend::merge::{CollageLayout, Separator};
+use crate::backend::paste;
+use crate::backend::secrets;
+use crate::backend::snippets::{self, Snippet};
+use crate::backend::stack::PasteStack;
+use crate::backend::transforms::{self, Transform};
+use crate::backend::utils::{humanize_size, humanize_time};
 
 const MAIN_CSS: Asset = asset!("/assets/main.css");
 const TAILWIND_CSS: Asset = asset!("/assets/tailwind.css");
 
+const PAGE_SIZE: i64 = 50; // Number of clipboard items fetched from the DB at a time
+const CARD_GAP: f64 = 20.0; // Gap between two `ClipboardCard`s, in px
+const CARD_SPAN: f64 = 240.0 + CARD_GAP; // Width of a `ClipboardCard` plus the gap after it, in px
+const OVERSCAN: usize = 4; // Number of cards rendered beyond each edge of the viewport
+const DEFAULT_PINBOARD_NAME: &str = "Pinned"; // Created the first time an item is pinned from the history
+const SEPARATORS: [(Separator, &str); 4] = [
+    (Separator::Newline, "Newline"),
+    (Separator::Comma, "Comma"),
+    (Separator::Tab, "Tab"),
+    (Separator::Custom(String::new()), "Custom"),
+]; // The separators offered when merging text items
+const PREVIEW_LENGTH: usize = 80; // Number of characters of a transformed text previewed by the command palette
+const UNDO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5); // How long a deletion can be undone from the toast
+
 #[derive(Clone)]
 pub struct WindowInfo {
     pub is_visible: bool, // represents the current window's status is visible or not
     pub visibility_setter: mpsc::UnboundedSender<bool>, // A mpsc sender for setting `is_visible`
+    pub paste_next: mpsc::UnboundedSender<()>, // A mpsc sender for pasting the next item of the paste stack
+}
+
+/// The field of the selected item being edited in the `Paste` window
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ItemField {
+    Tags,
+    Note,
+    Snippet, // The name of a new snippet made of the item's text
 }
 
 static WINDOW_REGISTRY: Lazy<Arc<RwLock<HashMap<String, WindowInfo>>>> =
@@ -35,6 +73,19 @@ static WINDOW_REGISTRY: Lazy<Arc<RwLock<HashMap<String, WindowInfo>>>> =
 //                            MAIN ENTRY
 // ------------------------------------------------------------------
 fn main() {
+    // Headless: capture the clipboard and serve the history to the `Paste` window (and any other client)
+    if std::env::args().any(|arg| arg == DAEMON_FLAG) {
+        daemon::run();
+        return;
+    }
+
+    // e.g. `paste-fork list --json`, see `cli::run`
+    if let Some(code) = cli::run() {
+        std::process::exit(code);
+    }
+
+    client::ensure_daemon();
+
     let config = Config::new().with_window(default_app_window_config());
 
     dioxus::LaunchBuilder::desktop()
@@ -69,6 +120,26 @@ fn default_paste_window_config() -> WindowBuilder {
         .with_visible(false)
 }
 
+/// Serve the thumbnails and app icons of the cards (see `blobs::thumbnail_url`), as the daemon reads them.
+///
+/// The web view can't read encrypted files, so it never gets their path, and the daemon only decrypts
+/// the images that are actually displayed.
+fn serve_display_file(_: WebViewId, request: Request<Vec<u8>>) -> Response<Cow<'static, [u8]>> {
+    match client::get_display_file(request.uri().path()) {
+        Ok(file) => Response::builder()
+            .header("Content-Type", file.mime.as_str())
+            .body(Cow::Owned(file.bytes()))
+            .unwrap(),
+        Err(err) => {
+            log::debug!("Failed to load {}: {}", request.uri(), err);
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Cow::Borrowed(&[][..]))
+                .unwrap()
+        }
+    }
+}
+
 // ------------------------------------------------------------------
 //                            COMPONENTS
 // ------------------------------------------------------------------
@@ -80,7 +151,9 @@ fn App() -> Element {
     use_effect(move || {
         window.new_window(
             VirtualDom::new(Paste),
-            Config::new().with_window(default_paste_window_config()),
+            Config::new()
+                .with_window(default_paste_window_config())
+                .with_custom_protocol(blobs::PROTOCOL, serve_display_file),
         );
     });
 
@@ -89,6 +162,10 @@ fn App() -> Element {
         if let HotKeyState::Pressed = s {
             if let Ok(mut registry) = WINDOW_REGISTRY.write() {
                 if let Some(info) = registry.get_mut("Paste") {
+                    // The app to paste into is the one focused before the window opens
+                    if !info.is_visible {
+                        paste::remember_focus();
+                    }
                     info.visibility_setter.send(!info.is_visible).unwrap();
                 }
             }
@@ -96,6 +173,18 @@ fn App() -> Element {
     })
     .unwrap();
 
+    // Hotkey: Paste the next item of the paste stack into the focused app
+    use_global_shortcut("ALT+CMD+V", move |s| {
+        if let HotKeyState::Pressed = s {
+            if let Ok(registry) = WINDOW_REGISTRY.read() {
+                if let Some(info) = registry.get("Paste") {
+                    info.paste_next.send(()).unwrap();
+                }
+            }
+        }
+    })
+    .unwrap();
+
     rsx!("")
 }
 
@@ -103,8 +192,30 @@ fn App() -> Element {
 fn Paste() -> Element {
     let window = use_window();
     let mut clipboard_items = use_signal(Vec::<clipboard::Item>::new);
+    let mut next_page = use_signal(|| None::<clipboard::PageCursor>);
     let mut search_bar = use_signal(|| "".to_string());
     let mut selected_item_index = use_signal(|| 0);
+    let mut card_list = use_signal(|| None::<Rc<MountedData>>);
+    let mut scroll_left = use_signal(|| 0.0);
+    let mut viewport_width = use_signal(|| 0.0);
+    let mut is_locked = use_signal(|| client::is_locked().unwrap_or(false));
+    let mut passphrase = use_signal(|| "".to_string());
+    let mut unlock_error = use_signal(|| None::<String>);
+    let mut pinboards = use_signal(Vec::<clipboard::Pinboard>::new);
+    let mut current_pinboard = use_signal(|| None::<i64>); // The pinboard tab shown, `None` for the history
+    let mut new_pinboard_name = use_signal(|| None::<String>); // The name typed in for a new pinboard, if any
+    let mut editing = use_signal(|| None::<(i64, ItemField, String)>); // The item, field and value being edited, if any
+    let mut last_deleted = use_signal(Vec::<i64>::new); // The items moved to the trash that the undo toast can restore
+    let mut paste_stack = use_signal(PasteStack::default); // The items pasted one after the other by the paste-next hotkey
+    let mut multi_selection = use_signal(Vec::<i64>::new); // The items selected to be merged, in the order they were selected
+    let mut merging = use_signal(|| false); // Whether the merge bar is shown
+    let mut merge_separator = use_signal(Separator::default);
+    let mut collage_layout = use_signal(CollageLayout::default);
+    let mut merge_error = use_signal(|| None::<String>);
+    let mut transforming = use_signal(|| None::<(clipboard::Item, String, usize)>); // The item, filter and highlighted transform of the command palette, if shown
+    let mut snippet_results = use_signal(Vec::<Snippet>::new); // The snippets matching the search bar, shown before the history
+    let mut selected_snippet = use_signal(|| None::<usize>); // The snippet selected instead of an item, if any
+    let mut snippet_inputs = use_signal(|| None::<(Snippet, Vec<(String, String)>)>); // The snippet being pasted and the values typed in for its `{input:Name}` fields
 
     // Change Window Size
     use_effect({
@@ -124,26 +235,434 @@ fn Paste() -> Element {
         }
     });
 
-    // A hook to filter the clipboard items based on the user input
-    // The search bar is used to filter the clipboard items
-    let filtered_items = use_memo(move || {
-        let query = search_bar.read().to_lowercase();
-        let clipboard_items = clipboard_items.read();
+    // A helper to replace the loaded clipboard items with the first page matching the search bar
+    // Non-empty queries are run against the full-text index of the database
+    let mut reload_items = move || {
+        // Nothing can be read while the history is locked
+        if is_locked() {
+            clipboard_items.set(Vec::new());
+            next_page.set(None);
+            snippet_results.set(Vec::new());
+            return;
+        }
+
+        let query = search_bar.read().trim().to_string();
 
-        if query.is_empty() {
-            log::trace!("Query is empty");
-            clipboard_items.clone()
+        // Pinboards are ordered by hand and small enough to be loaded at once
+        if let Some(pinboard_id) = current_pinboard() {
+            log::trace!("Loading pinboard {} for query: {}", pinboard_id, query);
+            snippet_results.set(Vec::new());
+
+            match client::get_pinboard_items(pinboard_id, &query) {
+                Ok(items) => {
+                    next_page.set(None);
+                    clipboard_items.set(items);
+                }
+                Err(err) => log::error!("Failed to load pinboard: {}", err),
+            }
+            return;
+        }
+
+        log::trace!("Loading first page for query: {}", query);
+
+        // Snippets are only searched for, see `snippets::search`
+        match client::get_snippets(&query) {
+            Ok(snippets) => snippet_results.set(snippets),
+            Err(err) => log::error!("Failed to search snippets: {}", err),
+        }
+
+        match client::get_page(None, PAGE_SIZE, &query) {
+            Ok(page) => {
+                next_page.set(page.next);
+                clipboard_items.set(page.items);
+            }
+            Err(err) => log::error!("Failed to load clipboard history: {}", err),
+        }
+    };
+
+    // A helper to append the next page of clipboard items, if there is one
+    let mut load_more_items = move || {
+        let Some(after) = next_page.peek().clone() else {
+            return;
+        };
+        if *is_locked.peek() || current_pinboard.peek().is_some() {
+            return;
+        }
+
+        let query = search_bar.peek().trim().to_string();
+        log::trace!("Loading next page after {:?}", after);
+
+        match client::get_page(Some(&after), PAGE_SIZE, &query) {
+            Ok(page) => {
+                next_page.set(page.next);
+                clipboard_items.write().extend(page.items);
+            }
+            Err(err) => log::error!("Failed to load clipboard history: {}", err),
+        }
+    };
+
+    // A helper to lock the history, see `clipboard::lock`
+    // The loaded items are dropped by `reload_items`, which reruns whenever `is_locked` changes
+    let mut lock_history = move || {
+        if let Err(err) = client::lock() {
+            log::error!("Failed to lock clipboard history: {}", err);
+            return;
+        }
+        search_bar.set("".to_string());
+        selected_item_index.set(0);
+        is_locked.set(true);
+    };
+
+    // A helper to unlock the history with the passphrase typed in (or the key of the OS keyring)
+    let mut unlock_history = move || {
+        let typed_passphrase = passphrase.peek().clone();
+        let result = client::unlock(
+            encryption::needs_passphrase().then_some(typed_passphrase.as_str()),
+        );
+
+        match result {
+            Ok(()) => {
+                passphrase.set("".to_string());
+                unlock_error.set(None);
+                is_locked.set(false);
+            }
+            Err(err) => {
+                log::warn!("Failed to unlock clipboard history: {}", err);
+                unlock_error.set(Some(err.to_string()));
+            }
+        }
+    };
+
+    // A hook to load the pinboards whenever the history is (un)locked
+    use_effect(move || {
+        if is_locked() {
+            pinboards.set(Vec::new());
+            current_pinboard.set(None);
         } else {
-            log::trace!("User input: {}", query);
-            clipboard_items
+            pinboards.set(client::get_pinboards().unwrap_or_default());
+        }
+    });
+
+    // A helper to show the tab `offset` tabs away from the current one, the history being the first tab
+    let mut switch_tab = move |offset: i64| {
+        let tabs: Vec<Option<i64>> = std::iter::once(None)
+            .chain(pinboards.peek().iter().map(|pinboard| Some(pinboard.id)))
+            .collect();
+        let current = tabs
+            .iter()
+            .position(|tab| *tab == *current_pinboard.peek())
+            .unwrap_or(0) as i64;
+
+        current_pinboard.set(tabs[(current + offset).rem_euclid(tabs.len() as i64) as usize]);
+    };
+
+    // A helper to create a pinboard with the name typed in, and show it
+    let mut add_pinboard = move || {
+        let name = new_pinboard_name.take().unwrap_or_default().trim().to_string();
+        if name.is_empty() {
+            return;
+        }
+
+        match client::create_pinboard(&name) {
+            Ok(id) => {
+                pinboards.set(client::get_pinboards().unwrap_or_default());
+                current_pinboard.set(Some(id));
+            }
+            Err(err) => log::error!("Failed to create pinboard: {}", err),
+        }
+    };
+
+    // A helper to delete a pinboard, its items stay in the history
+    let mut remove_pinboard = move |id: i64| {
+        if let Err(err) = client::delete_pinboard(id) {
+            log::error!("Failed to delete pinboard {}: {}", id, err);
+            return;
+        }
+
+        pinboards.set(client::get_pinboards().unwrap_or_default());
+        current_pinboard.set(None);
+    };
+
+    // A helper to pin or unpin the selected item
+    // On a pinboard, the item is removed from it. In the history, the item is added to the first pinboard,
+    // or removed from every pinboard if it is already pinned.
+    let mut toggle_pin = move || {
+        let Some(item) = clipboard_items
+            .peek()
+            .get(*selected_item_index.peek())
+            .cloned()
+        else {
+            return;
+        };
+
+        let result = match *current_pinboard.peek() {
+            Some(pinboard_id) => client::unpin(item.id, pinboard_id),
+            None if item.pinned => pinboards
+                .peek()
                 .iter()
-                .filter(|item| {
-                    item.source_app.to_lowercase().contains(&query)
-                        || item.content.to_lowercase().contains(&query)
-                })
-                .cloned()
-                .collect()
+                .try_for_each(|pinboard| client::unpin(item.id, pinboard.id)),
+            None => {
+                let pinboard_id = match pinboards.peek().first() {
+                    Some(pinboard) => Ok(pinboard.id),
+                    None => client::create_pinboard(DEFAULT_PINBOARD_NAME),
+                };
+                pinboard_id.and_then(|pinboard_id| client::pin(item.id, pinboard_id))
+            }
+        };
+
+        if let Err(err) = result {
+            log::error!("Failed to pin item {}: {}", item.id, err);
+        }
+
+        pinboards.set(client::get_pinboards().unwrap_or_default());
+        reload_items();
+
+        let max_len = clipboard_items.peek().len();
+        if *selected_item_index.peek() >= max_len {
+            selected_item_index.set(max_len.saturating_sub(1));
         }
+    };
+
+    // A helper to move the selected item of a pinboard by `offset` places, keeping it selected
+    let mut move_selected_item = move |offset: i64| {
+        let Some(pinboard_id) = *current_pinboard.peek() else {
+            return;
+        };
+        let Some(item_id) = clipboard_items
+            .peek()
+            .get(*selected_item_index.peek())
+            .map(|item| item.id)
+        else {
+            return;
+        };
+
+        if let Err(err) = client::move_pinned_item(item_id, pinboard_id, offset) {
+            log::error!("Failed to move item {}: {}", item_id, err);
+            return;
+        }
+
+        reload_items();
+
+        let position = clipboard_items
+            .peek()
+            .iter()
+            .position(|item| item.id == item_id);
+        if let Some(position) = position {
+            selected_item_index.set(position);
+        }
+    };
+
+    // A helper to move the selected item to the trash, and offer to undo it for a few seconds
+    let mut delete_selected_item = move || {
+        let Some(item_id) = clipboard_items
+            .peek()
+            .get(*selected_item_index.peek())
+            .map(|item| item.id)
+        else {
+            return;
+        };
+
+        if let Err(err) = client::delete_many(&[item_id]) {
+            log::error!("Failed to delete item {}: {}", item_id, err);
+            return;
+        }
+
+        clipboard_items.write().retain(|item| item.id != item_id);
+        paste_stack.write().remove(item_id);
+        let max_len = clipboard_items.peek().len();
+        if *selected_item_index.peek() >= max_len {
+            selected_item_index.set(max_len.saturating_sub(1));
+        }
+
+        // The toast is only hidden if no other item was deleted in the meantime
+        last_deleted.set(vec![item_id]);
+        spawn(async move {
+            tokio::time::sleep(UNDO_TIMEOUT).await;
+            if *last_deleted.peek() == [item_id] {
+                last_deleted.set(Vec::new());
+            }
+        });
+    };
+
+    // A helper to restore the items deleted last, and select the first of them
+    let mut undo_delete = move || {
+        let ids = last_deleted.take();
+        if ids.is_empty() {
+            return;
+        }
+
+        if let Err(err) = client::restore_items(&ids) {
+            log::error!("Failed to restore items {:?}: {}", ids, err);
+            return;
+        }
+
+        reload_items();
+
+        let position = clipboard_items
+            .peek()
+            .iter()
+            .position(|item| ids.contains(&item.id));
+        if let Some(position) = position {
+            selected_item_index.set(position);
+        }
+    };
+
+    // A helper to put the selected item on the paste stack, or take it off
+    let mut toggle_stacked = move || {
+        let Some(item) = clipboard_items
+            .peek()
+            .get(*selected_item_index.peek())
+            .cloned()
+        else {
+            return;
+        };
+
+        paste_stack.write().toggle(item);
+    };
+
+    // A helper to add the items at `from` and `to` to the multi-selection, when the selection moves with ⇧ or ⌘ held
+    let mut extend_selection = move |from: usize, to: usize| {
+        let ids = {
+            let items = clipboard_items.peek();
+            [from, to]
+                .iter()
+                .filter_map(|index| items.get(*index).map(|item| item.id))
+                .collect::<Vec<_>>()
+        };
+
+        let mut selection = multi_selection.write();
+        for id in ids {
+            if !selection.contains(&id) {
+                selection.push(id);
+            }
+        }
+    };
+
+    // A helper to show the merge bar, once at least two items are selected
+    let mut start_merging = move || {
+        if multi_selection.peek().len() < 2 {
+            return;
+        }
+        merge_error.set(None);
+        merging.set(true);
+    };
+
+    // A helper to show the command palette of the transforms of the selected item, unless it is an image
+    let mut start_transforming = move || {
+        let Some(item) = clipboard_items
+            .peek()
+            .get(*selected_item_index.peek())
+            .cloned()
+        else {
+            return;
+        };
+
+        if item.content_type == ContentTypes::Image || item.redacted {
+            return;
+        }
+        transforming.set(Some((item, "".to_string(), 0)));
+    };
+
+    // A helper to edit the tags or the note of the selected item, or to name a snippet made of it
+    let mut start_editing = move |field: ItemField| {
+        let Some(item) = clipboard_items
+            .peek()
+            .get(*selected_item_index.peek())
+            .cloned()
+        else {
+            return;
+        };
+
+        // Only plain text can be a snippet
+        if field == ItemField::Snippet && (item.content_type != ContentTypes::Text || item.redacted) {
+            return;
+        }
+
+        let value = match field {
+            ItemField::Tags => item
+                .tags
+                .iter()
+                .map(|tag| format!("#{}", tag))
+                .collect::<Vec<_>>()
+                .join(" "),
+            ItemField::Note => item.note.clone(),
+            ItemField::Snippet => item.content.lines().next().unwrap_or_default().trim().chars().take(40).collect(),
+        };
+        editing.set(Some((item.id, field, value)));
+    };
+
+    // A helper to save the tags or the note being edited and refresh the edited card, or to save the snippet being named
+    let mut save_editing = move || {
+        let Some((id, field, value)) = editing.take() else {
+            return;
+        };
+
+        let result = match field {
+            ItemField::Tags => client::set_tags(
+                id,
+                &value.split_whitespace().map(str::to_string).collect::<Vec<_>>(),
+            ),
+            ItemField::Note => client::set_note(id, &value),
+            ItemField::Snippet => {
+                let content = clipboard_items
+                    .peek()
+                    .iter()
+                    .find(|item| item.id == id)
+                    .map(|item| item.content.clone())
+                    .unwrap_or_default();
+                client::save_snippet(value.trim(), &content).map(|_| ())
+            }
+        };
+
+        if let Err(err) = result {
+            log::error!("Failed to save item {}: {}", id, err);
+            return;
+        }
+
+        if let Ok(Some(item)) = client::get_record(id) {
+            if let Some(card) = clipboard_items.write().iter_mut().find(|i| i.id == id) {
+                *card = item;
+            }
+        }
+    };
+
+    // A hook to reload the clipboard items whenever the search bar or the tab changes, or the history is (un)locked
+    use_effect(move || {
+        reload_items();
+        selected_item_index.set(0);
+
+        // The first snippet is selected when the search matches snippets only
+        let only_snippets = clipboard_items.peek().is_empty() && !snippet_results.peek().is_empty();
+        selected_snippet.set(only_snippets.then_some(0));
+    });
+
+    // A hook to keep the selected clipboard item inside the viewport
+    // Cards outside the viewport aren't rendered, so the browser can't do this on its own
+    use_effect(move || {
+        let index = selected_item_index();
+        let snippet_index = selected_snippet();
+        let Some(card_list) = card_list.peek().clone() else {
+            return;
+        };
+
+        // The snippet cards come before the items
+        let card_left = snippet_index.unwrap_or(snippet_results.peek().len() + index) as f64 * CARD_SPAN;
+        let visible_left = *scroll_left.peek();
+        let visible_right = visible_left + *viewport_width.peek();
+
+        let target = if card_left < visible_left {
+            card_left
+        } else if card_left + CARD_SPAN > visible_right {
+            card_left + CARD_SPAN - *viewport_width.peek()
+        } else {
+            return;
+        };
+
+        spawn(async move {
+            let _ = card_list
+                .scroll(PixelsVector2D::new(target, 0.0), ScrollBehavior::Instant)
+                .await;
+        });
     });
 
     // A hook to set the visibility of the `Paste` window
@@ -181,63 +700,57 @@ fn Paste() -> Element {
         tx
     });
 
-    // Start listening to system clipboard after component rendered
+    // Start listening to the items saved by the daemon after component rendered
     use_effect(move || {
-        let (tx, mut rx) = mpsc::unbounded_channel::<()>();
-        thread::spawn(move || clipboard::listen(tx));
+        let (tx, mut rx) = mpsc::unbounded_channel::<i64>();
+        thread::spawn(move || client::subscribe(tx));
 
         spawn(async move {
-            while rx.recv().await.is_some() {
-                log::trace!("Received clipboard DB completed updating signal");
-                clipboard_items.set(clipboard::get_all_records().unwrap()); // BUG: Memory could goes insufficient if `get_all_records` returns massive amount of data
-            }
-        });
-    });
+            while let Some(id) = rx.recv().await {
+                log::trace!("Received clipboard DB completed updating signal: {}", id);
 
-    // Register the `Paste` window to the window registry after component rendered
-    use_effect({
-        to_owned![visibility_setter];
+                // Whether a new item matches the search query (or is on the pinboard shown) is up to the DB, so just reload
+                if !search_bar.peek().trim().is_empty() || current_pinboard.peek().is_some() {
+                    reload_items();
+                    continue;
+                }
 
-        move || {
-            if let Ok(mut registry) = WINDOW_REGISTRY.write() {
-                registry.insert(
-                    "Paste".to_string(),
-                    WindowInfo {
-                        visibility_setter: visibility_setter.clone(),
-                        is_visible: false,
-                    },
-                );
+                // Otherwise only fetch the new item and put it at the front
+                if let Ok(Some(item)) = client::get_record(id) {
+                    let mut clipboard_items = clipboard_items.write();
+                    clipboard_items.retain(|i| i.id != id);
+                    clipboard_items.insert(0, item);
+                }
             }
-            log::trace!("Paste window registered");
-        }
+        });
     });
 
     // Action Handler `do_paste`: Copy the selected clipboard item
     // Triggered when user select a clipboard item
+    // `as_plain_text` drops the formatting (HTML, RTF...) the item was copied with,
+    // `transform` pastes its text changed by a transform instead, see `transforms::apply`
     let do_paste = {
         to_owned![visibility_setter];
 
-        move |item: clipboard::Item| {
+        move |item: clipboard::Item, as_plain_text: bool, transform: Option<&'static str>| {
             spawn(async move {
-                // BE Update: update system clipboard
-                let mut clipboard = Clipboard::new().unwrap();
-
-                IS_INTERNAL_PASTE.store(true, Ordering::SeqCst);
-
-                if item.content_type == ContentTypes::Text {
-                    clipboard.set_text(&item.content).unwrap();
-                } else {
-                    clipboard.set_image(b64_to_img_data(&item.content)).unwrap();
+                // BE Update: update system clipboard, in every format the item was copied in,
+                // and update the selected item's timestamp to now
+                let result = match transform {
+                    Some(transform) => client::set_clipboard_transformed(item.id, transform),
+                    None => client::set_clipboard(item.id, as_plain_text),
+                };
+                if let Err(err) = result {
+                    log::error!("Failed to paste item {}: {}", item.id, err);
                 }
 
-                // DB Update: Update the selected item's timestamp to now
-                update_timestamp(item.id).unwrap();
-
-                // UI Update: Move the selected item to the index[0]
-                let mut clipboard_items = clipboard_items.write();
-                if let Some(pos) = clipboard_items.iter().position(|i| i.id == item.id) {
-                    let item = clipboard_items.remove(pos);
-                    clipboard_items.insert(0, item);
+                // UI Update: Move the selected item to the index[0], unless it is on the pinboard shown
+                if current_pinboard.peek().is_none() {
+                    let mut clipboard_items = clipboard_items.write();
+                    if let Some(pos) = clipboard_items.iter().position(|i| i.id == item.id) {
+                        let item = clipboard_items.remove(pos);
+                        clipboard_items.insert(0, item);
+                    }
                 }
 
                 // UI Update: Reset the search bar and selected index
@@ -247,53 +760,265 @@ fn Paste() -> Element {
                 // UI Update: Hide the window
                 visibility_setter.send(false).unwrap();
 
-                // UX Update: refocusing preview application
-                Command::new("osascript")
-                    .arg("-e")
-                    .arg(
-                        r#"
-                        tell application "System Events"
-                            set visible of first process whose frontmost is true to false
-                        end tell
-                    "#,
-                    )
-                    .output()
-                    .unwrap();
-
-                // TODO UX Update: Automatically pasting
-                // Currently not supported.
-                // Pasting immediately after user selection would require integration with macOS system APIs.
+                // UX Update: refocusing the previous application, and pasting into it
+                paste::paste_into_previous_app().await;
             });
-            
         }
     };
 
+    // Action Handler `paste_snippet`: Render a snippet with the values of its `{input:Name}` fields, paste it,
+    // and move the caret back to its `{cursor}`, see `snippets::write_to_clipboard`
+    let paste_snippet = {
+        to_owned![visibility_setter];
+
+        move |snippet: Snippet, inputs: HashMap<String, String>| {
+            spawn(async move {
+                let rendered = match client::copy_snippet(snippet.id, &inputs) {
+                    Ok(rendered) => rendered,
+                    Err(err) => {
+                        log::error!("Failed to paste snippet {}: {}", snippet.id, err);
+                        return;
+                    }
+                };
+
+                // UI Update: Reset the search bar and selection, and hide the window
+                snippet_inputs.set(None);
+                search_bar.set("".to_string());
+                selected_item_index.set(0);
+                visibility_setter.send(false).unwrap();
+
+                if paste::paste_into_previous_app().await {
+                    paste::move_cursor_back(rendered.cursor_offset);
+                }
+            });
+        }
+    };
+
+    // A helper to paste a snippet, asking for the values of its `{input:Name}` fields first, if it has any
+    let start_snippet = {
+        to_owned![paste_snippet];
+
+        move |snippet: Snippet| {
+            let names = snippets::inputs(&snippet.content);
+            if names.is_empty() {
+                to_owned![paste_snippet];
+                paste_snippet(snippet, HashMap::new());
+            } else {
+                let fields = names.into_iter().map(|name| (name, String::new())).collect();
+                snippet_inputs.set(Some((snippet, fields)));
+            }
+        }
+    };
+
+    // A helper to merge the multi-selected items into a new item, and paste it
+    let merge_selected = {
+        to_owned![do_paste];
+
+        move || {
+            let ids = multi_selection.peek().clone();
+            let result = client::merge(&ids, &merge_separator.peek(), *collage_layout.peek())
+                .and_then(client::get_record);
+
+            match result {
+                Ok(Some(item)) => {
+                    merging.set(false);
+                    multi_selection.set(Vec::new());
+
+                    // `do_paste` moves it to the front, if it is there
+                    if current_pinboard.peek().is_none() {
+                        clipboard_items.write().insert(0, item.clone());
+                    }
+
+                    to_owned![do_paste];
+                    do_paste(item, false, None);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    log::error!("Failed to merge items {:?}: {}", ids, err);
+                    merge_error.set(Some(err.to_string()));
+                }
+            }
+        }
+    };
+
+    // A helper to paste the item of the command palette changed by the transform `id`
+    let apply_transform = {
+        to_owned![do_paste];
+
+        move |id: &'static str| {
+            let Some((item, _, _)) = transforming.take() else {
+                return;
+            };
+
+            to_owned![do_paste];
+            do_paste(item, false, Some(id));
+        }
+    };
+
+    // A hook to paste the next item of the paste stack whenever the paste-next hotkey is pressed
+    let paste_next = use_hook(|| {
+        to_owned![window, do_paste];
+        let (tx, mut rx) = mpsc::unbounded_channel::<()>();
+
+        spawn(async move {
+            while rx.recv().await.is_some() {
+                let Some(item) = paste_stack.write().pop() else {
+                    continue;
+                };
+
+                // With the window hidden, the item is pasted into the app focused right now
+                if !window.is_visible() {
+                    paste::remember_focus();
+                }
+                to_owned![do_paste];
+                do_paste(item, false, None);
+            }
+        });
+        tx
+    });
+
+    // Register the `Paste` window to the window registry after component rendered
+    use_effect({
+        to_owned![visibility_setter, paste_next];
+
+        move || {
+            if let Ok(mut registry) = WINDOW_REGISTRY.write() {
+                registry.insert(
+                    "Paste".to_string(),
+                    WindowInfo {
+                        visibility_setter: visibility_setter.clone(),
+                        paste_next: paste_next.clone(),
+                        is_visible: false,
+                    },
+                );
+            }
+            log::trace!("Paste window registered");
+        }
+    });
+
     // Keyboard handler: User can use arrow keys to navigate the clipboard items
     let handle_keydown = {
-        to_owned![visibility_setter, do_paste];
+        to_owned![visibility_setter, do_paste, start_snippet];
 
         move |evt: KeyboardEvent| {
-            to_owned![do_paste];
+            to_owned![do_paste, start_snippet];
+
+            // ⌘ L: Lock the history
+            if let Key::Character(c) = evt.key() {
+                if c == "l"
+                    && evt.modifiers().contains(Modifiers::META)
+                    && encryption::is_enabled()
+                    && !is_locked()
+                {
+                    lock_history();
+                    return;
+                }
+            }
+
+            // ⌘ Z: Restore the item deleted last, while the undo toast is shown
+            if let Key::Character(c) = evt.key() {
+                if c == "z"
+                    && evt.modifiers().contains(Modifiers::META)
+                    && !last_deleted.peek().is_empty()
+                {
+                    evt.prevent_default();
+                    undo_delete();
+                    return;
+                }
+            }
+
+            // Tab / ⇧ Tab: Show the next / previous pinboard
+            if evt.key() == Key::Tab && !is_locked() {
+                evt.prevent_default();
+                switch_tab(if evt.modifiers().contains(Modifiers::SHIFT) { -1 } else { 1 });
+                return;
+            }
 
-            let max_len = filtered_items.read().len();
-            let filtered_items = filtered_items.read();
+            // ← → Enter: Select and paste the snippets, which come before the items
+            let snippet_count = snippet_results.peek().len();
+            let snippet_index = selected_snippet.peek().filter(|index| *index < snippet_count);
+            if let Some(index) = snippet_index {
+                match evt.key() {
+                    Key::ArrowLeft => selected_snippet.set(Some(index.saturating_sub(1))),
+                    Key::ArrowRight if index + 1 < snippet_count => selected_snippet.set(Some(index + 1)),
+                    Key::ArrowRight if !clipboard_items.peek().is_empty() => {
+                        selected_snippet.set(None);
+                        selected_item_index.set(0);
+                    }
+                    Key::Enter => {
+                        let snippet = snippet_results.peek()[index].clone();
+                        start_snippet(snippet);
+                    }
+                    Key::Escape => {
+                        visibility_setter.send(false).unwrap();
+                    }
+                    _ => {}
+                }
+                return;
+            }
+            if evt.key() == Key::ArrowLeft
+                && evt.modifiers().is_empty()
+                && *selected_item_index.peek() == 0
+                && snippet_count > 0
+            {
+                selected_snippet.set(Some(snippet_count - 1));
+                return;
+            }
 
-            if max_len == 0 {
+            if clipboard_items.read().is_empty() {
                 return;
             }
 
             match evt.key() {
+                // ⌥ ← / ⌥ →: Move the selected item on the pinboard shown
+                Key::ArrowRight if evt.modifiers().contains(Modifiers::ALT) => {
+                    move_selected_item(1);
+                }
+                Key::ArrowLeft if evt.modifiers().contains(Modifiers::ALT) => {
+                    move_selected_item(-1);
+                }
                 Key::ArrowRight => {
                     let current_idx = *selected_item_index.read();
-                    selected_item_index.set((current_idx + 1) % max_len);
+
+                    // Fetch the next page before the selection reaches the end of the loaded items
+                    if current_idx + 1 + OVERSCAN >= clipboard_items.read().len() {
+                        load_more_items();
+                    }
+
+                    let max_len = clipboard_items.read().len();
+                    let next_idx = (current_idx + 1) % max_len;
+                    selected_item_index.set(next_idx);
+
+                    // ⇧ → / ⌘ →: Select the next item too
+                    if evt.modifiers().intersects(Modifiers::SHIFT | Modifiers::META) {
+                        evt.prevent_default();
+                        extend_selection(current_idx, next_idx);
+                    }
+                }
+                // ⌘ ⌫ / Delete: Move the selected item to the trash
+                Key::Backspace if evt.modifiers().contains(Modifiers::META) => {
+                    evt.prevent_default();
+                    delete_selected_item();
+                }
+                Key::Delete => {
+                    evt.prevent_default();
+                    delete_selected_item();
                 }
                 Key::ArrowLeft => {
                     let current_idx = *selected_item_index.read();
-                    selected_item_index.set(if current_idx == 0 {
+                    let max_len = clipboard_items.read().len();
+                    let previous_idx = if current_idx == 0 {
                         max_len - 1
                     } else {
                         current_idx - 1
-                    });
+                    };
+                    selected_item_index.set(previous_idx);
+
+                    // ⇧ ← / ⌘ ←: Select the previous item too
+                    if evt.modifiers().intersects(Modifiers::SHIFT | Modifiers::META) {
+                        evt.prevent_default();
+                        extend_selection(current_idx, previous_idx);
+                    }
                 }
                 Key::Character(c) => {
                     if evt.modifiers().contains(Modifiers::META) {
@@ -303,18 +1028,54 @@ fn Paste() -> Element {
                                 n => n.saturating_sub(1),
                             };
 
-                            if let Some(item) = filtered_items.get(idx) {
-                                do_paste(item.clone());
+                            let item = clipboard_items.read().get(idx).cloned();
+                            if let Some(item) = item {
+                                do_paste(item, false, None);
                             }
+                        } else if c == "p" {
+                            toggle_pin();
+                        } else if c == "t" {
+                            start_editing(ItemField::Tags);
+                        } else if c == "n" {
+                            start_editing(ItemField::Note);
+                        } else if c == "s" {
+                            evt.prevent_default();
+                            toggle_stacked();
+                        } else if c == "r" && !paste_stack.peek().is_empty() {
+                            evt.prevent_default();
+                            paste_stack.write().reverse();
+                        } else if c == "j" {
+                            evt.prevent_default();
+                            start_merging();
+                        } else if c == "k" {
+                            evt.prevent_default();
+                            start_transforming();
+                        } else if c == "e" {
+                            evt.prevent_default();
+                            start_editing(ItemField::Snippet);
                         }
                     }
                 }
                 Key::Enter => {
-                    if let Some(item) = filtered_items.get(*selected_item_index.read()) {
-                        do_paste(item.clone());
+                    // Once there is a paste stack, its next item is pasted instead of the selected one
+                    let item = if paste_stack.peek().is_empty() {
+                        clipboard_items
+                            .read()
+                            .get(*selected_item_index.read())
+                            .cloned()
+                    } else {
+                        paste_stack.write().pop()
+                    };
+                    if let Some(item) = item {
+                        do_paste(item, evt.modifiers().contains(Modifiers::SHIFT), None);
                     }
                     visibility_setter.send(false).unwrap();
                 }
+                // Esc: Clear the multi-selection first, if there is one
+                Key::Escape if !multi_selection.peek().is_empty() => {
+                    multi_selection.set(Vec::new());
+                    merging.set(false);
+                }
                 Key::Escape => {
                     visibility_setter.send(false).unwrap();
                 }
@@ -323,6 +1084,41 @@ fn Paste() -> Element {
         }
     };
 
+    // Only the cards inside (or close to) the viewport are rendered, the rest is replaced by spacers
+    // The snippet cards are few, and always rendered before the items
+    let total_items = clipboard_items.read().len();
+    let snippet_count = snippet_results.read().len();
+    let first_visible = (((scroll_left() - snippet_count as f64 * CARD_SPAN).max(0.0) / CARD_SPAN) as usize)
+        .saturating_sub(OVERSCAN)
+        .min(total_items);
+    let last_visible =
+        (first_visible + (viewport_width() / CARD_SPAN).ceil() as usize + 2 * OVERSCAN)
+            .min(total_items);
+    let leading_space = first_visible as f64 * CARD_SPAN - CARD_GAP;
+    let trailing_space = (total_items - last_visible) as f64 * CARD_SPAN - CARD_GAP;
+    let more_items_hint = if next_page().is_some() { "+" } else { "" };
+    let merging_images = {
+        let items = clipboard_items.read();
+        multi_selection.read().iter().all(|id| {
+            items
+                .iter()
+                .any(|item| item.id == *id && item.content_type == ContentTypes::Image)
+        })
+    };
+
+    let palette_transforms = transforming
+        .read()
+        .as_ref()
+        .map(|(_, filter, _)| matching_transforms(filter))
+        .unwrap_or_default();
+    let palette_preview = transforming
+        .read()
+        .as_ref()
+        .and_then(|(item, _, highlighted)| {
+            let preview = palette_transforms.get(*highlighted)?.apply(&item.content);
+            Some(preview.map(|text| preview_label(&text, item.masked)))
+        });
+
     rsx! {
         document::Link { rel: "stylesheet", href: MAIN_CSS }
         document::Link { rel: "stylesheet", href: TAILWIND_CSS }
@@ -336,39 +1132,479 @@ fn Paste() -> Element {
                 // Header (Search Bar, Item Count)
                 div {
                     class: "flex-none w-full h-[60px] flex items-center px-6 bg-[#333333] shadow-md z-10 pt-1",
-                    div { class: "mr-3 text-2xl", "🔍" }
-                    input {
-                        class: "flex-1 bg-transparent border-none outline-none text-xl text-white placeholder-gray-500 font-light",
-                        placeholder: "Type to search...",
-                        value: "{search_bar}",
-                        oninput: move |evt| { search_bar.set(evt.value()); selected_item_index.set(0); },
-                        autofocus: true,
+                    if is_locked() {
+                        div { class: "mr-3 text-2xl", "🔒" }
+                        div { class: "flex-1 text-xl text-gray-500 font-light", "Clipboard history is locked" }
+                    } else {
+                        div { class: "mr-3 text-2xl", "🔍" }
+                        input {
+                            class: "flex-1 bg-transparent border-none outline-none text-xl text-white placeholder-gray-500 font-light",
+                            placeholder: "Type to search...",
+                            value: "{search_bar}",
+                            oninput: move |evt| { search_bar.set(evt.value()); selected_item_index.set(0); },
+                            autofocus: true,
+                        }
+                        div { class: "text-gray-500 text-sm font-mono", "{total_items}{more_items_hint} items" }
+                        if encryption::is_enabled() {
+                            button {
+                                class: "ml-4 text-xl opacity-60 hover:opacity-100",
+                                title: "Lock (⌘ L)",
+                                onclick: move |_| lock_history(),
+                                "🔒"
+                            }
+                        }
+                    }
+                }
+
+                // Tabs (History, Pinboards)
+                if !is_locked() {
+                    div {
+                        class: "flex-none w-full h-[36px] flex items-center gap-2 px-6 bg-[#2a2a2a] border-b border-white/5 text-sm select-none",
+                        button {
+                            class: tab_class(current_pinboard().is_none()),
+                            onclick: move |_| current_pinboard.set(None),
+                            "History"
+                        }
+                        for pinboard in pinboards() {
+                            button {
+                                key: "{pinboard.id}",
+                                class: tab_class(current_pinboard() == Some(pinboard.id)),
+                                onclick: move |_| current_pinboard.set(Some(pinboard.id)),
+                                "📌 {pinboard.name}"
+                                if current_pinboard() == Some(pinboard.id) {
+                                    span {
+                                        class: "ml-2 opacity-50 hover:opacity-100",
+                                        title: "Delete pinboard, its items stay in the history",
+                                        onclick: move |evt| {
+                                            evt.stop_propagation();
+                                            remove_pinboard(pinboard.id);
+                                        },
+                                        "×"
+                                    }
+                                }
+                            }
+                        }
+                        if let Some(name) = new_pinboard_name() {
+                            input {
+                                class: "w-[160px] px-2 py-0.5 rounded bg-[#333333] border border-white/10 outline-none text-white placeholder-gray-500",
+                                placeholder: "Pinboard name",
+                                value: "{name}",
+                                oninput: move |evt| new_pinboard_name.set(Some(evt.value())),
+                                onkeydown: move |evt| {
+                                    // Keep Enter & Escape from pasting or closing the window
+                                    evt.stop_propagation();
+                                    match evt.key() {
+                                        Key::Enter => add_pinboard(),
+                                        Key::Escape => new_pinboard_name.set(None),
+                                        _ => {}
+                                    }
+                                },
+                                onmounted: move |evt| async move {
+                                    let _ = evt.set_focus(true).await;
+                                },
+                            }
+                        } else {
+                            button {
+                                class: tab_class(false),
+                                title: "New pinboard",
+                                onclick: move |_| new_pinboard_name.set(Some("".to_string())),
+                                "+"
+                            }
+                        }
+                    }
+                }
+
+                // Editor (Tags, Note of the selected item, Name of a snippet made of it)
+                if let Some((id, field, value)) = editing() {
+                    div {
+                        class: "flex-none w-full h-[40px] flex items-center gap-3 px-6 bg-[#2a2a2a] border-b border-white/5 text-sm",
+                        span { class: "text-gray-400",
+                            match field {
+                                ItemField::Tags => "🏷️ Tags",
+                                ItemField::Note => "📝 Note",
+                                ItemField::Snippet => "✂️ Save as Snippet",
+                            }
+                        }
+                        input {
+                            key: "{id}-{field:?}",
+                            class: "flex-1 px-2 py-1 rounded bg-[#333333] border border-white/10 outline-none text-white placeholder-gray-500",
+                            placeholder: match field {
+                                ItemField::Tags => "#work #todo",
+                                ItemField::Note => "A note about this item",
+                                ItemField::Snippet => "The name of the snippet",
+                            },
+                            value: "{value}",
+                            oninput: move |evt| editing.set(Some((id, field, evt.value()))),
+                            onkeydown: move |evt| {
+                                // Keep Enter & Escape from pasting or closing the window
+                                evt.stop_propagation();
+                                match evt.key() {
+                                    Key::Enter => save_editing(),
+                                    Key::Escape => editing.set(None),
+                                    _ => {}
+                                }
+                            },
+                            onmounted: move |evt| async move {
+                                let _ = evt.set_focus(true).await;
+                            },
+                        }
+                        span { class: "text-gray-500 text-xs", "Enter to save, Esc to cancel" }
+                    }
+                }
+
+                // Snippet Inputs (Values of the `{input:Name}` fields of the snippet being pasted)
+                if let Some((snippet, fields)) = snippet_inputs() {
+                    div {
+                        class: "flex-none w-full h-[40px] flex items-center gap-2 px-6 bg-[#2a2a2a] border-b border-white/5 text-sm",
+                        span { class: "text-purple-300 whitespace-nowrap", "✂️ {snippet.name}" }
+                        for (position, (name, value)) in fields.into_iter().enumerate() {
+                            input {
+                                key: "{snippet.id}-{name}",
+                                class: "flex-1 min-w-0 px-2 py-1 rounded bg-[#333333] border border-white/10 outline-none text-white placeholder-gray-500",
+                                placeholder: "{name}",
+                                value: "{value}",
+                                oninput: move |evt| {
+                                    if let Some((_, fields)) = snippet_inputs.write().as_mut() {
+                                        fields[position].1 = evt.value();
+                                    }
+                                },
+                                onkeydown: {
+                                    to_owned![paste_snippet];
+                                    move |evt: KeyboardEvent| {
+                                        // Keep Enter & Escape from pasting or closing the window, and ⇥ from switching tabs
+                                        evt.stop_propagation();
+                                        match evt.key() {
+                                            Key::Enter => {
+                                                let pending = snippet_inputs.peek().clone();
+                                                if let Some((snippet, fields)) = pending {
+                                                    to_owned![paste_snippet];
+                                                    paste_snippet(snippet, fields.into_iter().collect());
+                                                }
+                                            }
+                                            Key::Escape => snippet_inputs.set(None),
+                                            _ => {}
+                                        }
+                                    }
+                                },
+                                onmounted: move |evt| async move {
+                                    if position == 0 {
+                                        let _ = evt.set_focus(true).await;
+                                    }
+                                },
+                            }
+                        }
+                        span { class: "text-gray-500 text-xs whitespace-nowrap", "Enter to paste, Esc to cancel" }
+                    }
+                }
+
+                // Command Palette (Transforms of the selected item, pasted as plain text)
+                if let Some((item, filter, highlighted)) = transforming() {
+                    div {
+                        class: "flex-none w-full h-[40px] flex items-center gap-2 px-6 bg-[#2a2a2a] border-b border-white/5 text-sm",
+                        span { class: "text-gray-400", "⚡ Transform" }
+                        input {
+                            key: "{item.id}-transform",
+                            class: "w-[140px] px-2 py-1 rounded bg-[#333333] border border-white/10 outline-none text-white placeholder-gray-500",
+                            placeholder: "Filter",
+                            value: "{filter}",
+                            oninput: move |evt| {
+                                if let Some((_, filter, highlighted)) = transforming.write().as_mut() {
+                                    *filter = evt.value();
+                                    *highlighted = 0;
+                                }
+                            },
+                            onkeydown: {
+                                to_owned![apply_transform, palette_transforms];
+                                move |evt: KeyboardEvent| {
+                                    // Keep Enter & Escape from pasting or closing the window
+                                    evt.stop_propagation();
+                                    let count = palette_transforms.len().max(1);
+                                    match evt.key() {
+                                        Key::ArrowDown | Key::ArrowUp => {
+                                            evt.prevent_default();
+                                            let step = if evt.key() == Key::ArrowDown { 1 } else { count - 1 };
+                                            if let Some((_, _, highlighted)) = transforming.write().as_mut() {
+                                                *highlighted = (*highlighted + step) % count;
+                                            }
+                                        }
+                                        Key::Enter => {
+                                            if let Some(transform) = palette_transforms.get(highlighted) {
+                                                apply_transform(transform.id());
+                                            }
+                                        }
+                                        Key::Escape => transforming.set(None),
+                                        _ => {}
+                                    }
+                                }
+                            },
+                            onmounted: move |evt| async move {
+                                let _ = evt.set_focus(true).await;
+                            },
+                        }
+                        div { class: "flex-none flex items-center gap-1 max-w-[55%] overflow-hidden",
+                            // Start at the highlighted transform, so it is never cut off
+                            for (index, transform) in palette_transforms.iter().copied().enumerate().skip(highlighted.saturating_sub(1)) {
+                                button {
+                                    key: "{transform.id()}",
+                                    class: "{tab_class(index == highlighted)} whitespace-nowrap",
+                                    onclick: {
+                                        to_owned![apply_transform];
+                                        move |_| apply_transform(transform.id())
+                                    },
+                                    "{transform.name()}"
+                                }
+                            }
+                            if palette_transforms.is_empty() {
+                                span { class: "text-gray-500", "No matching transform" }
+                            }
+                        }
+                        match palette_preview {
+                            Some(Ok(preview)) => rsx! {
+                                span { class: "flex-1 truncate text-gray-400 font-mono text-xs", "→ {preview}" }
+                            },
+                            Some(Err(err)) => rsx! {
+                                span { class: "flex-1 truncate text-red-400 text-xs", "{err}" }
+                            },
+                            None => rsx! {},
+                        }
+                        span { class: "text-gray-500 text-xs", "↑ ↓ Enter Esc" }
+                    }
+                }
+
+                // Merge Bar (Separator or Layout of the multi-selected items)
+                if merging() {
+                    div {
+                        class: "flex-none w-full h-[40px] flex items-center gap-2 px-6 bg-[#2a2a2a] border-b border-white/5 text-sm outline-none select-none",
+                        tabindex: 0,
+                        onmounted: move |evt| async move {
+                            let _ = evt.set_focus(true).await;
+                        },
+                        onkeydown: {
+                            to_owned![merge_selected];
+                            move |evt: KeyboardEvent| {
+                                // Keep Enter & Escape from pasting or closing the window
+                                evt.stop_propagation();
+                                match evt.key() {
+                                    Key::Enter => merge_selected(),
+                                    Key::Escape => merging.set(false),
+                                    Key::Character(c) => {
+                                        let Some(index) = c.parse::<usize>().ok().and_then(|n| n.checked_sub(1)) else {
+                                            return;
+                                        };
+                                        if merging_images {
+                                            match index {
+                                                0 => collage_layout.set(CollageLayout::Vertical),
+                                                1 => collage_layout.set(CollageLayout::Horizontal),
+                                                _ => {}
+                                            }
+                                        } else if let Some((separator, _)) = SEPARATORS.get(index) {
+                                            merge_separator.set(separator.clone());
+                                        }
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        },
+                        span { class: "text-gray-400 mr-1", "🔗 Merge {multi_selection.read().len()} items" }
+                        if merging_images {
+                            button {
+                                class: tab_class(collage_layout() == CollageLayout::Vertical),
+                                onclick: move |_| collage_layout.set(CollageLayout::Vertical),
+                                "1 Vertical"
+                            }
+                            button {
+                                class: tab_class(collage_layout() == CollageLayout::Horizontal),
+                                onclick: move |_| collage_layout.set(CollageLayout::Horizontal),
+                                "2 Horizontal"
+                            }
+                        } else {
+                            for (index, (separator, label)) in SEPARATORS.into_iter().enumerate() {
+                                button {
+                                    class: tab_class(std::mem::discriminant(&merge_separator()) == std::mem::discriminant(&separator)),
+                                    onclick: move |_| merge_separator.set(separator.clone()),
+                                    "{index + 1} {label}"
+                                }
+                            }
+                            if let Separator::Custom(custom) = merge_separator() {
+                                input {
+                                    class: "w-[120px] px-2 py-0.5 rounded bg-[#333333] border border-white/10 outline-none text-white placeholder-gray-500 font-mono",
+                                    placeholder: "e.g. \" | \"",
+                                    value: "{custom}",
+                                    oninput: move |evt| merge_separator.set(Separator::Custom(evt.value())),
+                                    onkeydown: {
+                                        to_owned![merge_selected];
+                                        move |evt: KeyboardEvent| {
+                                            // Digits are part of the separator, not a choice of another one
+                                            evt.stop_propagation();
+                                            match evt.key() {
+                                                Key::Enter => merge_selected(),
+                                                Key::Escape => merging.set(false),
+                                                _ => {}
+                                            }
+                                        }
+                                    },
+                                    onmounted: move |evt| async move {
+                                        let _ = evt.set_focus(true).await;
+                                    },
+                                }
+                            }
+                        }
+                        button {
+                            class: "ml-auto px-3 py-0.5 rounded-md bg-[#007acc] text-white font-semibold",
+                            onclick: {
+                                to_owned![merge_selected];
+                                move |_| merge_selected()
+                            },
+                            "Merge & Paste"
+                        }
+                        if let Some(err) = merge_error() {
+                            span { class: "text-red-400 text-xs", "{err}" }
+                        } else {
+                            span { class: "text-gray-500 text-xs", "Enter to merge, Esc to cancel" }
+                        }
+                    }
+                }
+
+                // Paste Stack (pasted one after the other by ⌥ ⌘ V)
+                if !paste_stack.read().is_empty() {
+                    div {
+                        class: "flex-none w-full h-[36px] flex items-center gap-2 px-6 bg-[#2a2a2a] border-b border-white/5 text-sm select-none",
+                        span { class: "text-gray-400", "📚 Paste Stack" }
+                        button {
+                            class: "px-2 py-0.5 rounded bg-white/10 text-xs font-bold text-gray-300 hover:bg-white/20",
+                            title: "Reverse the order (⌘ R)",
+                            onclick: move |_| paste_stack.write().reverse(),
+                            "{paste_stack.read().order().label()} ⇅"
+                        }
+                        div {
+                            class: "flex-1 flex items-center gap-1 overflow-hidden whitespace-nowrap",
+                            for (position, item) in paste_stack.read().items().into_iter().enumerate() {
+                                span {
+                                    key: "{item.id}",
+                                    class: "max-w-[160px] truncate px-2 py-0.5 rounded bg-amber-500/20 text-amber-200 text-xs",
+                                    "{position + 1}. {stack_label(&item)}"
+                                }
+                            }
+                        }
+                        span { class: "text-gray-500 text-xs", "Enter or ⌥ ⌘ V pastes the next one" }
+                        button {
+                            class: "opacity-50 hover:opacity-100",
+                            title: "Clear the paste stack",
+                            onclick: move |_| paste_stack.write().clear(),
+                            "×"
+                        }
                     }
-                    div { class: "text-gray-500 text-sm font-mono", "{filtered_items.read().len()} items" }
                 }
 
                 // Body (Items)
                 div {
                     class: "flex-1 w-full overflow-x-auto overflow-y-hidden flex flex-row items-center gap-5 px-6 scrollbar-hide bg-[#1e1e1e]",
+                    onmounted: move |evt| {
+                        let element = evt.data();
+                        card_list.set(Some(element.clone()));
+                        spawn(async move {
+                            if let Ok(rect) = element.get_client_rect().await {
+                                viewport_width.set(rect.width());
+                            }
+                        });
+                    },
+                    onscroll: move |evt| {
+                        scroll_left.set(evt.scroll_left());
+                        viewport_width.set(evt.client_width() as f64);
 
-                    if filtered_items.read().is_empty() {
+                        // Fetch the next page before the user scrolls to the end of the loaded items
+                        let scroll_right = evt.scroll_left() + evt.client_width() as f64;
+                        if scroll_right + CARD_SPAN * OVERSCAN as f64 >= evt.scroll_width() as f64 {
+                            load_more_items();
+                        }
+                    },
+
+                    if is_locked() {
+                        div {
+                            class: "w-full flex flex-col items-center gap-3",
+                            if encryption::needs_passphrase() {
+                                input {
+                                    class: "w-[320px] px-3 py-2 rounded-md bg-[#333333] border border-white/10 outline-none text-white placeholder-gray-500",
+                                    r#type: "password",
+                                    placeholder: "Passphrase",
+                                    value: "{passphrase}",
+                                    oninput: move |evt| passphrase.set(evt.value()),
+                                    onkeydown: move |evt| {
+                                        if evt.key() == Key::Enter {
+                                            unlock_history();
+                                        }
+                                    },
+                                    onmounted: move |evt| async move {
+                                        let _ = evt.set_focus(true).await;
+                                    },
+                                }
+                            }
+                            button {
+                                class: "px-4 py-1 rounded-md bg-[#007acc] text-white font-semibold",
+                                onclick: move |_| unlock_history(),
+                                "Unlock"
+                            }
+                            if let Some(err) = unlock_error() {
+                                div { class: "text-red-400 text-sm", "Failed to unlock: {err}" }
+                            }
+                        }
+                    } else if total_items == 0 && snippet_count == 0 {
+                        if current_pinboard().is_some() && search_bar.read().trim().is_empty() {
+                            div { class: "w-full text-center text-gray-500 text-xl", "Nothing pinned yet, ⌘ P pins the selected item of the history 📌" }
+                        } else {
                             div { class: "w-full text-center text-gray-500 text-xl", "No records found 🕵️‍♂️" }
+                        }
                     } else {
+                        for (index, snippet) in snippet_results().into_iter().enumerate() {
+                            SnippetCard {
+                                key: "snippet-{snippet.id}",
+                                is_selected: selected_snippet() == Some(index),
+                                snippet: snippet.clone(),
+                                on_click: {
+                                    to_owned![start_snippet];
+                                    move |_| {
+                                        to_owned![start_snippet];
+                                        if *selected_snippet.peek() == Some(index) {
+                                            start_snippet(snippet.clone());
+                                        } else {
+                                            selected_snippet.set(Some(index));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        if first_visible > 0 {
+                            div { class: "flex-shrink-0 h-px", style: "width: {leading_space}px" }
+                        }
                         {
-                            filtered_items.read().iter().enumerate().map(|(index, item)| {
+                            clipboard_items.read()[first_visible..last_visible].iter().enumerate().map(|(offset, item)| {
                                 to_owned![do_paste, item];
+                                let index = first_visible + offset;
 
                                 rsx! {
                                     ClipboardCard {
                                         key: "{item.id}",
                                         index: index,
-                                        is_selected: index == *selected_item_index.read(),
+                                        is_selected: selected_snippet().is_none() && index == *selected_item_index.read(),
+                                        is_marked: multi_selection.read().contains(&item.id),
+                                        stack_position: paste_stack.read().position(item.id),
                                         item: item.clone(),
-                                        on_click: move |_| {
+                                        on_click: move |evt: MouseEvent| {
                                             to_owned![do_paste];
-                                            if index == *selected_item_index.read() {
-                                                do_paste(item.clone());
+                                            if evt.modifiers().contains(Modifiers::META) {
+                                                paste_stack.write().toggle(item.clone());
+                                            } else if evt.modifiers().contains(Modifiers::SHIFT) {
+                                                let mut selection = multi_selection.write();
+                                                match selection.iter().position(|id| *id == item.id) {
+                                                    Some(position) => {
+                                                        selection.remove(position);
+                                                    }
+                                                    None => selection.push(item.id),
+                                                }
+                                            } else if index == *selected_item_index.read() && selected_snippet.peek().is_none() {
+                                                do_paste(item.clone(), false, None);
                                             } else {
+                                                selected_snippet.set(None);
                                                 selected_item_index.set(index);
                                             }
                                         }
@@ -376,6 +1612,22 @@ fn Paste() -> Element {
                                 }
                             })
                         }
+                        if last_visible < total_items {
+                            div { class: "flex-shrink-0 h-px", style: "width: {trailing_space}px" }
+                        }
+                    }
+                }
+
+                // Undo Toast
+                if !last_deleted().is_empty() {
+                    div {
+                        class: "absolute bottom-[36px] left-1/2 -translate-x-1/2 z-20 flex items-center gap-3 px-4 py-2 rounded-lg bg-[#333333] border border-white/10 shadow-2xl text-sm select-none",
+                        span { class: "text-gray-300", "🗑️ Moved to the trash" }
+                        button {
+                            class: "px-2 py-0.5 rounded bg-[#007acc] text-white font-semibold",
+                            onclick: move |_| undo_delete(),
+                            "Undo (⌘ Z)"
+                        }
                     }
                 }
 
@@ -396,10 +1648,62 @@ fn Paste() -> Element {
                             span { class: "opacity-80", "Paste" }
                         }
 
+                        div { class: "flex items-center gap-1",
+                            span { "⇧ Enter" }
+                            span { class: "opacity-80", "Paste as Plain Text" }
+                        }
+
                         div { class: "flex items-center gap-1",
                             span { "← →" }
                             span { class: "opacity-80", "Select" }
                         }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘ P" }
+                            span { class: "opacity-80", if current_pinboard().is_some() { "Unpin" } else { "Pin" } }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⇥" }
+                            span { class: "opacity-80", "Pinboards" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘ T / ⌘ N" }
+                            span { class: "opacity-80", "Tags / Note" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘ ⌫" }
+                            span { class: "opacity-80", "Delete" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘ S" }
+                            span { class: "opacity-80", "Paste Stack" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⇧ ← → / ⌘ J" }
+                            span { class: "opacity-80", "Select / Merge" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘ K" }
+                            span { class: "opacity-80", "Transform" }
+                        }
+
+                        div { class: "flex items-center gap-1",
+                            span { "⌘ E" }
+                            span { class: "opacity-80", "Save as Snippet" }
+                        }
+
+                        if encryption::is_enabled() {
+                            div { class: "flex items-center gap-1",
+                                span { "⌘ L" }
+                                span { class: "opacity-80", "Lock" }
+                            }
+                        }
                     }
 
                     span {
@@ -416,20 +1720,33 @@ fn Paste() -> Element {
 fn ClipboardCard(
     index: usize,
     is_selected: bool,
+    is_marked: bool,
+    stack_position: Option<usize>,
     item: clipboard::Item,
-    on_click: EventHandler<()>,
+    on_click: EventHandler<MouseEvent>,
 ) -> Element {
     let base_style = "flex-shrink-0 w-[240px] h-[180px] rounded-lg flex flex-col cursor-pointer relative overflow-hidden transition-all duration-200";
     let active_style = if is_selected {
         "ring-4 ring-blue-500 bg-[#3c3c3c] scale-105 shadow-2xl z-10"
+    } else if is_marked {
+        "ring-2 ring-sky-400 bg-[#2d2d2d]"
     } else {
         "bg-[#2d2d2d] hover:bg-[#333333] opacity-80 hover:opacity-100"
     };
 
+    // The file metadata only has to be loaded once per card, whether the files still exist is checked on every render
+    let files = use_hook(|| {
+        if item.content_type == ContentTypes::Files {
+            client::get_files(item.id).unwrap_or_default()
+        } else {
+            Vec::new()
+        }
+    });
+
     rsx! {
         div {
             class: "{base_style} {active_style}",
-            onclick: move |_| on_click.call(()),
+            onclick: move |evt| on_click.call(evt),
 
             // Header: SourceApp, RelativeTimestamp, Icon
             div {
@@ -438,7 +1755,18 @@ fn ClipboardCard(
                 // Left: SourceApp, RelativeTimestamp
                 div {
                     class: "flex flex-col justify-center",
-                    span { class: "text-sm font-bold text-gray-200 truncate max-w-[180px]", "{item.source_app}" }
+                    span { class: "text-sm font-bold text-gray-200 truncate max-w-[180px]",
+                        if is_marked {
+                            span { class: "mr-1 text-sky-400", title: "Selected for merging (⌘ J)", "✓" }
+                        }
+                        if let Some(position) = stack_position {
+                            span { class: "mr-1 px-1 rounded bg-amber-500 text-black text-[10px]", title: "Pasted #{position} from the paste stack", "{position}" }
+                        }
+                        if item.pinned {
+                            span { class: "mr-1", title: "Pinned", "📌" }
+                        }
+                        "{item.source_app}"
+                    }
                     span { class: "text-[10px] text-gray-500 font-mono mt-0.5", "{humanize_time(item.timestamp)}" }
                 }
 
@@ -448,7 +1776,20 @@ fn ClipboardCard(
                     img {
                         class: "w-full h-full object-contain",
                         alt: "App Icon",
-                        src: "{item.icon_path}"
+                        src: "{blobs::icon_url(item.id)}"
+                    }
+                }
+            }
+
+            // Tags & Note
+            if !item.tags.is_empty() || !item.note.is_empty() {
+                div {
+                    class: "px-3 pt-2 flex items-center gap-1 text-[10px] whitespace-nowrap overflow-hidden",
+                    for tag in item.tags.iter() {
+                        span { class: "px-1.5 py-0.5 rounded bg-[#007acc]/30 text-blue-300", "#{tag}" }
+                    }
+                    if !item.note.is_empty() {
+                        span { class: "truncate italic text-gray-400", title: "{item.note}", "📝 {item.note}" }
                     }
                 }
             }
@@ -456,13 +1797,47 @@ fn ClipboardCard(
             // Content
             div {
                 class: "flex-1 p-3 overflow-hidden text-xs text-gray-300 font-mono leading-relaxed break-all whitespace-pre-wrap [mask-image:linear-gradient(to_bottom,black_70%,transparent)]",
-                if item.content_type == ContentTypes::Text {
+                if item.redacted {
+                    div { class: "h-full flex flex-col items-center justify-center gap-1 text-gray-500",
+                        span { class: "text-2xl", "🔒" }
+                        span { "Hidden by a privacy rule" }
+                    }
+                } else if item.content_type == ContentTypes::Text && item.masked {
+                    "{secrets::mask(&item.content)}"
+                } else if item.content_type == ContentTypes::Text {
                     "{&item.content}"
                 } else if item.content_type == ContentTypes::Image {
                     img {
                         class: "w-full h-full object-contain block",
                         alt: "Image Preview",
-                        src: "data:image/png;base64,{&item.content}"
+                        src: "{blobs::thumbnail_url(&item.content_hash)}"
+                    }
+                } else if item.content_type == ContentTypes::Files {
+                    div {
+                        class: "flex flex-col gap-1 whitespace-nowrap",
+                        for file in files.iter() {
+                            div {
+                                class: if file.exists() { "flex items-center gap-2" } else { "flex items-center gap-2 text-gray-500" },
+                                title: "{file.path.display()}",
+                                span { "{file_icon(file)}" }
+                                span { class: if file.exists() { "truncate" } else { "truncate line-through" }, "{file.name()}" }
+                                if !file.exists() {
+                                    span { class: "ml-auto text-[10px] text-red-400", "Deleted" }
+                                } else if !file.is_dir {
+                                    span { class: "ml-auto text-[10px] text-gray-500", "{humanize_size(file.size)}" }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Formats Hint: the representations that are restored next to the plain text
+            if !item.formats.is_empty() {
+                div {
+                    class: "absolute bottom-2 left-2 flex gap-1",
+                    for format in item.formats.iter() {
+                        span { class: "px-1.5 py-0.5 rounded bg-black/50 text-[10px] text-gray-500 font-bold uppercase", "{format.label()}" }
                     }
                 }
             }
@@ -475,9 +1850,134 @@ fn ClipboardCard(
     }
 }
 
+#[component]
+fn SnippetCard(is_selected: bool, snippet: Snippet, on_click: EventHandler<MouseEvent>) -> Element {
+    let base_style = "flex-shrink-0 w-[240px] h-[180px] rounded-lg flex flex-col cursor-pointer relative overflow-hidden transition-all duration-200 border border-purple-500/40";
+    let active_style = if is_selected {
+        "ring-4 ring-purple-500 bg-[#3a3348] scale-105 shadow-2xl z-10"
+    } else {
+        "bg-[#2b2838] hover:bg-[#332f42] opacity-80 hover:opacity-100"
+    };
+    let inputs = snippets::inputs(&snippet.content);
+
+    rsx! {
+        div {
+            class: "{base_style} {active_style}",
+            onclick: move |evt| on_click.call(evt),
+
+            // Header: Name, Icon
+            div {
+                class: "h-12 px-3 flex items-center justify-between bg-purple-500/10 border-b border-purple-500/20",
+
+                div {
+                    class: "flex flex-col justify-center",
+                    span { class: "text-sm font-bold text-purple-200 truncate max-w-[180px]", "{snippet.name}" }
+                    span { class: "text-[10px] text-gray-500 font-mono mt-0.5", "Snippet · {humanize_time(snippet.timestamp)}" }
+                }
+
+                div { class: "w-8 h-8 rounded bg-purple-500/20 flex items-center justify-center", "✂️" }
+            }
+
+            // Content: the template, placeholders included
+            div {
+                class: "flex-1 p-3 overflow-hidden text-xs text-gray-300 font-mono leading-relaxed break-all whitespace-pre-wrap [mask-image:linear-gradient(to_bottom,black_70%,transparent)]",
+                "{snippet.content}"
+            }
+
+            // Inputs Hint: the fields asked for when pasting
+            if !inputs.is_empty() {
+                div {
+                    class: "absolute bottom-2 left-2 flex gap-1",
+                    for name in inputs {
+                        span { class: "px-1.5 py-0.5 rounded bg-black/50 text-[10px] text-purple-300 font-bold", "{name}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
 // ------------------------------------------------------------------
 //                             INTERNAL
 // ------------------------------------------------------------------
+/// A helper function to style a tab above the card strip
+fn tab_class(is_active: bool) -> &'static str {
+    if is_active {
+        "px-3 py-0.5 rounded-md bg-[#007acc] text-white font-semibold"
+    } else {
+        "px-3 py-0.5 rounded-md text-gray-400 hover:bg-white/10 hover:text-white"
+    }
+}
+
+/// A helper function to list the transforms of the command palette whose name or id contains `filter`
+fn matching_transforms(filter: &str) -> Vec<&'static dyn Transform> {
+    let filter = filter.trim().to_lowercase();
+
+    transforms::all()
+        .iter()
+        .map(|transform| transform.as_ref())
+        .filter(|transform| {
+            transform.name().to_lowercase().contains(&filter) || transform.id().contains(&filter)
+        })
+        .collect()
+}
+
+/// A helper function to preview a transformed text on a single line, masking it if it is a secret
+fn preview_label(text: &str, masked: bool) -> String {
+    let text = if masked {
+        secrets::mask(text)
+    } else {
+        text.to_string()
+    };
+
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(PREVIEW_LENGTH)
+        .collect()
+}
+
+/// A helper function to label an item of the paste stack
+fn stack_label(item: &clipboard::Item) -> String {
+    const LABEL_LENGTH: usize = 40;
+
+    let label = match item.content_type {
+        _ if item.redacted => return "🔒 Hidden".to_string(),
+        ContentTypes::Image => return "🖼️ Image".to_string(),
+        ContentTypes::Files => return format!("📄 {} files", item.content.lines().count()),
+        ContentTypes::Text if item.masked => secrets::mask(&item.content),
+        ContentTypes::Text => item.content.clone(),
+    };
+
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(LABEL_LENGTH)
+        .collect()
+}
+
+/// A helper function to pick the icon of a file in a `ClipboardCard`
+fn file_icon(file: &clipboard::FileEntry) -> &'static str {
+    const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "heic", "svg"];
+
+    let extension = file
+        .path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    if file.is_dir {
+        "📁"
+    } else if IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+        "🖼️"
+    } else {
+        "📄"
+    }
+}
+
 /// A helper function to set the visibility of a window
 fn set_window_visibility(name: &str, is_visible: bool) {
     if let Ok(mut registry) = WINDOW_REGISTRY.write() {